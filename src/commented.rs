@@ -0,0 +1,396 @@
+//! Editing commented JSON config files in place, so `//`, `#` and `/* */`
+//! comments and the surrounding layout survive a change to one value.
+//!
+//! Use [`crate::config::LocalConf`] to read and merge configs; this is for
+//! tools that change a setting in a file a person maintains by hand.
+//!
+//! # Examples
+//!
+//! ```
+//! use ovos_config::commented::CommentedConfig;
+//! use serde_json::json;
+//!
+//! let mut conf = CommentedConfig::parse(
+//!     "{\n  // Wake word engine\n  \"listener\": {\"sample_rate\": 16000}\n}\n",
+//! )
+//! .unwrap();
+//! conf.set("listener.sample_rate", &json!(48000)).unwrap();
+//! assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+//! assert!(conf.to_string().contains("// Wake word engine"));
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config::{get_path, read_text, uncomment_json, write_atomic, ConfigDict, ConfigError};
+
+/// A commented JSON document that keeps its original text, changing only
+/// the values that are [`set`](CommentedConfig::set).
+#[derive(Clone, Debug)]
+pub struct CommentedConfig {
+    text: String,
+    data: ConfigDict,
+}
+
+impl CommentedConfig {
+    /// Parses commented JSON text, which must hold an object.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let data = parse_data(text)?;
+        Ok(Self {
+            text: text.to_string(),
+            data,
+        })
+    }
+
+    /// Reads and parses a commented JSON file.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Self::parse(&read_text(path)?)
+    }
+
+    /// Writes the text, comments included, to `path` atomically.
+    pub fn store(&self, path: &Path) -> Result<(), ConfigError> {
+        write_atomic(path, self.text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the value at a dotted path.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        get_path(&self.data, key).cloned()
+    }
+
+    /// Replaces the value at a dotted path, leaving the rest of the text as
+    /// is. A missing key is added as the last member of its parent object,
+    /// creating intermediate objects as needed. Fails if the path runs
+    /// through something other than an object.
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<(), ConfigError> {
+        let segments: Vec<&str> = key.split('.').collect();
+        let mut scanner = Scanner::new(&self.text);
+        scanner.skip_trivia();
+        let text = match scanner.find(&segments)? {
+            Located::Value { start, end } => {
+                let rendered = serde_json::to_string(value).map_err(parse_error)?;
+                format!("{}{}{}", &self.text[..start], rendered, &self.text[end..])
+            }
+            Located::Missing {
+                found,
+                last_member_end,
+                close,
+                separator,
+            } => {
+                let mut nested = value.clone();
+                for segment in segments[found + 1..].iter().rev() {
+                    let mut object = serde_json::Map::new();
+                    object.insert(segment.to_string(), nested);
+                    nested = Value::Object(object);
+                }
+                let member = format!(
+                    "{}: {}",
+                    serde_json::to_string(segments[found]).map_err(parse_error)?,
+                    serde_json::to_string(&nested).map_err(parse_error)?
+                );
+                match last_member_end {
+                    Some(end) => {
+                        // Insert before trailing whitespace so a line comment
+                        // after the last member stays on its own line
+                        let insert = self.text[..close].trim_end().len().max(end);
+                        format!(
+                            "{},{}{}{}{}",
+                            &self.text[..end],
+                            &self.text[end..insert],
+                            separator,
+                            member,
+                            &self.text[insert..]
+                        )
+                    }
+                    None => format!("{}{}{}", &self.text[..close], member, &self.text[close..]),
+                }
+            }
+        };
+        self.data = parse_data(&text)?;
+        self.text = text;
+        Ok(())
+    }
+}
+
+impl fmt::Display for CommentedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Helper function to parse the data of commented JSON text.
+fn parse_data(text: &str) -> Result<ConfigDict, ConfigError> {
+    serde_json::from_str(&uncomment_json(text)).map_err(parse_error)
+}
+
+fn parse_error(e: serde_json::Error) -> ConfigError {
+    ConfigError::Parse(format!("commented JSON: {}", e))
+}
+
+/// Where a dotted path is in the text.
+enum Located {
+    /// The byte range of the existing value.
+    Value { start: usize, end: usize },
+    /// The segment at index `found` is missing from the object closing at
+    /// byte `close`, whose last member ends at `last_member_end`. `separator`
+    /// is the line break and indentation to put before a new member.
+    Missing {
+        found: usize,
+        last_member_end: Option<usize>,
+        close: usize,
+        separator: String,
+    },
+}
+
+/// Byte-level walker over commented JSON that records positions.
+struct Scanner<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn error(&self, msg: &str) -> ConfigError {
+        ConfigError::Parse(format!("commented JSON at byte {}: {}", self.pos, msg))
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            match (self.peek(), self.bytes.get(self.pos + 1)) {
+                (Some(b), _) if b.is_ascii_whitespace() => self.pos += 1,
+                (Some(b'#'), _) | (Some(b'/'), Some(b'/')) => {
+                    while self.peek().is_some_and(|b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    self.pos += 2;
+                    while self.pos < self.bytes.len() && !self.bytes[self.pos..].starts_with(b"*/")
+                    {
+                        self.pos += 1;
+                    }
+                    self.pos = (self.pos + 2).min(self.bytes.len());
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Reads a string literal at the current position, returning it decoded.
+    fn read_string(&mut self) -> Result<String, ConfigError> {
+        let start = self.pos;
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut escaped = false;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    return serde_json::from_str(&self.text[start..self.pos]).map_err(parse_error)
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    /// Skips the value at the current position.
+    fn skip_value(&mut self) -> Result<(), ConfigError> {
+        match self.peek() {
+            Some(b'"') => self.read_string().map(|_| ()),
+            Some(open @ (b'{' | b'[')) => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+                loop {
+                    self.skip_trivia();
+                    match self.peek() {
+                        Some(b) if b == close => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        Some(b',' | b':') => self.pos += 1,
+                        Some(_) => self.skip_value()?,
+                        None => return Err(self.error("unterminated object or array")),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|b| !b.is_ascii_whitespace() && !b",}]/#".contains(&b))
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.error("expected a value"));
+                }
+                Ok(())
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Finds `segments` below the object at the current position.
+    fn find(&mut self, segments: &[&str]) -> Result<Located, ConfigError> {
+        let mut depth = 0;
+        'object: loop {
+            if self.peek() != Some(b'{') {
+                return Err(
+                    self.error(&format!("{} is not an object", segments[..depth].join(".")))
+                );
+            }
+            self.pos += 1;
+            let mut last_member: Option<(usize, usize)> = None;
+            loop {
+                self.skip_trivia();
+                match self.peek() {
+                    Some(b'}') => {
+                        let separator = match last_member {
+                            Some((key_start, _)) => self.separator(key_start),
+                            None => String::new(),
+                        };
+                        return Ok(Located::Missing {
+                            found: depth,
+                            last_member_end: last_member.map(|(_, end)| end),
+                            close: self.pos,
+                            separator,
+                        });
+                    }
+                    Some(b',') => self.pos += 1,
+                    Some(b'"') => {
+                        let key_start = self.pos;
+                        let key = self.read_string()?;
+                        self.skip_trivia();
+                        if self.peek() != Some(b':') {
+                            return Err(self.error("expected ':'"));
+                        }
+                        self.pos += 1;
+                        self.skip_trivia();
+                        if key == segments[depth] {
+                            if depth + 1 == segments.len() {
+                                let start = self.pos;
+                                self.skip_value()?;
+                                return Ok(Located::Value {
+                                    start,
+                                    end: self.pos,
+                                });
+                            }
+                            depth += 1;
+                            continue 'object;
+                        }
+                        self.skip_value()?;
+                        last_member = Some((key_start, self.pos));
+                    }
+                    Some(_) => return Err(self.error("expected a key")),
+                    None => return Err(self.error("unterminated object")),
+                }
+            }
+        }
+    }
+
+    /// Returns the break to put before a new member so it lines up with the
+    /// member whose key starts at `key_start`.
+    fn separator(&self, key_start: usize) -> String {
+        let line_start = self.text[..key_start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &self.text[line_start..key_start];
+        if indent.chars().all(char::is_whitespace) && line_start > 0 {
+            format!("\n{}", indent)
+        } else {
+            " ".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn set_adds_missing_nested_keys_below_existing_comments() {
+        let mut conf = CommentedConfig::parse(
+            "{\n  // Audio input\n  \"listener\": {\n    \"sample_rate\": 16000 // Hz\n  }\n}\n",
+        )
+        .unwrap();
+        conf.set("listener.vad.module", &json!("silero")).unwrap();
+        conf.set("lang", &json!("en-us")).unwrap();
+        assert_eq!(
+            conf.to_string(),
+            "{\n  // Audio input\n  \"listener\": {\n    \"sample_rate\": 16000, // Hz\n    \
+             \"vad\": {\"module\":\"silero\"}\n  },\n  \"lang\": \"en-us\"\n}\n"
+        );
+        assert_eq!(conf.get("listener.vad.module"), Some(json!("silero")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(16000)));
+
+        let mut empty = CommentedConfig::parse("{}").unwrap();
+        empty.set("tts.module", &json!("piper")).unwrap();
+        assert_eq!(empty.to_string(), "{\"tts\": {\"module\":\"piper\"}}");
+    }
+
+    #[test]
+    fn set_keeps_block_comments_next_to_the_value() {
+        let mut conf = CommentedConfig::parse(
+            "{\"sample_rate\": /* Hz */ 16000 /* default */, \"mute\": false}",
+        )
+        .unwrap();
+        conf.set("sample_rate", &json!(48000)).unwrap();
+        assert_eq!(
+            conf.to_string(),
+            "{\"sample_rate\": /* Hz */ 48000 /* default */, \"mute\": false}"
+        );
+        assert_eq!(conf.get("mute"), Some(json!(false)));
+    }
+
+    #[test]
+    fn set_after_a_hash_comment_on_the_last_member() {
+        let mut conf =
+            CommentedConfig::parse("{\n  \"lang\": \"en-us\" # shipped default\n}\n").unwrap();
+        conf.set("hotwords", &json!(["hey ovos"])).unwrap();
+        assert_eq!(
+            conf.to_string(),
+            "{\n  \"lang\": \"en-us\", # shipped default\n  \"hotwords\": [\"hey ovos\"]\n}\n"
+        );
+        assert_eq!(conf.get("hotwords"), Some(json!(["hey ovos"])));
+    }
+
+    #[test]
+    fn set_through_a_non_object_fails_and_changes_nothing() {
+        let text = "{\"lang\": \"en-us\", \"hotwords\": [\"hey mycroft\"]}";
+        let mut conf = CommentedConfig::parse(text).unwrap();
+        for key in ["lang.region", "hotwords.0"] {
+            match conf.set(key, &json!("x")) {
+                Err(ConfigError::Parse(message)) => assert!(
+                    message.contains(&format!(
+                        "{} is not an object",
+                        key.split('.').next().unwrap()
+                    )),
+                    "{}",
+                    message
+                ),
+                other => panic!("expected a parse error for {}, got {:?}", key, other),
+            }
+        }
+        assert_eq!(conf.to_string(), text);
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+    }
+}
@@ -1,242 +1,8930 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime};
 
-use parking_lot::RwLock;
-use serde_json::Value;
+use base64::prelude::*;
+use parking_lot::{RwLock, RwLockWriteGuard};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Number, Value};
 use serde_yaml;
 
-use crate::locations::{DEFAULT_CONFIG, DISTRIBUTION_CONFIG, SYSTEM_CONFIG, USER_CONFIG};
-use crate::log::{debug, error};
+use crate::locations::{
+    ensure_folder_exists, find_default_config, get_xdg_config_locations, get_xdg_config_save_path,
+    DEFAULT_CONFIG, DISTRIBUTION_CONFIG, SYSTEM_CONFIG, USER_CONFIG,
+};
+use crate::log::{debug, error, warn};
+use crate::validation::{ValidationError, ValidationRule};
+use crate::xdg;
 
-type ConfigDict = HashMap<String, Value>;
+pub type ConfigDict = HashMap<String, Value>;
+
+lazy_static::lazy_static! {
+    /// The default `mycroft.conf` bundled into the crate at build time.
+    pub static ref EMBEDDED_DEFAULT_CONFIG: ConfigDict =
+        serde_json::from_str(&uncomment_json(include_str!("mycroft.conf")))
+            .expect("Bundled mycroft.conf is valid JSON");
+
+    // Per-platform overrides of the bundled defaults
+    static ref PLATFORM_DEFAULTS: Vec<(&'static str, ConfigDict)> = vec![
+        ("mark2", platform_overlay(include_str!("platforms/mark2.conf"))),
+        ("raspbian", platform_overlay(include_str!("platforms/raspbian.conf"))),
+        ("docker", platform_overlay(include_str!("platforms/docker.conf"))),
+    ];
+
+    // Dotted paths that have moved, as (deprecated, replacement)
+    static ref DEPRECATIONS: RwLock<Vec<(String, String)>> = RwLock::new(vec![
+        (String::from("padatious"), String::from("intents.padatious")),
+    ]);
+}
+
+/// Returns the bundled defaults for a device platform such as `mark2`,
+/// `raspbian` or `docker`. Unknown platforms get the generic defaults.
+pub fn default_config_for(platform: &str) -> ConfigDict {
+    let mut config = EMBEDDED_DEFAULT_CONFIG.clone();
+    match PLATFORM_DEFAULTS.iter().find(|(name, _)| *name == platform) {
+        Some((_, overlay)) => merge_dict(&mut config, overlay),
+        None => debug(&format!(
+            "No defaults for platform '{}', using generic",
+            platform
+        )),
+    }
+    config
+}
+
+/// Returns the platform named by `OVOS_PLATFORM`, or else the one detected
+/// at runtime, or `generic`. Only containers and Raspberry Pi OS can be
+/// detected; a Mark II needs the variable set.
+pub fn current_platform() -> String {
+    if let Ok(platform) = env::var("OVOS_PLATFORM") {
+        return platform;
+    }
+    if Path::new("/.dockerenv").exists() {
+        return String::from("docker");
+    }
+    let os_release = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
+    if os_release.lines().any(|l| l == "ID=raspbian") {
+        return String::from("raspbian");
+    }
+    String::from("generic")
+}
+
+// Helper function to parse an embedded platform overlay
+fn platform_overlay(contents: &str) -> ConfigDict {
+    serde_json::from_str(&uncomment_json(contents)).expect("Bundled platform config is valid JSON")
+}
+
+/// Registers a deprecated dotted path and the one that replaces it, so
+/// [`Configuration::warn_deprecated`] reports it.
+pub fn register_deprecation(old: &str, new: &str) {
+    let mut deprecations = DEPRECATIONS.write();
+    if !deprecations.iter().any(|(o, _)| o == old) {
+        deprecations.push((old.to_string(), new.to_string()));
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    /// A config file exists but the current user may not read it.
+    PermissionDenied(PathBuf),
+    /// The contents could not be parsed or serialized.
+    Parse(String),
+    /// The requested profile is not defined under `profiles` in any layer.
+    MissingProfile(String),
+    /// The configuration was frozen with [`LocalConf::freeze`].
+    Frozen,
+    /// The configuration has no backing file to write to, or a required one
+    /// doesn't exist.
+    MissingLocation,
+    /// Another writer held the config's lock file for longer than the timeout.
+    LockTimeout(PathBuf),
+    /// A bus message doesn't have the expected envelope.
+    InvalidMessage(String),
+    /// A `$from_file` reference could not be read.
+    FileRef {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// An encryption key of the wrong length was supplied.
+    InvalidKey,
+    /// Decryption failed, either because the key is wrong or the file was tampered with.
+    AuthenticationFailed,
+    /// The configuration was opened read-only.
+    ReadOnly,
+    /// A YAML source holds a NaN or infinite float, which JSON can't represent.
+    NonFinite {
+        path: String,
+    },
+    /// Resolving the path hit a cycle of symbolic links.
+    SymlinkLoop {
+        path: PathBuf,
+    },
+    /// A config path exists but isn't a regular file, e.g. a directory left
+    /// by a bad mount. `kind` names what it is instead.
+    NotAFile {
+        path: PathBuf,
+        kind: &'static str,
+    },
+    /// [`LocalConf::set_preserving`] can't change `key` in `path` without
+    /// rewriting the whole file and losing its comments.
+    CommentsNotPreserved {
+        key: String,
+        path: PathBuf,
+    },
+    /// Files reach themselves through `$include`; `chain` runs from the first
+    /// file back to the repeated one.
+    IncludeCycle {
+        chain: Vec<PathBuf>,
+    },
+    /// A config file isn't valid UTF-8, e.g. it was saved as Latin-1.
+    /// `offset` is the position of the first invalid byte.
+    Encoding {
+        path: PathBuf,
+        offset: usize,
+    },
+    /// The edit would change a key made immutable with [`LocalConf::lock_key`].
+    KeyLocked(String),
+    /// A layer gives a key a different JSON type than a lower one, under
+    /// [`TypeConflictPolicy::Error`].
+    TypeConflict {
+        path: String,
+        existing: JsonType,
+        incoming: JsonType,
+    },
+    /// A D-Bus connection or method call failed.
+    Dbus(String),
+    /// A rename would overwrite the value already at this dotted path.
+    KeyExists(String),
+    /// A config bundle holds a member whose name is absolute or climbs out
+    /// of the archive with `..`.
+    UnsafeArchiveMember {
+        archive: PathBuf,
+        member: String,
+    },
+    /// Two layers set a key whose schema marks it `"x-merge": "error"` to
+    /// different values.
+    MergeConflict {
+        path: String,
+    },
+    /// Setting `key` would go through an array, but `segment` isn't the index
+    /// of one of its `len` items.
+    InvalidIndex {
+        key: String,
+        segment: String,
+        len: usize,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "I/O error: {}", e),
+            ConfigError::PermissionDenied(path) => write!(
+                f,
+                "Permission denied reading {:?}; is the service running as the wrong user?",
+                path
+            ),
+            ConfigError::Parse(msg) => write!(f, "Parse error: {}", msg),
+            ConfigError::MissingProfile(name) => {
+                write!(f, "Profile '{}' is not defined under 'profiles'", name)
+            }
+            ConfigError::FileRef { path, source } => {
+                write!(f, "Unable to read $from_file {:?}: {}", path, source)
+            }
+            ConfigError::Frozen => write!(f, "Configuration is frozen and cannot be modified"),
+            ConfigError::MissingLocation => write!(f, "Configuration has no backing file"),
+            ConfigError::LockTimeout(path) => write!(f, "Timed out waiting for lock {:?}", path),
+            ConfigError::InvalidMessage(msg) => write!(f, "Invalid bus message: {}", msg),
+            ConfigError::InvalidKey => write!(f, "Encryption key must be 32 bytes"),
+            ConfigError::AuthenticationFailed => {
+                write!(f, "Decryption failed: wrong key or corrupted file")
+            }
+            ConfigError::ReadOnly => write!(
+                f,
+                "This configuration is read-only and cannot be modified at runtime"
+            ),
+            ConfigError::NonFinite { path } => {
+                write!(f, "{} is NaN or infinite, which JSON can't represent", path)
+            }
+            ConfigError::SymlinkLoop { path } => {
+                write!(f, "Too many levels of symbolic links resolving {:?}", path)
+            }
+            ConfigError::NotAFile { path, kind } => {
+                write!(f, "{:?} is a {}, not a config file", path, kind)
+            }
+            ConfigError::CommentsNotPreserved { key, path } => write!(
+                f,
+                "Unable to edit {:?} in {:?} without losing comments",
+                key, path
+            ),
+            ConfigError::IncludeCycle { chain } => {
+                let chain: Vec<String> = chain.iter().map(|p| format!("{:?}", p)).collect();
+                write!(f, "$include cycle: {}", chain.join(" -> "))
+            }
+            ConfigError::Encoding { path, offset } => write!(
+                f,
+                "{:?} is not valid UTF-8 (first invalid byte at offset {}); re-save it as UTF-8",
+                path, offset
+            ),
+            ConfigError::UnsafeArchiveMember { archive, member } => write!(
+                f,
+                "{:?} holds {:?}, which points outside the archive",
+                archive, member
+            ),
+            ConfigError::TypeConflict {
+                path,
+                existing,
+                incoming,
+            } => write!(
+                f,
+                "{} is a {} but a later layer sets a {}",
+                path, existing, incoming
+            ),
+            ConfigError::Dbus(msg) => write!(f, "D-Bus error: {}", msg),
+            ConfigError::KeyLocked(key) => write!(f, "{} is locked and can't be changed", key),
+            ConfigError::KeyExists(key) => write!(f, "{} is already set", key),
+            ConfigError::MergeConflict { path } => {
+                write!(
+                    f,
+                    "{} is set to conflicting values by different layers",
+                    path
+                )
+            }
+            ConfigError::InvalidIndex { key, segment, len } => write!(
+                f,
+                "{}: {:?} is not an index into the array of {} items there",
+                key, segment, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
 
 #[derive(Clone)]
 pub struct LocalConf {
     path: Option<PathBuf>,
     data: Arc<RwLock<ConfigDict>>,
     last_loaded: Arc<RwLock<Option<SystemTime>>>,
+    frozen: Arc<RwLock<Frozen>>,
+    subscribers: Arc<RwLock<Vec<Subscriber>>>,
+    non_finite: NonFinitePolicy,
+    hash_reload: bool,
+    last_hash: Arc<RwLock<Option<u64>>>,
+    cache: ReadCache,
+    header: Option<String>,
+    pretty: bool,
+    locked_keys: Arc<RwLock<Vec<String>>>,
+    fallbacks: Vec<PathBuf>,
+    history: Option<Arc<RwLock<History>>>,
+}
+
+type Subscriber = Arc<dyn Fn(&[ConfigChange]) + Send + Sync>;
+
+// One recorded write: each top-level key it touched with the values before
+// and after, `None` where the key was absent
+type Edit = Vec<(String, Option<Value>, Option<Value>)>;
+
+// The undo and redo stacks kept with `LocalConfBuilder::history`
+struct History {
+    depth: usize,
+    undo: std::collections::VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+// Memoized `get` results keyed by dotted path, shared by clones. It's filled
+// under the data read lock and must be cleared under the write lock, so a read
+// racing a write can't cache a stale value.
+#[derive(Clone, Default)]
+struct ReadCache(Arc<RwLock<HashMap<String, Option<Value>>>>);
+
+impl ReadCache {
+    fn get(&self, data: &RwLock<ConfigDict>, key: &str) -> Option<Value> {
+        if let Some(hit) = self.0.read().get(key) {
+            return hit.clone();
+        }
+        let data = data.read();
+        let value = get_path(&data, key).cloned();
+        self.0.write().insert(key.to_string(), value.clone());
+        value
+    }
+
+    fn clear(&self) {
+        self.0.write().clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Frozen {
+    No,
+    AllowReload,
+    Yes,
 }
 
 impl LocalConf {
     pub fn new(path: Option<PathBuf>) -> Self {
-        let conf = Self {
-            path: path.clone(),
-            data: Arc::new(RwLock::new(HashMap::new())),
-            last_loaded: Arc::new(RwLock::new(None)),
-        };
+        let conf = Self::unloaded(path.clone());
         if let Some(p) = path {
             conf.load_local(Some(&p));
         }
         conf
     }
 
+    /// Starts building a config backed by `path`; see [`LocalConfBuilder`].
+    pub fn builder(path: Option<PathBuf>) -> LocalConfBuilder {
+        LocalConfBuilder {
+            path,
+            require_file: false,
+            non_finite: NonFinitePolicy::Null,
+            hash_reload: false,
+            header: None,
+            pretty: true,
+            fallbacks: Vec::new(),
+            history_depth: 0,
+        }
+    }
+
+    /// Creates a config with no backing file, seeded with `initial`. Reads and
+    /// edits work as usual; storing reports that there is no save location.
+    pub fn in_memory(initial: ConfigDict) -> Self {
+        let conf = Self::unloaded(None);
+        *conf.write_data() = initial;
+        conf
+    }
+
+    /// Returns the backing file, or `None` for an in-memory config.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Loads the first of `paths` that exists and can be opened, and adopts it
+    /// as the backing file. Unlike [`Configuration`], nothing is merged: the
+    /// later paths are only fallbacks. Fails with
+    /// [`ConfigError::MissingLocation`] when none is usable, and passes on
+    /// parse errors from the chosen file.
+    pub fn from_first_existing(paths: &[PathBuf]) -> Result<LocalConf, ConfigError> {
+        for path in paths {
+            match file_metadata(path) {
+                Ok(Some(_)) => {}
+                Ok(None) => continue,
+                Err(e @ ConfigError::NotAFile { .. }) => {
+                    warn(&format!("{}, skipping", e));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            if let Err(e) = File::open(path) {
+                debug(&format!("Skipping unreadable {:?}: {}", path, e));
+                continue;
+            }
+            let conf = Self::unloaded(Some(path.clone()));
+            conf.load(None, false, FileRefPolicy::Warn, false)?;
+            return Ok(conf);
+        }
+        Err(ConfigError::MissingLocation)
+    }
+
+    fn unloaded(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            data: Arc::new(RwLock::new(HashMap::new())),
+            last_loaded: Arc::new(RwLock::new(None)),
+            frozen: Arc::new(RwLock::new(Frozen::No)),
+            subscribers: Arc::new(RwLock::new(Vec::new())),
+            non_finite: NonFinitePolicy::Null,
+            hash_reload: false,
+            last_hash: Arc::new(RwLock::new(None)),
+            cache: ReadCache::default(),
+            header: None,
+            pretty: true,
+            locked_keys: Arc::new(RwLock::new(Vec::new())),
+            fallbacks: Vec::new(),
+            history: None,
+        }
+    }
+
+    // Write access to the data; every write goes through here so memoized
+    // reads are dropped
+    fn write_data(&self) -> RwLockWriteGuard<'_, ConfigDict> {
+        let data = self.data.write();
+        self.cache.clear();
+        data
+    }
+
+    // Like `write_data`, but `None` instead of waiting when the lock is held
+    fn try_write_data(&self) -> Option<RwLockWriteGuard<'_, ConfigDict>> {
+        let data = self.data.try_write()?;
+        self.cache.clear();
+        Some(data)
+    }
+
     fn get_file_format(&self, path: Option<&Path>) -> &'static str {
-        let path = path
-            .or_else(|| self.path.as_deref())
-            .unwrap_or_else(|| Path::new(""));
+        let path = path.or(self.path.as_deref()).unwrap_or(Path::new(""));
         match path.extension().and_then(|s| s.to_str()) {
             Some("yml") | Some("yaml") => "yaml",
             _ => "json",
         }
     }
 
+    /// Loads `path` (or the backing file) over the current data. Files that
+    /// can't be read due to permissions are logged and skipped, as are paths
+    /// that turn out to be a directory or other non-file, with a warning.
     pub fn load_local(&self, path: Option<&Path>) {
-        let path = path.or_else(|| self.path.as_deref());
+        handle_load_result(self.load(path, false, FileRefPolicy::Warn, false));
+    }
+
+    /// Like [`LocalConf::load_local`], but returns read and parse failures,
+    /// including [`ConfigError::NotAFile`], and fails on `$from_file` references to missing files instead of keeping them.
+    pub fn try_load_local(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        self.load(path, false, FileRefPolicy::Error, false)
+    }
+
+    /// Re-reads the backing file, replacing rather than adding to the current
+    /// data so keys deleted from the file are dropped too.
+    pub fn reload_replace(&self) {
+        handle_load_result(self.load(None, true, FileRefPolicy::Warn, false));
+    }
+
+    /// Loads JSON by deserializing straight from the file, so the raw text and
+    /// the parsed config are never held in memory together. This helps with very
+    /// large files; YAML files are still read in full. Failures are returned
+    /// like [`LocalConf::try_load_local`].
+    pub fn load_local_streaming(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        self.load(path, false, FileRefPolicy::Error, true)
+    }
+
+    fn load(
+        &self,
+        path: Option<&Path>,
+        replace: bool,
+        refs: FileRefPolicy,
+        streaming: bool,
+    ) -> Result<(), ConfigError> {
+        if *self.frozen.read() == Frozen::Yes {
+            return Err(ConfigError::Frozen);
+        }
+        let path = path.or(self.path.as_deref());
         if let Some(path) = path {
-            if path.exists() && path.is_file() {
-                let config = match self.get_file_format(Some(path)) {
-                    "yaml" => {
-                        let mut file = File::open(path).expect("Unable to open file");
-                        let mut contents = String::new();
-                        file.read_to_string(&mut contents)
-                            .expect("Unable to read file");
-                        serde_yaml::from_str(&contents).expect("Unable to parse YAML")
-                    }
-                    _ => load_commented_json(path).expect("Unable to load JSON"),
-                };
-                let mut data = self.data.write();
-                for (key, value) in config {
-                    data.insert(key, value);
-                }
-                debug(&format!("Configuration {:?} loaded", path));
-                if path == self.path.as_deref().unwrap_or_else(|| Path::new("")) {
-                    if let Ok(metadata) = path.metadata() {
-                        if let Ok(modified) = metadata.modified() {
-                            *self.last_loaded.write() = Some(modified);
-                        }
-                    }
+            if file_metadata(path)?.is_some() {
+                let (config, modified, hash) = self.read_file(path, streaming)?;
+                self.apply_loaded(path, config, modified, replace, refs)?;
+                if hash.is_some() {
+                    *self.last_hash.write() = hash;
                 }
             } else {
                 debug(&format!("Configuration {:?} not defined, skipping", path));
             }
         }
+        Ok(())
+    }
+
+    // Parses `path`, returning the config with the file's mtime and, in hash
+    // reload mode, the hash of its bytes when it's the backing file
+    fn read_file(
+        &self,
+        path: &Path,
+        streaming: bool,
+    ) -> Result<(ConfigDict, Option<SystemTime>, Option<u64>), ConfigError> {
+        // Hashed before parsing so a write racing the load is picked up by the
+        // next reload rather than masked
+        let hash = (self.hash_reload && Some(path) == self.path.as_deref())
+            .then(|| file_hash(path))
+            .flatten();
+        let config = match self.get_file_format(Some(path)) {
+            "yaml" => {
+                let contents = read_text(path)?;
+                parse_yaml(&contents, path, self.non_finite)?
+            }
+            _ if streaming => load_commented_json_streaming(path)?,
+            _ => load_commented_json(path)?,
+        };
+        let modified = path.metadata().and_then(|m| m.modified()).ok();
+        Ok((config, modified, hash))
+    }
+
+    /// Reads stdin to EOF and merges the document over the current data like
+    /// [`LocalConf::try_load_local`]. Stdin can only be consumed once, so a
+    /// second call sees empty input; empty input is a no-op.
+    pub fn load_from_stdin(&self, format: Format) -> Result<(), ConfigError> {
+        self.load_reader(std::io::stdin().lock(), format, Path::new("<stdin>"))
+    }
+
+    /// Like [`LocalConf::load_from_stdin`], for any reader.
+    pub fn load_from_reader<R: Read>(&self, reader: R, format: Format) -> Result<(), ConfigError> {
+        self.load_reader(reader, format, Path::new("<reader>"))
+    }
+
+    fn load_reader<R: Read>(
+        &self,
+        mut reader: R,
+        format: Format,
+        source: &Path,
+    ) -> Result<(), ConfigError> {
+        if *self.frozen.read() == Frozen::Yes {
+            return Err(ConfigError::Frozen);
+        }
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        if contents.trim().is_empty() {
+            debug(&format!("{:?} is empty, nothing to load", source));
+            return Ok(());
+        }
+        let config = match format {
+            Format::Yaml => parse_yaml(&contents, source, self.non_finite)?,
+            Format::Json => serde_json::from_str(&uncomment_json(&contents))
+                .map_err(|e| ConfigError::Parse(format!("{:?}: {}", source, e)))?,
+        };
+        self.apply_loaded(source, config, None, false, FileRefPolicy::Error)
+    }
+
+    // Resolves file references and XDG tokens in freshly parsed `config` and
+    // applies it, recording `modified` as the load time when `path` is the
+    // backing file
+    fn apply_loaded(
+        &self,
+        path: &Path,
+        mut config: ConfigDict,
+        modified: Option<SystemTime>,
+        replace: bool,
+        refs: FileRefPolicy,
+    ) -> Result<(), ConfigError> {
+        resolve_file_refs(&mut config, refs)?;
+        resolve_xdg_tokens(&mut config);
+        self.commit_loaded(&mut self.write_data(), path, config, modified, replace);
+        Ok(())
+    }
+
+    // Writes resolved `config` into the locked `data`
+    fn commit_loaded(
+        &self,
+        data: &mut ConfigDict,
+        path: &Path,
+        config: ConfigDict,
+        modified: Option<SystemTime>,
+        replace: bool,
+    ) {
+        if replace {
+            data.clear();
+        }
+        for (key, value) in config {
+            data.insert(key, value);
+        }
+        debug(&format!("Configuration {:?} loaded", path));
+        if path == self.path.as_deref().unwrap_or_else(|| Path::new("")) && modified.is_some() {
+            *self.last_loaded.write() = modified;
+        }
+    }
+
+    /// Async counterpart of [`LocalConf::try_load_local`], reading the file
+    /// through `tokio::fs` so slow mounts don't block the runtime. Files named
+    /// by `$from_file` references are still read synchronously.
+    #[cfg(feature = "async")]
+    pub async fn load_local_async(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        self.load_async(path, FileRefPolicy::Error).await
+    }
+
+    #[cfg(feature = "async")]
+    async fn load_async(
+        &self,
+        path: Option<&Path>,
+        refs: FileRefPolicy,
+    ) -> Result<(), ConfigError> {
+        if *self.frozen.read() == Frozen::Yes {
+            return Err(ConfigError::Frozen);
+        }
+        let Some(path) = path.or(self.path.as_deref()) else {
+            return Ok(());
+        };
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => check_is_file(path, metadata)?,
+            Err(e) if is_symlink_loop(&e) => {
+                return Err(ConfigError::SymlinkLoop {
+                    path: path.to_path_buf(),
+                })
+            }
+            _ => {
+                debug(&format!("Configuration {:?} not defined, skipping", path));
+                return Ok(());
+            }
+        };
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| read_error(path, e))?;
+        let contents = decode_utf8(path, bytes)?;
+        let config = match self.get_file_format(Some(path)) {
+            "yaml" => parse_yaml(&contents, path, self.non_finite)?,
+            _ => serde_json::from_str(&uncomment_json(&contents))
+                .map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))?,
+        };
+        self.apply_loaded(path, config, metadata.modified().ok(), false, refs)
     }
 
     pub fn reload(&self) {
-        if let Some(path) = &self.path {
-            if path.is_file() {
-                if let Ok(metadata) = path.metadata() {
-                    if let Ok(modified) = metadata.modified() {
-                        let last_loaded = self.last_loaded.read();
-                        if last_loaded.map_or(true, |last| last < modified) {
-                            drop(last_loaded); // Release the read lock before calling load_local
-                            self.load_local(Some(path));
-                        } else {
-                            debug(&format!("{:?} not changed since last load", path));
+        handle_load_result(self.reload_checked().map(|_| ()));
+    }
+
+    /// Returns whether the in-memory data differs from the backing file as it
+    /// is on disk now, whether from unsaved edits or from the file changing
+    /// underneath. The file is re-read and parsed each call; a missing file
+    /// counts as empty and an unreadable one as differing. Always `false` for
+    /// an in-memory config, which has nowhere to save to.
+    pub fn is_dirty(&self) -> bool {
+        let Some(path) = self.path.as_deref() else {
+            return false;
+        };
+        let on_disk = match file_metadata(path) {
+            Ok(None) => ConfigDict::new(),
+            Ok(Some(_)) => match self.read_file(path, false) {
+                Ok((mut config, _, _)) => {
+                    if resolve_file_refs(&mut config, FileRefPolicy::Warn).is_err() {
+                        return true;
+                    }
+                    resolve_xdg_tokens(&mut config);
+                    config
+                }
+                Err(_) => return true,
+            },
+            Err(_) => return true,
+        };
+        *self.data.read() != on_disk
+    }
+
+    /// Re-reads the backing file if it changed on disk since the last load,
+    /// returning whether it was re-read.
+    pub fn reload_checked(&self) -> Result<bool, ConfigError> {
+        let Some(path) = self.pending_reload()? else {
+            return Ok(false);
+        };
+        self.load(Some(path), false, FileRefPolicy::Warn, false)?;
+        Ok(true)
+    }
+
+    /// Calls [`LocalConf::reload_checked`] every `interval` on a background
+    /// thread, for mounts where change notifications can't be trusted, and
+    /// passes `callback` the changes whenever a reload altered the data.
+    /// Reload failures are logged. Polling stops when the returned guard is
+    /// dropped.
+    pub fn poll_reload<F>(
+        &self,
+        interval: Duration,
+        mut callback: F,
+    ) -> Result<ConfigWatcher, ConfigError>
+    where
+        F: FnMut(&[ConfigChange]) + Send + 'static,
+    {
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let conf = self.clone();
+        let thread = std::thread::Builder::new()
+            .name("ovos-config-poll".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    match conf.pending_reload() {
+                        Ok(Some(_)) => {}
+                        Ok(None) => continue,
+                        Err(e) => {
+                            error(&e.to_string());
+                            continue;
                         }
                     }
+                    let before = conf.data.read().clone();
+                    if let Err(e) = conf.reload_checked() {
+                        error(&e.to_string());
+                        continue;
+                    }
+                    let changes = diff(&before, &conf.data.read());
+                    if !changes.is_empty() {
+                        callback(&changes);
+                    }
                 }
+            })?;
+        Ok(ConfigWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Like [`LocalConf::reload_checked`], but never waits for the data lock:
+    /// returns `None` if another thread holds it, so a UI thread can simply try
+    /// again on its next tick. The file is parsed before the lock is tried, so
+    /// a contended attempt costs that parse.
+    pub fn try_reload(&self) -> Option<Result<bool, ConfigError>> {
+        self.try_reload_uncontended().transpose()
+    }
+
+    fn try_reload_uncontended(&self) -> Result<Option<bool>, ConfigError> {
+        let Some(path) = self.pending_reload()? else {
+            return Ok(Some(false));
+        };
+        if *self.frozen.read() == Frozen::Yes {
+            return Err(ConfigError::Frozen);
+        }
+        let (mut config, modified, hash) = self.read_file(path, false)?;
+        resolve_file_refs(&mut config, FileRefPolicy::Warn)?;
+        resolve_xdg_tokens(&mut config);
+        let Some(mut data) = self.try_write_data() else {
+            debug(&format!("{:?} is locked, skipping reload", path));
+            return Ok(None);
+        };
+        self.commit_loaded(&mut data, path, config, modified, false);
+        drop(data);
+        if hash.is_some() {
+            *self.last_hash.write() = hash;
+        }
+        Ok(Some(true))
+    }
+
+    // Returns the backing file if it changed on disk since the last load
+    fn pending_reload(&self) -> Result<Option<&Path>, ConfigError> {
+        let Some(path) = self.path.as_deref() else {
+            return Ok(None);
+        };
+        let Some(metadata) = file_metadata(path)? else {
+            return Ok(None);
+        };
+        let Ok(modified) = metadata.modified() else {
+            return Ok(None);
+        };
+        if self.last_loaded.read().is_some_and(|last| last >= modified) {
+            debug(&format!("{:?} not changed since last load", path));
+            return Ok(None);
+        }
+        if self.hash_reload {
+            let hash = file_hash(path);
+            if hash.is_some() && hash == *self.last_hash.read() {
+                debug(&format!("{:?} touched but content unchanged", path));
+                *self.last_loaded.write() = Some(modified);
+                return Ok(None);
             }
         }
+        Ok(Some(path))
     }
-    pub fn store(&self, path: Option<&Path>) {
-        let path = path.or_else(|| self.path.as_deref());
-        if let Some(path) = path {
-            let data = self.data.read();
-            match self.get_file_format(Some(path)) {
-                "yaml" => {
-                    let yaml_string =
-                        serde_yaml::to_string(&*data).expect("Unable to serialize to YAML");
-                    let mut file = OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .open(path)
-                        .expect("Unable to open file");
-                    file.write_all(yaml_string.as_bytes())
-                        .expect("Unable to write file");
-                }
-                _ => {
-                    let json_string =
-                        serde_json::to_string_pretty(&*data).expect("Unable to serialize to JSON");
-                    let mut file = OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .open(path)
-                        .expect("Unable to open file");
-                    file.write_all(json_string.as_bytes())
-                        .expect("Unable to write file");
+
+    /// Writes the data to `path`, or the backing file, as YAML or JSON by
+    /// extension, below the header set with [`LocalConfBuilder::store_header`]
+    /// if any. Without `path`, the fallbacks set with
+    /// [`LocalConfBuilder::store_fallbacks`] are tried in turn if the backing
+    /// file can't be written, see [`LocalConf::store_with_fallbacks`]. Fails
+    /// with [`ConfigError::MissingLocation`] for an in-memory config without
+    /// fallbacks and passes on serialization and write errors.
+    ///
+    /// No lock is taken: the whole file is replaced with this config's data,
+    /// so serializing it against other writers would only pick which one's
+    /// changes are lost. Processes sharing a file should write through
+    /// [`LocalConf::set_locked`] instead.
+    pub fn store(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        match path {
+            None if !self.fallbacks.is_empty() => self.store_with_fallbacks().map(|_| ()),
+            _ => self.store_to(
+                path.or(self.path.as_deref())
+                    .ok_or(ConfigError::MissingLocation)?,
+            ),
+        }
+    }
+
+    /// Writes to the backing file, or else to the first of the fallbacks set
+    /// with [`LocalConfBuilder::store_fallbacks`] that can be written,
+    /// creating its directory, and returns the path written. Each failure and
+    /// the fallback used are logged; the config keeps loading from its backing
+    /// file. Fails with the last error if no location works, or with
+    /// [`ConfigError::MissingLocation`] if there are none.
+    pub fn store_with_fallbacks(&self) -> Result<PathBuf, ConfigError> {
+        let primary = self.path.iter().map(|path| (path, false));
+        let fallbacks = self.fallbacks.iter().map(|path| (path, true));
+        let mut last_error = ConfigError::MissingLocation;
+        for (path, is_fallback) in primary.chain(fallbacks) {
+            if is_fallback {
+                ensure_folder_exists(path);
+            }
+            match self.store_to(path) {
+                Ok(()) => {
+                    if is_fallback {
+                        warn(&format!("Stored configuration to fallback {:?}", path));
+                    }
+                    return Ok(path.clone());
+                }
+                Err(e) => {
+                    warn(&format!(
+                        "Unable to store configuration to {:?}: {}",
+                        path, e
+                    ));
+                    last_error = e;
                 }
             }
-        } else {
-            error("In-memory configuration, no save location");
         }
+        Err(last_error)
+    }
+
+    fn store_to(&self, path: &Path) -> Result<(), ConfigError> {
+        let body = {
+            let data = self.data.read();
+            match self.get_file_format(Some(path)) {
+                "yaml" => serde_yaml::to_string(&*data)
+                    .map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))?,
+                _ if self.pretty => serde_json::to_string_pretty(&*data)
+                    .map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))?,
+                _ => serde_json::to_string(&*data)
+                    .map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))?,
+            }
+        };
+        let contents = match &self.header {
+            Some(header) => comment_header(header) + &body,
+            None => body,
+        };
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
     }
 
-    pub fn merge(&mut self, conf: &ConfigDict) {
-        let mut data = self.data.write();
-        for (key, value) in conf {
-            data.insert(key.clone(), value.clone());
+    pub fn merge(&self, conf: &ConfigDict) -> Result<(), ConfigError> {
+        self.check_mutable()?;
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            let mut changed = Vec::new();
+            for (key, value) in conf {
+                let before = changes.len();
+                diff_value(key.clone(), data.get(key), Some(value), &mut changes);
+                if changes.len() > before {
+                    changed.push((key, value));
+                }
+            }
+            let edit = self.check_edit(&data, &changes)?;
+            for (key, value) in changed {
+                data.insert(key.clone(), value.clone());
+            }
+            self.record(&data, edit);
         }
+        self.notify(&changes);
+        Ok(())
     }
-}
 
-pub struct ReadOnlyConfig {
-    inner: LocalConf,
-    allow_overwrite: bool,
-}
+    /// Stores to the backing file while holding an advisory lock on an adjacent
+    /// `.lock` file, waiting up to `timeout` for other writers. The file is
+    /// overwritten with this config's data as is, so changes other writers
+    /// made since it was loaded are lost; use [`LocalConf::set_locked`] to
+    /// change a key without clobbering them.
+    pub fn store_locked(&self, timeout: Duration) -> Result<(), ConfigError> {
+        let path = self.path.as_deref().ok_or(ConfigError::MissingLocation)?;
+        let _lock = FileLock::acquire(path, timeout)?;
+        self.store(None)
+    }
 
-impl ReadOnlyConfig {
-    pub fn new(path: PathBuf, allow_overwrite: bool) -> Self {
-        Self {
-            inner: LocalConf::new(Some(path)),
-            allow_overwrite,
+    /// Read-modify-write of a single key coordinated across processes: takes
+    /// the lock, re-reads the file in place of the in-memory data to pick up
+    /// other writers' changes (including deleted keys), sets the key, stores
+    /// and releases the lock. Other unsaved in-memory edits are discarded.
+    pub fn set_locked(
+        &self,
+        key: &str,
+        value: Value,
+        timeout: Duration,
+    ) -> Result<(), ConfigError> {
+        let path = self.path.as_deref().ok_or(ConfigError::MissingLocation)?;
+        let _lock = FileLock::acquire(path, timeout)?;
+        self.load(Some(path), true, FileRefPolicy::Warn, false)?;
+        if !self.set_changed(key, value)? {
+            return Ok(());
         }
+        self.store(None)
     }
 
-    pub fn reload(&mut self) {
-        let old = self.allow_overwrite;
-        self.allow_overwrite = true;
-        self.inner.reload();
-        self.allow_overwrite = old;
+    /// Like [`LocalConf::merge`], but nested objects are merged key by key
+    /// instead of being replaced wholesale.
+    pub fn merge_deep(&self, conf: &ConfigDict) -> Result<(), ConfigError> {
+        self.merge_deep_changes(conf).map(|_| ())
     }
 
-    pub fn set(&mut self, key: &str, value: Value) -> Result<(), &'static str> {
-        if !self.allow_overwrite {
-            Err("This configuration is read-only and cannot be modified at runtime")
-        } else {
-            self.inner.data.write().insert(key.to_string(), value);
-            Ok(())
-        }
+    /// Deep-merges like [`LocalConf::merge_deep`] and returns every path where
+    /// an existing value was overwritten with a different one, so unintended
+    /// clobbers can be spotted. Newly added keys aren't conflicts.
+    pub fn merge_reporting(&self, conf: &ConfigDict) -> Result<Vec<Conflict>, ConfigError> {
+        let changes = self.merge_deep_changes(conf)?;
+        Ok(changes
+            .into_iter()
+            .filter_map(|change| match change {
+                ConfigChange::Changed { path, old, new } => Some(Conflict { path, old, new }),
+                _ => None,
+            })
+            .collect())
     }
 
-    pub fn merge(&mut self, conf: &ConfigDict) -> Result<(), &'static str> {
-        if !self.allow_overwrite {
-            Err("This configuration is read-only and cannot be modified at runtime")
-        } else {
-            self.inner.merge(conf);
-            Ok(())
+    /// Applies an RFC 7386 JSON Merge Patch: objects merge recursively, `null`
+    /// deletes the key and anything else replaces it. Unlike
+    /// [`LocalConf::merge_deep`], a `null` is never stored. The patch must be
+    /// an object, since the config root is one.
+    pub fn apply_merge_patch(&self, patch: &Value) -> Result<(), ConfigError> {
+        let Value::Object(patch) = patch else {
+            return Err(ConfigError::Parse(
+                "merge patch must be a JSON object".to_string(),
+            ));
+        };
+        self.check_mutable()?;
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            let mut patched = Vec::new();
+            for (key, value) in patch {
+                let old = data.get(key);
+                let new = (!value.is_null()).then(|| {
+                    let mut new = old.cloned().unwrap_or(Value::Null);
+                    merge_patch_value(&mut new, value);
+                    new
+                });
+                diff_value(key.clone(), old, new.as_ref(), &mut changes);
+                patched.push((key, new));
+            }
+            let edit = self.check_edit(&data, &changes)?;
+            for (key, new) in patched {
+                match new {
+                    Some(new) => data.insert(key.clone(), new),
+                    None => data.remove(key),
+                };
+            }
+            self.record(&data, edit);
         }
+        self.notify(&changes);
+        Ok(())
     }
 
-    pub fn store(&self, path: Option<&Path>) -> Result<(), &'static str> {
-        if !self.allow_overwrite {
-            Err("This configuration is read-only and cannot be modified at runtime")
-        } else {
-            self.inner.store(path);
-            Ok(())
+    fn merge_deep_changes(&self, conf: &ConfigDict) -> Result<Vec<ConfigChange>, ConfigError> {
+        self.check_mutable()?;
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            let mut changed = Vec::new();
+            for (key, value) in conf {
+                let mut merged = data.get(key).cloned().unwrap_or(Value::Null);
+                merge_value(&mut merged, value);
+                let before = changes.len();
+                diff_value(key.clone(), data.get(key), Some(&merged), &mut changes);
+                if changes.len() > before {
+                    changed.push((key, merged));
+                }
+            }
+            let edit = self.check_edit(&data, &changes)?;
+            for (key, merged) in changed {
+                data.insert(key.clone(), merged);
+            }
+            self.record(&data, edit);
         }
+        self.notify(&changes);
+        Ok(changes)
     }
-}
-
-pub struct MycroftDefaultConfig(ReadOnlyConfig);
 
-impl MycroftDefaultConfig {
-    pub fn new() -> Self {
-        Self(ReadOnlyConfig::new(DEFAULT_CONFIG.to_path_buf(), false))
+    /// Serializes `value` and deep-merges it at a dotted path, so a typed
+    /// settings struct can be stored and read back with
+    /// [`LocalConf::get_section`]. Keys the struct doesn't have are kept.
+    pub fn merge_struct<T: Serialize>(&self, key: &str, value: &T) -> Result<(), ConfigError> {
+        let value = serde_json::to_value(value)
+            .map_err(|e| ConfigError::Parse(format!("{}: {}", key, e)))?;
+        self.update_path(key, |current| {
+            let mut merged = current.cloned().unwrap_or(Value::Null);
+            merge_value(&mut merged, &value);
+            merged
+        })
+        .map(|_| ())
     }
 
-    pub fn set_root_config_path(&mut self, root_config: PathBuf) {
-        self.0.inner.path = Some(root_config);
-        self.0.reload();
+    /// Sets the value at a dotted path, creating intermediate objects as needed.
+    /// Numeric segments index into existing arrays, e.g. `servers.0.host`;
+    /// one past the end or beyond fails with [`ConfigError::InvalidIndex`].
+    /// Setting a key to the value it already has is a no-op.
+    pub fn set(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.set_changed(key, value).map(|_| ())
     }
-}
 
-pub struct OvosDistributionConfig(ReadOnlyConfig);
+    // Sets the value at a dotted path unless it's already there, returning
+    // whether anything changed
+    fn set_changed(&self, key: &str, value: Value) -> Result<bool, ConfigError> {
+        self.update_path(key, |_| value)
+    }
 
-impl OvosDistributionConfig {
-    pub fn new(allow_overwrite: bool) -> Self {
-        Self(ReadOnlyConfig::new(
-            DISTRIBUTION_CONFIG.to_path_buf(),
-            allow_overwrite,
-        ))
+    // Replaces the value at a dotted path with what `f` makes of the current
+    // one, under one write lock, returning whether anything changed
+    fn update_path(
+        &self,
+        key: &str,
+        f: impl FnOnce(Option<&Value>) -> Value,
+    ) -> Result<bool, ConfigError> {
+        self.check_mutable()?;
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            let value = f(get_path(&data, key));
+            diff_value(
+                key.to_string(),
+                get_path(&data, key),
+                Some(&value),
+                &mut changes,
+            );
+            let edit = self.check_edit(&data, &changes)?;
+            if !changes.is_empty() {
+                set_path(&mut data, key, value)?;
+            }
+            self.record(&data, edit);
+        }
+        self.notify(&changes);
+        Ok(!changes.is_empty())
     }
-}
 
-pub struct MycroftSystemConfig(ReadOnlyConfig);
+    /// Runs `f` with the dotted `key` set to `value`, then puts back the old
+    /// value, or removes the key if it wasn't set, even if `f` panics. A frozen
+    /// config can't be overridden; that's logged and `f` sees it unchanged.
+    pub fn with_override<R>(&self, key: &str, value: Value, f: impl FnOnce() -> R) -> R {
+        struct Restore<'a> {
+            conf: &'a LocalConf,
+            key: String,
+            previous: Option<Value>,
+        }
 
-impl MycroftSystemConfig {
-    pub fn new(allow_overwrite: bool) -> Self {
-        Self(ReadOnlyConfig::new(
-            SYSTEM_CONFIG.to_path_buf(),
-            allow_overwrite,
-        ))
-    }
-}
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                let restored = match self.previous.take() {
+                    Some(previous) => self.conf.set(&self.key, previous),
+                    None => self.conf.remove(&self.key).map(|_| ()),
+                };
+                if let Err(e) = restored {
+                    error(&format!("Unable to restore {:?}: {}", self.key, e));
+                }
+            }
+        }
 
-pub struct MycroftUserConfig(LocalConf);
+        // Setting the key creates or replaces any missing ancestors and those
+        // that are neither objects nor arrays, so remember the outermost one
+        // that will change
+        let segments: Vec<&str> = key.split('.').collect();
+        let (mut restore_key, mut previous) = (key.to_string(), None);
+        for i in 1..=segments.len() {
+            let prefix = segments[..i].join(".");
+            match self.get(&prefix) {
+                Some(node) if (node.is_object() || node.is_array()) && i < segments.len() => {
+                    continue
+                }
+                current => {
+                    (restore_key, previous) = (prefix, current);
+                    break;
+                }
+            }
+        }
+        if let Err(e) = self.set(key, value) {
+            error(&format!("Unable to override {:?}: {}", key, e));
+            return f();
+        }
+        let _restore = Restore {
+            conf: self,
+            key: restore_key,
+            previous,
+        };
+        f()
+    }
 
-impl MycroftUserConfig {
-    pub fn new() -> Self {
-        Self(LocalConf::new(Some(USER_CONFIG.to_path_buf())))
+    /// Removes the value at a dotted path, returning it if it was present.
+    pub fn remove(&self, key: &str) -> Result<Option<Value>, ConfigError> {
+        self.check_mutable()?;
+        let mut changes = Vec::new();
+        let removed = {
+            let mut data = self.write_data();
+            diff_value(key.to_string(), get_path(&data, key), None, &mut changes);
+            let edit = self.check_edit(&data, &changes)?;
+            let removed = remove_path(&mut data, key);
+            self.record(&data, edit);
+            removed
+        };
+        self.notify(&changes);
+        Ok(removed)
     }
-}
 
-pub type MycroftXDGConfig = MycroftUserConfig;
+    /// Moves the value at the dotted path `from` to `to` under one write
+    /// lock, creating intermediate objects as needed, and returns whether
+    /// anything moved. Fails with [`ConfigError::KeyExists`] if `to` is
+    /// already set; see [`LocalConf::rename_key_overwriting`].
+    pub fn rename_key(&self, from: &str, to: &str) -> Result<bool, ConfigError> {
+        self.rename_key_with(from, to, false)
+    }
 
-// Helper function to load JSON with comments
-fn load_commented_json(path: &Path) -> Result<ConfigDict, Box<dyn std::error::Error>> {
-    let mut file = File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+    /// Like [`LocalConf::rename_key`], replacing any value already at `to`.
+    pub fn rename_key_overwriting(&self, from: &str, to: &str) -> Result<bool, ConfigError> {
+        self.rename_key_with(from, to, true)
+    }
 
-    // Remove comments (both single-line and multi-line)
-    let re = regex::Regex::new(r"(/\*([^*]|[\r\n]|(\*+([^*/]|[\r\n])))*\*+/)|(//.*)")?;
-    let json_str = re.replace_all(&contents, "");
+    fn rename_key_with(&self, from: &str, to: &str, overwrite: bool) -> Result<bool, ConfigError> {
+        self.check_mutable()?;
+        if from == to {
+            return Ok(false);
+        }
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            if get_path(&data, from).is_none() {
+                return Ok(false);
+            }
+            let existing = get_path(&data, to).cloned();
+            if existing.is_some() && !overwrite {
+                return Err(ConfigError::KeyExists(to.to_string()));
+            }
+            let value = get_path(&data, from)
+                .cloned()
+                .expect("source was just found");
+            diff_value(from.to_string(), Some(&value), None, &mut changes);
+            diff_value(
+                to.to_string(),
+                existing.as_ref(),
+                Some(&value),
+                &mut changes,
+            );
+            let edit = self.check_edit(&data, &changes)?;
+            // Renamed on a copy, so a `to` that can't be set loses nothing
+            let mut renamed = data.clone();
+            remove_path(&mut renamed, from);
+            set_path(&mut renamed, to, value)?;
+            *data = renamed;
+            self.record(&data, edit);
+        }
+        self.notify(&changes);
+        Ok(true)
+    }
+
+    pub fn clear(&self) -> Result<(), ConfigError> {
+        self.check_mutable()?;
+        let changes = {
+            let mut data = self.write_data();
+            let changes = diff(&data, &ConfigDict::new());
+            let edit = self.check_edit(&data, &changes)?;
+            data.clear();
+            self.record(&data, edit);
+            changes
+        };
+        self.notify(&changes);
+        Ok(())
+    }
+
+    /// Swaps in `new` as the whole configuration under a single write lock,
+    /// so readers see either the old map or the new one, never a mix. The
+    /// differences are passed to [`LocalConf::subscribe`] callbacks.
+    pub fn replace_all(&self, new: ConfigDict) -> Result<(), ConfigError> {
+        self.check_mutable()?;
+        let changes = {
+            let mut data = self.write_data();
+            let changes = diff(&data, &new);
+            let edit = self.check_edit(&data, &changes)?;
+            *data = new;
+            self.record(&data, edit);
+            changes
+        };
+        self.notify(&changes);
+        Ok(())
+    }
+
+    // Runs the subscribers, unless nothing actually changed
+    fn notify(&self, changes: &[ConfigChange]) {
+        if !changes.is_empty() {
+            for subscriber in self.subscribers.read().iter() {
+                subscriber(changes);
+            }
+        }
+    }
+
+    /// Registers a callback run with the changes made by `set`, `merge`,
+    /// `merge_deep`, `remove`, `clear` and [`LocalConf::replace_all`].
+    /// Assigning a value a key already has reports nothing. Callbacks run on
+    /// the mutating thread after the lock is released.
+    pub fn subscribe<F>(&self, callback: F)
+    where
+        F: Fn(&[ConfigChange]) + Send + Sync + 'static,
+    {
+        self.subscribers.write().push(Arc::new(callback));
+    }
+
+    /// Sets a value at a dotted path and writes it back to the backing file,
+    /// changing only that value so comments and formatting elsewhere survive.
+    ///
+    /// JSON files are edited with [`crate::commented::CommentedConfig`]. YAML
+    /// files only support replacing an existing scalar; anything else returns
+    /// [`ConfigError::CommentsNotPreserved`] with the in-memory value already
+    /// set, so call [`LocalConf::store`] to rewrite the file without comments.
+    /// A file that doesn't exist yet is written with `store`.
+    pub fn set_preserving(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        if !self.set_changed(key, value.clone())? {
+            return Ok(());
+        }
+        let Some(path) = self.path.as_deref() else {
+            error("In-memory configuration, no save location");
+            return Ok(());
+        };
+        if !path.is_file() {
+            return self.store(None);
+        }
+        if self.get_file_format(Some(path)) == "yaml" {
+            let contents = read_text(path)?;
+            let Some(updated) = set_yaml_scalar(&contents, key, &value) else {
+                warn(&format!(
+                    "Unable to edit {:?} in place, {:?} left unchanged",
+                    key, path
+                ));
+                return Err(ConfigError::CommentsNotPreserved {
+                    key: key.to_string(),
+                    path: path.to_path_buf(),
+                });
+            };
+            write_atomic(path, updated.as_bytes())?;
+            return Ok(());
+        }
+        let mut commented = crate::commented::CommentedConfig::load(path)?;
+        commented.set(key, &value)?;
+        commented.store(path)
+    }
+
+    /// Calls `f` with the dotted path and value of every scalar leaf, including
+    /// array elements (as `servers.0.host`).
+    pub fn for_each_leaf(&self, mut f: impl FnMut(&str, &Value)) {
+        for (key, value) in self.data.read().iter() {
+            walk_leaves(key, value, true, &mut f);
+        }
+    }
+
+    /// Returns a copy of the top-level map sorted by key, for deterministic
+    /// iteration.
+    pub fn to_btree(&self) -> BTreeMap<String, Value> {
+        self.data
+            .read()
+            .iter()
+            .map(|(key, value)| (key.clone(), sorted_value(value)))
+            .collect()
+    }
+
+    /// Returns the whole config as one `Value` with the keys of every object,
+    /// at any depth, in sorted order. Keys are sorted explicitly, so this holds
+    /// even if another crate in the build enables serde_json's `preserve_order`.
+    pub fn to_sorted_value(&self) -> Value {
+        Value::Object(self.to_btree().into_iter().collect())
+    }
+
+    /// Returns the scalar leaves whose dotted path matches `pattern`, sorted by
+    /// path. A `*` segment matches exactly one segment and `**` any number,
+    /// including none, so `tts.*.voice` finds each TTS plugin's voice and
+    /// `**.lang` every `lang` setting.
+    pub fn get_glob(&self, pattern: &str) -> Vec<(String, Value)> {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        let mut matches = Vec::new();
+        self.for_each_leaf(|path, value| {
+            let segments: Vec<&str> = path.split('.').collect();
+            if glob_matches(&pattern, &segments) {
+                matches.push((path.to_string(), value.clone()));
+            }
+        });
+        matches.sort_by(|a, b| a.0.cmp(&b.0));
+        matches
+    }
+
+    /// Replaces every scalar leaf with the value `f` returns for it.
+    pub fn map_leaves(&self, mut f: impl FnMut(&str, &Value) -> Value) -> Result<(), ConfigError> {
+        self.check_mutable()?;
+        let mut data = self.write_data();
+        let mut mapped = data.clone();
+        for (key, value) in mapped.iter_mut() {
+            map_leaves_value(key, value, &mut f);
+        }
+        let edit = self.check_edit(&data, &diff(&data, &mapped))?;
+        *data = mapped;
+        self.record(&data, edit);
+        Ok(())
+    }
+
+    /// Rewrites path separators in string values at the given dotted `keys`
+    /// (and any leaves beneath them) to the current platform's, e.g.
+    /// `C:\data\cache` becomes `C:/data/cache` on Unix. Other values are left
+    /// alone since there is no telling a path from any other string;
+    /// [`PATH_KEYS`] lists the known path settings.
+    pub fn normalize_paths(&self, keys: &[&str]) -> Result<(), ConfigError> {
+        let foreign = if std::path::MAIN_SEPARATOR == '/' {
+            '\\'
+        } else {
+            '/'
+        };
+        self.map_leaves(|path, value| match value {
+            Value::String(s) if is_under_any(path, keys) => {
+                Value::String(s.replace(foreign, std::path::MAIN_SEPARATOR_STR))
+            }
+            other => other.clone(),
+        })
+    }
+
+    /// Pins the config as read-only: `set`, `merge`, `remove` and `clear` fail
+    /// with [`ConfigError::Frozen`] from now on while reads keep working.
+    /// `allow_reload` decides whether it may still be reloaded from disk.
+    pub fn freeze(&self, allow_reload: bool) {
+        *self.frozen.write() = if allow_reload {
+            Frozen::AllowReload
+        } else {
+            Frozen::Yes
+        };
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        *self.frozen.read() != Frozen::No
+    }
+
+    fn check_mutable(&self) -> Result<(), ConfigError> {
+        if self.is_frozen() {
+            Err(ConfigError::Frozen)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes the value at a dotted path, such as a device id, immutable at
+    /// runtime: any `set`, `merge`, `remove` or other edit that would change
+    /// it, including replacing or removing an ancestor, fails with
+    /// [`ConfigError::KeyLocked`] and leaves the data untouched. Writes that
+    /// leave the key as it is still succeed. Reloading from disk isn't
+    /// affected.
+    pub fn lock_key(&self, key: &str) {
+        let mut locked = self.locked_keys.write();
+        if !locked.iter().any(|k| k == key) {
+            locked.push(key.to_string());
+        }
+    }
+
+    // Fails if any of `changes` alters a locked key
+    fn check_unlocked(&self, changes: &[ConfigChange]) -> Result<(), ConfigError> {
+        let locked = self.locked_keys.read();
+        for key in locked.iter() {
+            if changes.iter().any(|change| change_alters(change, key)) {
+                return Err(ConfigError::KeyLocked(key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // Checks `changes` against the locked keys, then snapshots the top-level
+    // entries they touch for the history, if it's kept
+    fn check_edit(
+        &self,
+        data: &ConfigDict,
+        changes: &[ConfigChange],
+    ) -> Result<Option<Edit>, ConfigError> {
+        self.check_unlocked(changes)?;
+        if self.history.is_none() || changes.is_empty() {
+            return Ok(None);
+        }
+        let mut keys: Vec<&str> = changes
+            .iter()
+            .map(|change| change.path().split('.').next().unwrap_or_default())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(Some(
+            keys.into_iter()
+                .map(|key| (key.to_string(), data.get(key).cloned(), None))
+                .collect(),
+        ))
+    }
+
+    // Completes an edit snapshotted by `check_edit` with the new values and
+    // pushes it onto the history, dropping the oldest beyond its depth
+    fn record(&self, data: &ConfigDict, edit: Option<Edit>) {
+        let (Some(history), Some(mut edit)) = (&self.history, edit) else {
+            return;
+        };
+        for (key, _, after) in edit.iter_mut() {
+            *after = data.get(key.as_str()).cloned();
+        }
+        let mut history = history.write();
+        history.undo.push_back(edit);
+        while history.undo.len() > history.depth {
+            history.undo.pop_front();
+        }
+        history.redo.clear();
+    }
+
+    /// Reverts the most recent edit recorded by the history enabled with
+    /// [`LocalConfBuilder::history`], returning whether there was one. Every
+    /// write counts as one edit, whether it's a `set`, `remove`, `merge` or
+    /// `clear`; loading from disk isn't recorded. Subscribers see the change.
+    pub fn undo(&self) -> Result<bool, ConfigError> {
+        self.step_history(true)
+    }
+
+    /// Re-applies the last edit reverted with [`LocalConf::undo`], returning
+    /// whether there was one. Any new edit clears what can be redone.
+    pub fn redo(&self) -> Result<bool, ConfigError> {
+        self.step_history(false)
+    }
+
+    fn step_history(&self, undo: bool) -> Result<bool, ConfigError> {
+        self.check_mutable()?;
+        let Some(history) = &self.history else {
+            return Ok(false);
+        };
+        let mut changes = Vec::new();
+        {
+            let mut data = self.write_data();
+            let mut history = history.write();
+            let edit = if undo {
+                history.undo.back()
+            } else {
+                history.redo.last()
+            };
+            let Some(edit) = edit else {
+                return Ok(false);
+            };
+            for (key, before, after) in edit {
+                let (from, to) = if undo {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                diff_value(key.clone(), from.as_ref(), to.as_ref(), &mut changes);
+            }
+            self.check_unlocked(&changes)?;
+            let edit = if undo {
+                history.undo.pop_back()
+            } else {
+                history.redo.pop()
+            }
+            .expect("edit was just found");
+            for (key, before, after) in &edit {
+                match if undo { before } else { after } {
+                    Some(value) => data.insert(key.clone(), value.clone()),
+                    None => data.remove(key),
+                };
+            }
+            if undo {
+                history.redo.push(edit);
+            } else {
+                history.undo.push_back(edit);
+            }
+        }
+        self.notify(&changes);
+        Ok(true)
+    }
+
+    /// Returns the modification time of the backing file as of the last load,
+    /// or `None` for in-memory configs and files that haven't been loaded.
+    pub fn last_loaded(&self) -> Option<SystemTime> {
+        *self.last_loaded.read()
+    }
+
+    /// Returns the current modification time of the backing file.
+    pub fn file_modified(&self) -> Option<SystemTime> {
+        self.path.as_ref()?.metadata().ok()?.modified().ok()
+    }
+
+    /// Returns a copy of the value at a dotted path such as `listener.sample_rate`.
+    /// Numeric segments index into arrays, e.g. `servers.0.host`.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.cache.get(&self.data, key)
+    }
+
+    /// Returns the value at a dotted path if it has the `expected` type, or an
+    /// error naming both the expected and the actual type.
+    pub fn get_typed(&self, key: &str, expected: JsonType) -> Result<Value, TypeMismatch> {
+        check_type(key, self.get(key), expected)
+    }
+
+    /// Deserializes the value at a dotted path into `T`, or returns `None` if
+    /// it's missing or doesn't fit.
+    pub fn get_section<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        serde_json::from_value(self.get(key)?).ok()
+    }
+
+    /// Like [`LocalConf::get_section`], but falls back to `T::default()`,
+    /// logging a warning if the section is present but doesn't fit `T`.
+    pub fn get_section_or_default<T: DeserializeOwned + Default>(&self, key: &str) -> T {
+        let Some(value) = self.get(key) else {
+            return T::default();
+        };
+        serde_json::from_value(value).unwrap_or_else(|e| {
+            warn(&format!("Invalid section {:?}, using defaults: {}", key, e));
+            T::default()
+        })
+    }
+
+    /// Returns the array at a dotted path with each element deserialized into
+    /// `T`, or `None` if the value isn't an array or any element doesn't fit.
+    pub fn get_array<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>> {
+        serde_json::from_value(self.get(key)?).ok()
+    }
+
+    /// Returns the value at a dotted path deserialized into `T`, or `default`
+    /// when the key is missing or holds something that doesn't fit, logging
+    /// the mismatch at debug level.
+    pub fn get_or_typed<T: DeserializeOwned>(&self, key: &str, default: T) -> T {
+        deserialize_or(key, self.get(key), default)
+    }
+
+    /// Returns a size setting in bytes.
+    ///
+    /// Integers are taken as a byte count. Strings may carry a unit suffix,
+    /// matched case-insensitively and with optional whitespace:
+    /// - decimal (SI) units are powers of 1000: `B`, `KB`, `MB`, `GB`, `TB`
+    /// - binary (IEC) units are powers of 1024: `KiB`, `MiB`, `GiB`, `TiB`
+    ///
+    /// So `"16MB"` is 16,000,000 bytes while `"16MiB"` is 16,777,216 bytes.
+    /// Returns `None` if the key is missing, the value can't be parsed, or
+    /// the result overflows a `u64`.
+    pub fn get_size_bytes(&self, key: &str) -> Option<u64> {
+        match self.get(key)? {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => parse_size_bytes(&s),
+            _ => None,
+        }
+    }
+
+    /// Returns an integer setting as an `i64`. A number that doesn't fit, such
+    /// as one above `i64::MAX` or with a fraction, is logged and gives `None`
+    /// rather than being truncated.
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        integer_as(key, self.get(key), Number::as_i64, "i64")
+    }
+
+    /// Returns an integer setting as a `u64`; see [`LocalConf::get_i64`].
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        integer_as(key, self.get(key), Number::as_u64, "u64")
+    }
+
+    /// Returns an integer setting as an `i128`. JSON numbers are read as 64-bit
+    /// integers, so this only widens the range to cover both `i64` and `u64`.
+    pub fn get_i128(&self, key: &str) -> Option<i128> {
+        integer_as(key, self.get(key), Number::as_i128, "i128")
+    }
+
+    /// Returns an integer setting as a `u128`; see [`LocalConf::get_i128`].
+    pub fn get_u128(&self, key: &str) -> Option<u128> {
+        integer_as(key, self.get(key), Number::as_u128, "u128")
+    }
+}
+
+/// Serialization format of config text without a file name to go by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// JSON, with `//`, `#` and `/* */` comments allowed.
+    Json,
+    Yaml,
+}
+
+/// Builds a [`LocalConf`] with load failures returned rather than logged or
+/// panicking.
+pub struct LocalConfBuilder {
+    path: Option<PathBuf>,
+    require_file: bool,
+    non_finite: NonFinitePolicy,
+    hash_reload: bool,
+    header: Option<String>,
+    pretty: bool,
+    fallbacks: Vec<PathBuf>,
+    history_depth: usize,
+}
+
+impl LocalConfBuilder {
+    /// Fail with [`ConfigError::MissingLocation`] instead of starting empty
+    /// when there's no path or no file at it. Off by default.
+    pub fn require_file(mut self, require_file: bool) -> Self {
+        self.require_file = require_file;
+        self
+    }
+
+    /// How YAML `.nan` and `.inf` values, which JSON can't represent, are
+    /// handled on load. Defaults to [`NonFinitePolicy::Null`].
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// When the file's mtime moves, compare a hash of its bytes with the last
+    /// load and skip re-parsing if they match, so a `touch`, an rsync or an
+    /// editor saving unchanged text costs a read rather than a parse. Off by
+    /// default.
+    pub fn reload_by_hash(mut self, enabled: bool) -> Self {
+        self.hash_reload = enabled;
+        self
+    }
+
+    /// Text written as `#` comment lines at the top of the file on every
+    /// [`LocalConf::store`], e.g. `Managed by OVOS - edit with care`. The
+    /// commented-JSON and YAML loaders both skip it. None by default.
+    pub fn store_header(mut self, header: impl Into<String>) -> Self {
+        self.header = Some(header.into());
+        self
+    }
+
+    /// Whether [`LocalConf::store`] indents JSON for editing by hand or writes
+    /// it on one line, which is smaller and faster on constrained flash. YAML
+    /// is unaffected. Pretty by default.
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Locations, in order, that [`LocalConf::store`] falls back to when the
+    /// backing file can't be written, e.g. on an immutable image.
+    /// [`store_fallback_locations`](crate::locations::store_fallback_locations)
+    /// gives the usual chain. None by default.
+    pub fn store_fallbacks(mut self, fallbacks: Vec<PathBuf>) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+
+    /// Keeps the last `depth` edits for [`LocalConf::undo`] and
+    /// [`LocalConf::redo`]. Off (0) by default.
+    pub fn history(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    pub fn build(self) -> Result<LocalConf, ConfigError> {
+        if self.require_file {
+            let path = self.path.as_deref().ok_or(ConfigError::MissingLocation)?;
+            if file_metadata(path)?.is_none() {
+                return Err(ConfigError::MissingLocation);
+            }
+        }
+        let mut conf = LocalConf::unloaded(self.path);
+        conf.non_finite = self.non_finite;
+        conf.hash_reload = self.hash_reload;
+        conf.header = self.header;
+        conf.pretty = self.pretty;
+        conf.fallbacks = self.fallbacks;
+        if self.history_depth > 0 {
+            conf.history = Some(Arc::new(RwLock::new(History {
+                depth: self.history_depth,
+                undo: Default::default(),
+                redo: Vec::new(),
+            })));
+        }
+        conf.load(None, false, FileRefPolicy::Warn, false)?;
+        Ok(conf)
+    }
+}
+
+#[cfg(feature = "archive")]
+impl Configuration {
+    /// Loads a provisioning bundle: a zip or tar archive of config fragments.
+    ///
+    /// Every `*.conf`, `*.json`, `*.yaml` and `*.yml` member is parsed and
+    /// deep-merged in member name order, later names winning, and the result
+    /// becomes the only layer, as [`ConfigLayer::Distribution`]. Profiles and
+    /// env overrides apply as usual, but the files on disk aren't read and the
+    /// bundle can't be written to. Fails with
+    /// [`ConfigError::UnsafeArchiveMember`] if any member name is absolute or
+    /// contains `..`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "archive")]
+    /// # {
+    /// use std::io::Write;
+    /// use ovos_config::config::{ConfigError, Configuration};
+    /// use zip::write::SimpleFileOptions;
+    ///
+    /// let write_bundle = |path: &std::path::Path, members: &[(&str, &str)]| {
+    ///     let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    ///     for (name, contents) in members {
+    ///         zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+    ///         zip.write_all(contents.as_bytes()).unwrap();
+    ///     }
+    ///     zip.finish().unwrap();
+    /// };
+    /// let dir = std::env::temp_dir();
+    ///
+    /// let bundle = dir.join("load-bundle-example.zip");
+    /// write_bundle(&bundle, &[
+    ///     ("20-lang.yaml", "lang: de-de\n"),
+    ///     ("10-base.json", r#"{"lang": "en-gb", "listener": {"sample_rate": 8000}}"#),
+    /// ]);
+    /// let conf = Configuration::load_bundle(&bundle).unwrap();
+    /// assert_eq!(conf.get("lang"), Some("de-de".into()));
+    /// assert_eq!(conf.get("listener.sample_rate"), Some(8000.into()));
+    ///
+    /// let malicious = dir.join("load-bundle-malicious.zip");
+    /// write_bundle(&malicious, &[("../../etc/mycroft.json", "{}")]);
+    /// assert!(matches!(
+    ///     Configuration::load_bundle(&malicious),
+    ///     Err(ConfigError::UnsafeArchiveMember { .. })
+    /// ));
+    /// # std::fs::remove_file(bundle).unwrap();
+    /// # std::fs::remove_file(malicious).unwrap();
+    /// # }
+    /// ```
+    pub fn load_bundle(path: &Path) -> Result<Configuration, ConfigError> {
+        let mut members = read_archive(path)?;
+        members.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut merged = ConfigDict::new();
+        for (name, bytes) in members {
+            let source = path.join(&name);
+            let fragment = match Path::new(&name).extension().and_then(|e| e.to_str()) {
+                Some("yaml" | "yml") => parse_yaml(
+                    &decode_utf8(&source, bytes)?,
+                    &source,
+                    NonFinitePolicy::Null,
+                )?,
+                Some("conf" | "json") => {
+                    serde_json::from_str(&uncomment_json(&decode_utf8(&source, bytes)?))
+                        .map_err(|e| ConfigError::Parse(format!("{:?}: {}", source, e)))?
+                }
+                _ => continue,
+            };
+            merge_dict(&mut merged, &fragment);
+        }
+        Self::from_dict(merged)
+    }
+}
+
+/// Makes the `GetConfig` call for [`Configuration::load_from_dbus_with`], so
+/// the D-Bus connection can be swapped out, e.g. for the system bus or a test
+/// double.
+#[cfg(feature = "dbus")]
+pub trait DbusTransport {
+    /// Calls `GetConfig` on `interface` at `object_path` of `bus_name`,
+    /// returning the JSON string it replies with.
+    fn get_config(
+        &self,
+        bus_name: &str,
+        object_path: &str,
+        interface: &str,
+    ) -> Result<String, ConfigError>;
+}
+
+/// The session bus, which desktop services expose their config on.
+#[cfg(feature = "dbus")]
+pub struct SessionBus;
+
+#[cfg(feature = "dbus")]
+impl DbusTransport for SessionBus {
+    fn get_config(
+        &self,
+        bus_name: &str,
+        object_path: &str,
+        interface: &str,
+    ) -> Result<String, ConfigError> {
+        let dbus_error = |e: zbus::Error| ConfigError::Dbus(e.to_string());
+        let connection = zbus::blocking::Connection::session().map_err(dbus_error)?;
+        let reply = connection
+            .call_method(
+                Some(bus_name),
+                object_path,
+                Some(interface),
+                "GetConfig",
+                &(),
+            )
+            .map_err(dbus_error)?;
+        reply.body().deserialize::<String>().map_err(dbus_error)
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl Configuration {
+    /// Loads the config a desktop service exposes on the session bus: calls
+    /// its `GetConfig` method, which takes no arguments and returns the config
+    /// as a JSON string. The result becomes the only layer, as with
+    /// [`Configuration::load_bundle`]. Connection and call failures are
+    /// [`ConfigError::Dbus`]; a reply that isn't a JSON object is
+    /// [`ConfigError::Parse`].
+    pub fn load_from_dbus(
+        bus_name: &str,
+        object_path: &str,
+        interface: &str,
+    ) -> Result<Configuration, ConfigError> {
+        Self::load_from_dbus_with(&SessionBus, bus_name, object_path, interface)
+    }
+
+    /// Like [`Configuration::load_from_dbus`], over `transport`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "dbus")]
+    /// # {
+    /// use ovos_config::config::{ConfigError, Configuration, DbusTransport};
+    ///
+    /// struct Mock(Result<&'static str, &'static str>);
+    ///
+    /// impl DbusTransport for Mock {
+    ///     fn get_config(&self, _: &str, _: &str, _: &str) -> Result<String, ConfigError> {
+    ///         self.0
+    ///             .map(str::to_string)
+    ///             .map_err(|e| ConfigError::Dbus(e.to_string()))
+    ///     }
+    /// }
+    ///
+    /// let (bus, path, iface) = ("org.OpenVoiceOS.Config", "/config", "org.OpenVoiceOS.Config");
+    /// let mock = Mock(Ok(r#"{"lang": "pt-pt", "listener": {"sample_rate": 16000}}"#));
+    /// let conf = Configuration::load_from_dbus_with(&mock, bus, path, iface).unwrap();
+    /// assert_eq!(conf.get("lang"), Some("pt-pt".into()));
+    /// assert_eq!(conf.get("listener.sample_rate"), Some(16000.into()));
+    ///
+    /// let unreachable = Mock(Err("org.freedesktop.DBus.Error.ServiceUnknown"));
+    /// assert!(matches!(
+    ///     Configuration::load_from_dbus_with(&unreachable, bus, path, iface),
+    ///     Err(ConfigError::Dbus(_))
+    /// ));
+    /// # }
+    /// ```
+    pub fn load_from_dbus_with(
+        transport: &impl DbusTransport,
+        bus_name: &str,
+        object_path: &str,
+        interface: &str,
+    ) -> Result<Configuration, ConfigError> {
+        let json = transport.get_config(bus_name, object_path, interface)?;
+        Self::from_dict(dbus_reply_to_dict(bus_name, &json)?)
+    }
+}
+
+// Helper function to parse the JSON string a `GetConfig` call on `bus_name`
+// replied with
+#[cfg(feature = "dbus")]
+fn dbus_reply_to_dict(bus_name: &str, json: &str) -> Result<ConfigDict, ConfigError> {
+    serde_json::from_str(json)
+        .map_err(|e| ConfigError::Parse(format!("GetConfig reply from {}: {}", bus_name, e)))
+}
+
+// Helper function to read the name and contents of every regular file in a
+// zip or tar archive, rejecting names that escape the archive
+#[cfg(feature = "archive")]
+fn read_archive(path: &Path) -> Result<Vec<(String, Vec<u8>)>, ConfigError> {
+    use std::io::Cursor;
+
+    let bytes = std::fs::read(path).map_err(|e| read_error(path, e))?;
+    let archive_error = |e: &dyn fmt::Display| ConfigError::Parse(format!("{:?}: {}", path, e));
+    let check = |name: &str| {
+        let escapes = name.contains('\\')
+            || Path::new(name).components().any(|c| {
+                !matches!(
+                    c,
+                    std::path::Component::Normal(_) | std::path::Component::CurDir
+                )
+            });
+        if escapes {
+            Err(ConfigError::UnsafeArchiveMember {
+                archive: path.to_path_buf(),
+                member: name.to_string(),
+            })
+        } else {
+            Ok(())
+        }
+    };
+    let mut members = Vec::new();
+    if bytes.starts_with(b"PK") {
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| archive_error(&e))?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(|e| archive_error(&e))?;
+            let name = file.name().to_string();
+            check(&name)?;
+            if file.is_file() {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents)?;
+                members.push((name, contents));
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(Cursor::new(bytes));
+        for entry in tar.entries().map_err(|e| archive_error(&e))? {
+            let mut entry = entry.map_err(|e| archive_error(&e))?;
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            check(&name)?;
+            if entry.header().entry_type().is_file() {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+                members.push((name, contents));
+            }
+        }
+    }
+    Ok(members)
+}
+
+/// Magic bytes starting an encrypted config file, followed by a 12-byte nonce
+/// and the ChaCha20-Poly1305 ciphertext of the JSON document.
+#[cfg(feature = "crypto")]
+const ENCRYPTED_MAGIC: &[u8] = b"OVOSENC1";
+
+#[cfg(feature = "crypto")]
+impl LocalConf {
+    /// Decrypts a file written by [`LocalConf::store_encrypted`] and merges it
+    /// into this config. `key` must be 32 bytes.
+    pub fn load_encrypted(&self, path: &Path, key: &[u8]) -> Result<(), ConfigError> {
+        use chacha20poly1305::aead::{Aead, KeyInit};
+        use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+        if *self.frozen.read() == Frozen::Yes {
+            return Err(ConfigError::Frozen);
+        }
+        let cipher = ChaCha20Poly1305::new_from_slice(key).map_err(|_| ConfigError::InvalidKey)?;
+        let bytes = std::fs::read(path)?;
+        let body = bytes
+            .strip_prefix(ENCRYPTED_MAGIC)
+            .filter(|body| body.len() >= 12)
+            .ok_or_else(|| ConfigError::Parse(format!("{:?} is not an encrypted config", path)))?;
+        let (nonce, ciphertext) = body.split_at(12);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ConfigError::AuthenticationFailed)?;
+        let config: ConfigDict =
+            serde_json::from_slice(&plaintext).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let mut data = self.write_data();
+        for (key, value) in config {
+            data.insert(key, value);
+        }
+        debug(&format!("Encrypted configuration {:?} loaded", path));
+        Ok(())
+    }
+
+    /// Encrypts this config as JSON and writes it to `path`. `key` must be 32 bytes.
+    pub fn store_encrypted(&self, path: &Path, key: &[u8]) -> Result<(), ConfigError> {
+        use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+        use chacha20poly1305::ChaCha20Poly1305;
+
+        let cipher = ChaCha20Poly1305::new_from_slice(key).map_err(|_| ConfigError::InvalidKey)?;
+        let plaintext = serde_json::to_vec(&*self.data.read())
+            .map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(ENCRYPTED_MAGIC)?;
+        file.write_all(&nonce)?;
+        file.write_all(&ciphertext)?;
+        Ok(())
+    }
+}
+
+/// What to do when a `{"$from_file": "/run/secrets/key"}` reference points at
+/// a file that can't be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileRefPolicy {
+    /// Fail with [`ConfigError::FileRef`].
+    Error,
+    /// Log a warning and leave the directive in place.
+    Warn,
+}
+
+/// What to do with NaN and infinite floats in YAML sources. JSON has no
+/// representation for them, so they can't be kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Log a warning naming the path and store `null` instead.
+    Null,
+    /// Fail with [`ConfigError::NonFinite`].
+    Error,
+}
+
+/// Converts string leaves to numbers or booleans where the leaf at the same
+/// path in `reference` has that type, e.g. `"16000"` becomes `16000` when the
+/// reference holds an integer. Strings that don't parse are left untouched.
+/// Numbers and booleans in slots where the reference holds a string become
+/// strings.
+///
+/// [`Configuration`] applies this with [`EMBEDDED_DEFAULT_CONFIG`] as the
+/// reference, so string-typed overrides (env vars, quoted YAML) match the
+/// types the defaults declare.
+pub fn coerce_types(config: &mut ConfigDict, reference: &ConfigDict) {
+    for (key, value) in config.iter_mut() {
+        if let Some(reference) = reference.get(key) {
+            coerce_value(value, reference);
+        }
+    }
+}
+
+/// Key names, matched case-insensitively as substrings, whose values are masked
+/// by [`redact_sensitive`].
+pub const SENSITIVE_KEYS: &[&str] = &["api_key", "apikey", "password", "passwd", "secret", "token"];
+
+/// Replaces the values of sensitive keys (see [`SENSITIVE_KEYS`]) at any depth
+/// with `"***"`.
+pub fn redact_sensitive(config: &mut ConfigDict) {
+    for (key, value) in config.iter_mut() {
+        redact_value(key, value);
+    }
+}
+
+/// Top-level key naming one file, or an array of files, to merge beneath the
+/// rest of a config file; see [`load_with_includes`].
+pub const INCLUDE_KEY: &str = "$include";
+
+/// Loads a JSON or YAML config file, resolving [`INCLUDE_KEY`] first.
+///
+/// Included paths are relative to the including file. They are loaded in the
+/// order listed, each with its own includes resolved depth-first, and
+/// deep-merged; the including file's own keys are merged on top. A file may be
+/// included more than once along different branches, but a file reaching
+/// itself fails with [`ConfigError::IncludeCycle`].
+pub fn load_with_includes(path: &Path) -> Result<ConfigDict, ConfigError> {
+    load_including(path, &mut Vec::new())
+}
+
+/// Loads every `*.conf`, `*.json`, `*.yaml` and `*.yml` fragment in a drop-in
+/// directory such as `mycroft.conf.d`, in file name order.
+///
+/// Each fragment's includes are resolved with [`load_with_includes`] before
+/// any fragments are merged, so the result only depends on the file names:
+/// a fragment included by another sits beneath it, and the fragments are then
+/// deep-merged with later names winning. Cycles spanning several fragments
+/// fail with [`ConfigError::IncludeCycle`]. A missing directory is empty.
+pub fn load_dropin_dir(dir: &Path) -> Result<ConfigDict, ConfigError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ConfigDict::new()),
+        Err(e) => return Err(read_error(dir, e)),
+    };
+    let mut fragments: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("conf" | "json" | "yaml" | "yml")
+                )
+        })
+        .collect();
+    fragments.sort();
+    let mut merged = ConfigDict::new();
+    for fragment in fragments {
+        merge_dict(&mut merged, &load_with_includes(&fragment)?);
+    }
+    Ok(merged)
+}
+
+// Helper function to load `path` with its includes, `stack` holding the files
+// currently being resolved
+fn load_including(path: &Path, stack: &mut Vec<PathBuf>) -> Result<ConfigDict, ConfigError> {
+    let canonical = path.canonicalize().map_err(|e| read_error(path, e))?;
+    if let Some(start) = stack.iter().position(|p| *p == canonical) {
+        let mut chain = stack[start..].to_vec();
+        chain.push(canonical);
+        return Err(ConfigError::IncludeCycle { chain });
+    }
+    let mut config = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => parse_yaml(&read_text(path)?, path, NonFinitePolicy::Null)?,
+        _ => load_commented_json(path)?,
+    };
+    let includes = match config.remove(INCLUDE_KEY) {
+        None => Vec::new(),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(include) => Ok(include),
+                other => Err(include_error(path, &other)),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(other) => return Err(include_error(path, &other)),
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    stack.push(canonical);
+    let mut merged = ConfigDict::new();
+    for include in includes {
+        merge_dict(&mut merged, &load_including(&base.join(include), stack)?);
+    }
+    stack.pop();
+    merge_dict(&mut merged, &config);
+    Ok(merged)
+}
+
+// Helper function to report an `$include` that isn't a path or list of paths
+fn include_error(path: &Path, value: &Value) -> ConfigError {
+    ConfigError::Parse(format!(
+        "{:?}: {} must be a path or a list of paths, found {}",
+        path, INCLUDE_KEY, value
+    ))
+}
+
+/// Replaces every `{"$from_file": "<path>"}` object with the trimmed contents
+/// of that file, so secrets can live outside the config.
+pub fn resolve_file_refs(
+    config: &mut ConfigDict,
+    policy: FileRefPolicy,
+) -> Result<(), ConfigError> {
+    for value in config.values_mut() {
+        resolve_file_refs_value(value, policy)?;
+    }
+    Ok(())
+}
+
+/// Replaces `{xdg_config_home}`, `{xdg_data_home}`, `{xdg_cache_home}` and
+/// `{xdg_state_home}` in string values with the current XDG directories.
+/// Other `{...}` text is left as is.
+pub fn resolve_xdg_tokens(config: &mut ConfigDict) {
+    let tokens = [
+        ("{xdg_config_home}", xdg::xdg_config_home()),
+        ("{xdg_data_home}", xdg::xdg_data_home()),
+        ("{xdg_cache_home}", xdg::xdg_cache_home()),
+        ("{xdg_state_home}", xdg::xdg_state_home()),
+    ];
+    for (key, value) in config.iter_mut() {
+        map_leaves_value(key, value, &mut |_, leaf| match leaf {
+            Value::String(s) if s.contains("{xdg_") => {
+                let mut s = s.clone();
+                for (token, path) in &tokens {
+                    s = s.replace(token, &path.to_string_lossy());
+                }
+                Value::String(s)
+            }
+            other => other.clone(),
+        });
+    }
+}
+
+// Helper function to replace each directory in `tokens` with its token
+// where it starts a path in `s`, longest directory first so nested ones win
+fn tokenize_paths(s: &str, tokens: &[(&str, PathBuf)]) -> String {
+    let mut dirs: Vec<(&str, String)> = tokens
+        .iter()
+        .map(|(token, dir)| {
+            (
+                *token,
+                dir.to_string_lossy().trim_end_matches('/').to_string(),
+            )
+        })
+        .collect();
+    dirs.sort_by_key(|(_, dir)| std::cmp::Reverse(dir.len()));
+    let mut out = s.to_string();
+    for (token, dir) in &dirs {
+        let dir = dir.as_str();
+        if dir.is_empty() {
+            continue;
+        }
+        let mut replaced = String::new();
+        let mut rest = out.as_str();
+        while let Some(i) = rest.find(dir) {
+            let after = &rest[i + dir.len()..];
+            replaced.push_str(&rest[..i]);
+            if after.is_empty() || after.starts_with('/') {
+                replaced.push_str(token);
+            } else {
+                replaced.push_str(dir);
+            }
+            rest = after;
+        }
+        replaced.push_str(rest);
+        out = replaced;
+    }
+    out
+}
+
+/// Dotted paths of the stock settings holding filesystem paths, for
+/// [`LocalConf::normalize_paths`].
+pub const PATH_KEYS: &[&str] = &[
+    "data_dir",
+    "cache_path",
+    "intents.padatious.intent_cache",
+    "listener.save_path",
+];
+
+// Helper function to rebuild a value with every object's keys inserted in
+// sorted order
+fn sorted_value(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<&String, Value> =
+                map.iter().map(|(k, v)| (k, sorted_value(v))).collect();
+            Value::Object(sorted.into_iter().map(|(k, v)| (k.clone(), v)).collect())
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sorted_value).collect()),
+        other => other.clone(),
+    }
+}
+
+// Helper function to match dotted path segments against glob segments
+fn glob_matches(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            glob_matches(rest, path) || (!path.is_empty() && glob_matches(pattern, &path[1..]))
+        }
+        (Some((&p, rest)), Some((&segment, tail))) => {
+            (p == "*" || p == segment) && glob_matches(rest, tail)
+        }
+        _ => false,
+    }
+}
+
+// Helper function to tell whether a dotted path is one of `keys` or below one
+fn is_under_any(path: &str, keys: &[&str]) -> bool {
+    keys.iter().any(|key| {
+        path.strip_prefix(key)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// The kind of a JSON value, as named in [`TypeMismatch`] messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Bool,
+            Value::Number(_) => JsonType::Number,
+            Value::String(_) => JsonType::String,
+            Value::Array(_) => JsonType::Array,
+            Value::Object(_) => JsonType::Object,
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            JsonType::Null => "null",
+            JsonType::Bool => "bool",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A setting known from the bundled defaults, see [`schema_metadata`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldMeta {
+    /// Dotted path such as `listener.sample_rate`.
+    pub path: String,
+    /// The type of the default value; `Null` when the default doesn't say.
+    pub kind: JsonType,
+    pub default: Value,
+}
+
+/// Lists every setting in [`EMBEDDED_DEFAULT_CONFIG`] with its inferred type
+/// and default value, sorted by path, so a UI can build editors without a
+/// separate schema. Arrays are single fields rather than one per element.
+pub fn schema_metadata() -> Vec<FieldMeta> {
+    let mut fields = Vec::new();
+    for (key, value) in EMBEDDED_DEFAULT_CONFIG.iter() {
+        walk_leaves(key, value, false, &mut |path, leaf| {
+            fields.push(FieldMeta {
+                path: path.to_string(),
+                kind: JsonType::of(leaf),
+                default: leaf.clone(),
+            })
+        });
+    }
+    fields.sort_by(|a, b| a.path.cmp(&b.path));
+    fields
+}
+
+/// Generates a draft-07 JSON Schema from [`EMBEDDED_DEFAULT_CONFIG`] for
+/// editors and external tooling. Each known key gets the type of its default,
+/// and leaves carry the default itself; keys defaulting to `null` accept
+/// anything. Unknown keys are allowed, since skills add their own sections.
+pub fn generate_json_schema() -> Value {
+    let defaults: serde_json::Map<String, Value> = EMBEDDED_DEFAULT_CONFIG
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let mut schema = schema_for(&Value::Object(defaults));
+    schema["$schema"] = Value::from("http://json-schema.org/draft-07/schema#");
+    schema["title"] = Value::from("mycroft.conf");
+    schema
+}
+
+// Helper function to build the schema for one default value
+fn schema_for(value: &Value) -> Value {
+    let kind = match value {
+        Value::Null => return Value::Object(serde_json::Map::new()),
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(map) => {
+            let properties: serde_json::Map<String, Value> = map
+                .iter()
+                .map(|(key, child)| (key.clone(), schema_for(child)))
+                .collect();
+            return serde_json::json!({"type": "object", "properties": properties});
+        }
+    };
+    serde_json::json!({"type": kind, "default": value})
+}
+
+/// Returned by `get_typed` when a key is missing or holds another type.
+/// `actual` is `None` for a missing key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeMismatch {
+    pub key: String,
+    pub expected: JsonType,
+    pub actual: Option<JsonType>,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                "{}: expected {}, got {}",
+                self.key, self.expected, actual
+            ),
+            None => write!(
+                f,
+                "{}: expected {}, but it is not set",
+                self.key, self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TypeMismatch {}
+
+/// A problem found by [`Configuration::validate_paths`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathIssue {
+    /// The setting names a file or directory that doesn't exist. `path` is
+    /// the value after `~` and env var expansion.
+    Missing { key: String, path: PathBuf },
+    /// The setting holds something other than a string.
+    NotAString { key: String, actual: JsonType },
+}
+
+impl PathIssue {
+    pub fn key(&self) -> &str {
+        match self {
+            PathIssue::Missing { key, .. } | PathIssue::NotAString { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for PathIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathIssue::Missing { key, path } => {
+                write!(f, "{}: {} does not exist", key, path.display())
+            }
+            PathIssue::NotAString { key, actual } => {
+                write!(f, "{}: expected a path string, got {}", key, actual)
+            }
+        }
+    }
+}
+
+// Helper function to expand a leading `~` and `$VAR` / `${VAR}` references
+// in a path setting. Unset variables are left as written.
+fn expand_path(s: &str) -> PathBuf {
+    let s = match s.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", xdg::home_dir().display(), rest)
+        }
+        _ => s.to_string(),
+    };
+    let mut out = String::new();
+    let mut rest = s.as_str();
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let (name, len) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        match env::var(name) {
+            Ok(value) if !name.is_empty() => out.push_str(&value),
+            _ => {
+                out.push('$');
+                out.push_str(&rest[..len]);
+            }
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    PathBuf::from(out)
+}
+
+// Helper function checking a looked-up value against the expected type
+fn check_type(key: &str, value: Option<Value>, expected: JsonType) -> Result<Value, TypeMismatch> {
+    match value {
+        Some(value) if JsonType::of(&value) == expected => Ok(value),
+        other => Err(TypeMismatch {
+            key: key.to_string(),
+            expected,
+            actual: other.as_ref().map(JsonType::of),
+        }),
+    }
+}
+
+/// A single leaf-level difference between two configurations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigChange {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+/// An existing value overwritten by [`LocalConf::merge_reporting`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub path: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl ConfigChange {
+    pub fn path(&self) -> &str {
+        match self {
+            ConfigChange::Added { path, .. }
+            | ConfigChange::Removed { path, .. }
+            | ConfigChange::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// Computes the leaf-level changes needed to turn `old` into `new`.
+///
+/// Nested objects are compared key by key; any other differing value is
+/// reported as a single change at its dotted path.
+pub fn diff(old: &ConfigDict, new: &ConfigDict) -> Vec<ConfigChange> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let mut changes = Vec::new();
+    for key in keys {
+        diff_value(key.clone(), old.get(key), new.get(key), &mut changes);
+    }
+    changes
+}
+
+/// Deep-merges `delta` into `base`: nested objects are merged key by key,
+/// any other value in `delta` replaces the one in `base`.
+pub fn merge_dict(base: &mut ConfigDict, delta: &ConfigDict) {
+    for (key, value) in delta {
+        match base.get_mut(key) {
+            Some(existing) => merge_value(existing, value),
+            None => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Schema keyword naming the [`MergeStrategy`] for a property, e.g.
+/// `{"properties": {"hotwords": {"x-merge": "append"}}}`.
+pub const MERGE_ANNOTATION: &str = "x-merge";
+
+/// How [`merge_dict_with_schema`] combines a key set by both sides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Merge objects key by key, replace anything else. The default.
+    Deep,
+    /// Concatenate arrays, replace anything else.
+    Append,
+    /// Replace the value wholesale, objects included.
+    Replace,
+    /// Fail with [`ConfigError::MergeConflict`] if the values differ.
+    Error,
+}
+
+impl fmt::Display for MergeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeStrategy::Deep => write!(f, "deep"),
+            MergeStrategy::Append => write!(f, "append"),
+            MergeStrategy::Replace => write!(f, "replace"),
+            MergeStrategy::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::str::FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deep" => Ok(MergeStrategy::Deep),
+            "append" => Ok(MergeStrategy::Append),
+            "replace" => Ok(MergeStrategy::Replace),
+            "error" => Ok(MergeStrategy::Error),
+            other => Err(format!("unknown merge strategy {:?}", other)),
+        }
+    }
+}
+
+/// Like [`merge_dict`], but each key is merged with the strategy its
+/// property in the JSON `schema` names under [`MERGE_ANNOTATION`], found by
+/// following `properties` along the dotted path. Keys without an annotation,
+/// or with an unknown one, are deep-merged.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::config::{merge_dict_with_schema, ConfigDict};
+/// use serde_json::json;
+///
+/// let schema = json!({"properties": {"listener": {"properties": {
+///     "wake_words": {"x-merge": "append"},
+///     "channels": {"x-merge": "replace"},
+/// }}}});
+/// let mut base: ConfigDict =
+///     serde_json::from_value(json!({"listener": {"wake_words": ["hey"], "channels": [0, 1]}}))
+///         .unwrap();
+/// let delta: ConfigDict =
+///     serde_json::from_value(json!({"listener": {"wake_words": ["ok"], "channels": [2]}}))
+///         .unwrap();
+/// merge_dict_with_schema(&mut base, &delta, &schema).unwrap();
+/// assert_eq!(base["listener"]["wake_words"], json!(["hey", "ok"]));
+/// assert_eq!(base["listener"]["channels"], json!([2]));
+/// ```
+pub fn merge_dict_with_schema(
+    base: &mut ConfigDict,
+    delta: &ConfigDict,
+    schema: &Value,
+) -> Result<(), ConfigError> {
+    merge_dict_with(base, delta, Some(schema), TypeConflictPolicy::PreferHigher)
+}
+
+/// What [`merge_dict_with_policy`] does when a value in `delta` has another
+/// JSON type than the one it meets in `base`, e.g. `"16000"` over `16000`.
+/// `null` on either side is never a conflict.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TypeConflictPolicy {
+    /// Take the incoming value as is, like [`merge_dict`]. The default.
+    #[default]
+    PreferHigher,
+    /// Convert the incoming value to the existing type where it reads as one:
+    /// numeric and `true`/`false` strings, numbers and booleans as strings,
+    /// booleans as 0/1 and 0/1 as booleans. Values that don't convert are
+    /// taken as is, with a warning.
+    Coerce,
+    /// Fail with [`ConfigError::TypeConflict`].
+    Error,
+}
+
+/// Like [`merge_dict`], with `policy` deciding what happens when a value
+/// changes type.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::config::{merge_dict_with_policy, ConfigDict, TypeConflictPolicy};
+/// use serde_json::json;
+///
+/// let base: ConfigDict = serde_json::from_value(json!({"sample_rate": 16000})).unwrap();
+/// let delta: ConfigDict = serde_json::from_value(json!({"sample_rate": "48000"})).unwrap();
+///
+/// let mut merged = base.clone();
+/// merge_dict_with_policy(&mut merged, &delta, TypeConflictPolicy::PreferHigher).unwrap();
+/// assert_eq!(merged["sample_rate"], json!("48000"));
+///
+/// let mut merged = base.clone();
+/// merge_dict_with_policy(&mut merged, &delta, TypeConflictPolicy::Coerce).unwrap();
+/// assert_eq!(merged["sample_rate"], json!(48000));
+///
+/// let mut merged = base.clone();
+/// assert!(merge_dict_with_policy(&mut merged, &delta, TypeConflictPolicy::Error).is_err());
+/// ```
+pub fn merge_dict_with_policy(
+    base: &mut ConfigDict,
+    delta: &ConfigDict,
+    policy: TypeConflictPolicy,
+) -> Result<(), ConfigError> {
+    merge_dict_with(base, delta, None, policy)
+}
+
+// Helper function to merge with the strategies annotated in an optional
+// schema and a type conflict policy
+fn merge_dict_with(
+    base: &mut ConfigDict,
+    delta: &ConfigDict,
+    schema: Option<&Value>,
+    policy: TypeConflictPolicy,
+) -> Result<(), ConfigError> {
+    for (key, value) in delta {
+        let child = schema
+            .and_then(|s| s.get("properties"))
+            .and_then(|p| p.get(key));
+        match base.get_mut(key) {
+            Some(existing) => merge_value_with(existing, value, child, policy, key)?,
+            None => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compares two configs deeply, ignoring key order and treating numbers as
+/// equal when they have the same numeric value (so `1` equals `1.0`).
+pub fn configs_equal(a: &ConfigDict, b: &ConfigDict) -> bool {
+    configs_equal_with_tolerance(a, b, 0.0)
+}
+
+/// Like [`configs_equal`], but numeric leaves may differ by up to `epsilon`.
+pub fn configs_equal_with_tolerance(a: &ConfigDict, b: &ConfigDict, epsilon: f64) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(key, value)| {
+            b.get(key)
+                .is_some_and(|other| values_equal(value, other, epsilon))
+        })
+}
+
+/// Formats changes as a unified-style summary, one line per change, grouped
+/// as additions, then removals, then changes, each group sorted by path:
+///
+/// ```text
+/// + listener.sample_rate = 16000
+/// - tts.engine = "mimic"
+/// ~ foo.bar: 1 -> 2
+/// ```
+pub fn format_diff(changes: &[ConfigChange]) -> String {
+    let group = |change: &ConfigChange| match change {
+        ConfigChange::Added { .. } => 0,
+        ConfigChange::Removed { .. } => 1,
+        ConfigChange::Changed { .. } => 2,
+    };
+    let mut sorted: Vec<&ConfigChange> = changes.iter().collect();
+    sorted.sort_by(|a, b| (group(a), a.path()).cmp(&(group(b), b.path())));
+    let mut out = String::new();
+    for change in sorted {
+        let line = match change {
+            ConfigChange::Added { path, value } => format!("+ {} = {}", path, value),
+            ConfigChange::Removed { path, value } => format!("- {} = {}", path, value),
+            ConfigChange::Changed { path, old, new } => format!("~ {}: {} -> {}", path, old, new),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+pub struct ReadOnlyConfig {
+    inner: LocalConf,
+    allow_overwrite: bool,
+}
+
+impl ReadOnlyConfig {
+    pub fn new(path: PathBuf, allow_overwrite: bool) -> Self {
+        Self {
+            inner: LocalConf::new(Some(path)),
+            allow_overwrite,
+        }
+    }
+
+    pub fn reload(&mut self) {
+        let old = self.allow_overwrite;
+        self.allow_overwrite = true;
+        self.inner.reload();
+        self.allow_overwrite = old;
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) -> Result<(), &'static str> {
+        if !self.allow_overwrite {
+            Err("This configuration is read-only and cannot be modified at runtime")
+        } else {
+            self.inner.write_data().insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+
+    pub fn merge(&mut self, conf: &ConfigDict) -> Result<(), &'static str> {
+        if !self.allow_overwrite {
+            Err("This configuration is read-only and cannot be modified at runtime")
+        } else {
+            self.inner
+                .merge(conf)
+                .map_err(|_| "This configuration is frozen and cannot be modified")
+        }
+    }
+
+    pub fn store(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        if !self.allow_overwrite {
+            Err(ConfigError::ReadOnly)
+        } else {
+            self.inner.store(path)
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.inner.get(key)
+    }
+
+    pub fn get_typed(&self, key: &str, expected: JsonType) -> Result<Value, TypeMismatch> {
+        self.inner.get_typed(key, expected)
+    }
+
+    pub fn get_array<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>> {
+        self.inner.get_array(key)
+    }
+
+    pub fn get_or_typed<T: DeserializeOwned>(&self, key: &str, default: T) -> T {
+        self.inner.get_or_typed(key, default)
+    }
+
+    pub fn get_size_bytes(&self, key: &str) -> Option<u64> {
+        self.inner.get_size_bytes(key)
+    }
+
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.inner.get_i64(key)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.inner.get_u64(key)
+    }
+
+    pub fn get_i128(&self, key: &str) -> Option<i128> {
+        self.inner.get_i128(key)
+    }
+
+    pub fn get_u128(&self, key: &str) -> Option<u128> {
+        self.inner.get_u128(key)
+    }
+}
+
+pub struct MycroftDefaultConfig(ReadOnlyConfig);
+
+impl MycroftDefaultConfig {
+    /// Loads `/etc/mycroft/mycroft.conf` over the bundled defaults for
+    /// [`current_platform`].
+    pub fn new() -> Self {
+        let conf = ReadOnlyConfig::new(DEFAULT_CONFIG.to_path_buf(), false);
+        let mut data = default_config_for(&current_platform());
+        merge_dict(&mut data, &conf.inner.data.read());
+        *conf.inner.write_data() = data;
+        Self(conf)
+    }
+
+    pub fn set_root_config_path(&mut self, root_config: PathBuf) {
+        self.0.inner.path = Some(root_config);
+        self.0.reload();
+    }
+}
+
+impl Default for MycroftDefaultConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MycroftDefaultConfig {
+    type Target = ReadOnlyConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct OvosDistributionConfig(ReadOnlyConfig);
+
+impl OvosDistributionConfig {
+    pub fn new(allow_overwrite: bool) -> Self {
+        Self(ReadOnlyConfig::new(
+            DISTRIBUTION_CONFIG.to_path_buf(),
+            allow_overwrite,
+        ))
+    }
+}
+
+impl Deref for OvosDistributionConfig {
+    type Target = ReadOnlyConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct MycroftSystemConfig(ReadOnlyConfig);
+
+impl MycroftSystemConfig {
+    pub fn new(allow_overwrite: bool) -> Self {
+        Self(ReadOnlyConfig::new(
+            SYSTEM_CONFIG.to_path_buf(),
+            allow_overwrite,
+        ))
+    }
+}
+
+impl Deref for MycroftSystemConfig {
+    type Target = ReadOnlyConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct MycroftUserConfig(LocalConf);
+
+impl MycroftUserConfig {
+    pub fn new() -> Self {
+        Self(LocalConf::new(Some(USER_CONFIG.to_path_buf())))
+    }
+}
+
+impl Default for MycroftUserConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MycroftUserConfig {
+    type Target = LocalConf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub type MycroftXDGConfig = MycroftUserConfig;
+
+/// The standard layer precedence, lowest first.
+pub const DEFAULT_LAYER_ORDER: &[ConfigLayer] = &[
+    ConfigLayer::Default,
+    ConfigLayer::Distribution,
+    ConfigLayer::System,
+    ConfigLayer::Xdg,
+    ConfigLayer::User,
+];
+
+/// The layers stacked by [`Configuration`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConfigLayer {
+    Default,
+    Distribution,
+    System,
+    Xdg,
+    User,
+}
+
+/// The merged view of every config layer, from lowest to highest precedence:
+/// default, distribution, system, XDG config dirs and finally the user config.
+/// [`Configuration::with_layer_order`] stacks them in another order, and
+/// [`Configuration::with_merge_schema`] changes how individual keys merge.
+///
+/// A layer may define named overrides under a top-level `profiles` key. The
+/// profile named by the `OVOS_PROFILE` env var, if any, is deep-merged over the
+/// stacked result and the `profiles` key itself is dropped.
+///
+/// Env vars opted into with [`Configuration::include_env`] are nested next. A
+/// base64 document in [`ENV_BLOB_B64`] is applied after that, then lines from
+/// [`Configuration::load_env_file`] and finally env vars of the form
+/// `OVOS_CONFIG__listener__sample_rate=16000`, see [`from_env_vars`].
+#[derive(Clone)]
+pub struct Configuration {
+    layers: Arc<RwLock<Vec<(ConfigLayer, LocalConf)>>>,
+    order: Vec<ConfigLayer>,
+    profile: Option<String>,
+    env_namespace: Option<(String, String)>,
+    data: Arc<RwLock<ConfigDict>>,
+    cache: ReadCache,
+    subscribers: Arc<RwLock<Vec<Subscriber>>>,
+    env_file: Arc<RwLock<Vec<(String, String)>>>,
+    merge_schema: Option<Arc<Value>>,
+    type_conflicts: TypeConflictPolicy,
+}
+
+impl Configuration {
+    pub fn new() -> Self {
+        let profile = env::var("OVOS_PROFILE").ok().filter(|p| !p.is_empty());
+        Self::load_ordered(DEFAULT_LAYER_ORDER.to_vec(), profile)
+    }
+
+    /// Loads the standard layer files stacked in `order`, lowest precedence
+    /// first, e.g. with [`ConfigLayer::System`] last so a kiosk's system config
+    /// beats the user's. Layers left out of `order` aren't loaded and repeats
+    /// are ignored. Failures are logged as in [`Configuration::new`].
+    pub fn with_layer_order(order: Vec<ConfigLayer>) -> Self {
+        let profile = env::var("OVOS_PROFILE").ok().filter(|p| !p.is_empty());
+        let mut unique = Vec::new();
+        for layer in order {
+            if !unique.contains(&layer) {
+                unique.push(layer);
+            }
+        }
+        Self::load_ordered(unique, profile)
+    }
+
+    fn load_ordered(order: Vec<ConfigLayer>, profile: Option<String>) -> Self {
+        let conf = Self::from_layers(order, profile);
+        if let Err(errors) = conf.try_load_all() {
+            for e in errors {
+                error(&e.to_string());
+            }
+        }
+        conf
+    }
+
+    /// Exposes every env var starting with `prefix` as a string under
+    /// [`ENV_NAMESPACE_KEY`], e.g. `OVOS_LANG` at `env.OVOS_LANG`, and
+    /// re-stacks. See [`env_namespace`].
+    pub fn include_env(self, prefix: &str) -> Self {
+        self.include_env_under(prefix, ENV_NAMESPACE_KEY)
+    }
+
+    /// Like [`Configuration::include_env`], nesting the vars under `key`.
+    pub fn include_env_under(mut self, prefix: &str, key: &str) -> Self {
+        self.env_namespace = Some((prefix.to_string(), key.to_string()));
+        if let Err(e) = self.load_all() {
+            error(&e.to_string());
+        }
+        self
+    }
+
+    /// Merges the layers with the per-key strategies annotated in `schema`,
+    /// see [`merge_dict_with_schema`], and re-stacks. Profiles and env
+    /// overrides are still deep-merged on top.
+    pub fn with_merge_schema(mut self, schema: Value) -> Self {
+        self.merge_schema = Some(Arc::new(schema));
+        if let Err(e) = self.load_all() {
+            error(&e.to_string());
+        }
+        self
+    }
+
+    /// Merges the layers with `policy` deciding what happens when a layer
+    /// changes a value's type, see [`merge_dict_with_policy`], and re-stacks.
+    /// Profiles and env overrides are still merged on top as is.
+    pub fn with_type_conflict_policy(mut self, policy: TypeConflictPolicy) -> Self {
+        self.type_conflicts = policy;
+        if let Err(e) = self.load_all() {
+            error(&e.to_string());
+        }
+        self
+    }
+
+    /// Returns the layer kinds in stacking order, lowest precedence first.
+    pub fn layer_order(&self) -> &[ConfigLayer] {
+        &self.order
+    }
+
+    /// Loads the standard layers with the named profile applied.
+    pub fn load_profile(name: &str) -> Result<Self, ConfigError> {
+        let conf = Self::from_layers(DEFAULT_LAYER_ORDER.to_vec(), Some(name.to_string()));
+        for e in conf.read_layers() {
+            error(&e.to_string());
+        }
+        conf.load_all()?;
+        Ok(conf)
+    }
+
+    fn from_layers(order: Vec<ConfigLayer>, profile: Option<String>) -> Self {
+        let layers = layer_paths(&order)
+            .into_iter()
+            .map(|(layer, path)| (layer, LocalConf::unloaded(Some(path))))
+            .collect();
+        Self::with_layers(order, layers, profile)
+    }
+
+    // Stacks `data` as the only layer, as the distribution config
+    #[cfg(any(feature = "archive", feature = "dbus"))]
+    fn from_dict(data: ConfigDict) -> Result<Self, ConfigError> {
+        let profile = env::var("OVOS_PROFILE").ok().filter(|p| !p.is_empty());
+        let conf = Self::with_layers(
+            vec![ConfigLayer::Distribution],
+            vec![(ConfigLayer::Distribution, LocalConf::in_memory(data))],
+            profile,
+        );
+        conf.load_all()?;
+        Ok(conf)
+    }
+
+    fn with_layers(
+        order: Vec<ConfigLayer>,
+        layers: Vec<(ConfigLayer, LocalConf)>,
+        profile: Option<String>,
+    ) -> Self {
+        Self {
+            layers: Arc::new(RwLock::new(layers)),
+            order,
+            profile,
+            env_namespace: None,
+            data: Arc::new(RwLock::new(HashMap::new())),
+            cache: ReadCache::default(),
+            subscribers: Arc::new(RwLock::new(Vec::new())),
+            env_file: Arc::new(RwLock::new(Vec::new())),
+            merge_schema: None,
+            type_conflicts: TypeConflictPolicy::PreferHigher,
+        }
+    }
+
+    /// Reads any layer that changed on disk and re-stacks. A layer that fails
+    /// to load doesn't stop the others; every failure is returned, including
+    /// [`ConfigError::NotAFile`] for a layer path that is a directory or other
+    /// non-file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "xdg-override")]
+    /// # {
+    /// use std::fs;
+    /// use ovos_config::config::{ConfigError, Configuration};
+    /// use ovos_config::xdg::{self, XdgDirs};
+    ///
+    /// let config_home = std::env::temp_dir().join("try-load-all-not-a-file-example");
+    /// let user_conf = config_home.join("mycroft/mycroft.conf");
+    /// fs::create_dir_all(&user_conf).unwrap();
+    ///
+    /// let dirs = XdgDirs {
+    ///     config_home: Some(config_home.clone()),
+    ///     ..Default::default()
+    /// };
+    /// let errors = xdg::with_xdg_dirs(dirs, || Configuration::new().try_load_all()).unwrap_err();
+    /// assert!(errors.iter().any(|e| matches!(
+    ///     e,
+    ///     ConfigError::NotAFile { path, kind: "directory" } if *path == user_conf
+    /// )));
+    /// # fs::remove_dir_all(config_home).unwrap();
+    /// # }
+    /// ```
+    pub fn try_load_all(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = self.read_layers();
+        if let Err(e) = self.load_all() {
+            errors.push(e);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Reads every layer through `tokio::fs` and re-stacks, stopping at the
+    /// first failure.
+    #[cfg(feature = "async")]
+    pub async fn load_all_async(&self) -> Result<(), ConfigError> {
+        let layers: Vec<LocalConf> = self.layers.read().iter().map(|(_, c)| c.clone()).collect();
+        for conf in layers {
+            conf.load_async(None, FileRefPolicy::Warn).await?;
+        }
+        self.load_all()
+    }
+
+    // Reads every layer that changed on disk, collecting the failures
+    fn read_layers(&self) -> Vec<ConfigError> {
+        self.layers
+            .read()
+            .iter()
+            .filter_map(|(_, conf)| conf.reload_checked().err())
+            .collect()
+    }
+
+    /// Re-stacks the layers into the merged configuration.
+    pub fn load_all(&self) -> Result<(), ConfigError> {
+        self.restack().map(|_| ())
+    }
+
+    // Re-stacks the layers, returning whether the merged config changed
+    fn restack(&self) -> Result<bool, ConfigError> {
+        let merged = self.stack(None)?;
+        let (changed, changes) = {
+            let mut data = self.data.write();
+            let changed = *data != merged;
+            let changes = if !changed || self.subscribers.read().is_empty() {
+                Vec::new()
+            } else {
+                diff(&data, &merged)
+            };
+            *data = merged;
+            self.cache.clear();
+            (changed, changes)
+        };
+        if !changes.is_empty() {
+            for subscriber in self.subscribers.read().iter() {
+                subscriber(&changes);
+            }
+        }
+        self.warn_deprecated();
+        Ok(changed)
+    }
+
+    /// Reads `OVOS_CONFIG__a__b=value` lines from a dotenv-style file and
+    /// applies them like the matching env vars, which still take precedence,
+    /// then re-stacks. Blank lines, `#` comments and an `export ` prefix are
+    /// allowed; values may be single-quoted (literal) or double-quoted (with
+    /// `\n`, `\"` and `\\` escapes). Names without the prefix are ignored.
+    pub fn load_env_file(&self, path: &Path) -> Result<(), ConfigError> {
+        let contents = read_text(path)?;
+        let vars = parse_env_file(&contents)
+            .map_err(|(line, msg)| ConfigError::Parse(format!("{:?}:{}: {}", path, line, msg)))?;
+        self.env_file.write().extend(vars);
+        self.load_all()
+    }
+
+    /// Registers a callback run with the changes in the merged config each
+    /// time the layers are re-stacked, e.g. by [`Configuration::reload`].
+    /// Callbacks run on the reloading thread after the lock is released.
+    pub fn subscribe<F>(&self, callback: F)
+    where
+        F: Fn(&[ConfigChange]) + Send + Sync + 'static,
+    {
+        self.subscribers.write().push(Arc::new(callback));
+    }
+
+    /// Deserializes the section at the dotted `key` once and keeps the result
+    /// current: whenever a re-stack changes anything at or below `key`, the
+    /// section is deserialized again and swapped into the view, so readers
+    /// holding the view never pay for deserialization. A missing section
+    /// deserializes from `null`. If a later version no longer fits `T`, the
+    /// error is logged and the view keeps the last good value. Dropping the
+    /// last clone of the view unsubscribes it.
+    pub fn typed_view<T>(&self, key: &str) -> Result<TypedView<T>, ConfigError>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let initial = deserialize_section(&self.data.read(), key)?;
+        let current = Arc::new(RwLock::new(Arc::new(initial)));
+        let slot = Arc::downgrade(&current);
+        let data = Arc::downgrade(&self.data);
+        let key = key.to_string();
+        let callback: Subscriber = Arc::new(move |changes: &[ConfigChange]| {
+            let (Some(slot), Some(data)) = (slot.upgrade(), data.upgrade()) else {
+                return;
+            };
+            let relevant = changes.iter().any(|change| {
+                is_under_any(change.path(), &[&key]) || is_under_any(&key, &[change.path()])
+            });
+            if !relevant {
+                return;
+            }
+            let refreshed = deserialize_section::<T>(&data.read(), &key);
+            match refreshed {
+                Ok(value) => *slot.write() = Arc::new(value),
+                Err(e) => error(&format!("Keeping the previous {}: {}", key, e)),
+            }
+        });
+        self.subscribers.write().push(callback.clone());
+        Ok(TypedView {
+            current,
+            _subscription: Arc::new(ViewSubscription {
+                subscribers: Arc::downgrade(&self.subscribers),
+                callback,
+            }),
+        })
+    }
+
+    /// Logs a warning naming the replacement for each deprecated key set by
+    /// any layer above the bundled defaults, see
+    /// [`Configuration::deprecated_keys`].
+    pub fn warn_deprecated(&self) {
+        for (old, new) in self.deprecated_keys() {
+            warn(&format!("key '{}' is deprecated, use '{}'", old, new));
+        }
+    }
+
+    /// Returns each deprecated key set by any layer above the bundled
+    /// defaults, which keep some deprecated sections for migration, with the
+    /// key that replaces it.
+    pub fn deprecated_keys(&self) -> Vec<(String, String)> {
+        let layers = self.layers.read();
+        DEPRECATIONS
+            .read()
+            .iter()
+            .filter(|(old, _)| {
+                layers
+                    .iter()
+                    .filter(|(layer, _)| *layer != ConfigLayer::Default)
+                    .any(|(_, conf)| get_path(&conf.data.read(), old).is_some())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the merged config as if `overrides` were an extra user layer on
+    /// top, without touching the live configuration or any file.
+    pub fn preview_with(&self, overrides: ConfigDict) -> ConfigDict {
+        self.stack(Some(&overrides)).unwrap_or_else(|e| {
+            error(&e.to_string());
+            let mut merged = self.data.read().clone();
+            merge_dict(&mut merged, &overrides);
+            merged
+        })
+    }
+
+    // Merges the layers (plus an optional extra top layer), then applies the
+    // selected profile, env overrides and type coercion
+    fn stack(&self, top: Option<&ConfigDict>) -> Result<ConfigDict, ConfigError> {
+        let mut merged = ConfigDict::new();
+        for (_, conf) in self.layers.read().iter() {
+            self.merge_layer(&mut merged, &conf.data.read())?;
+        }
+        if let Some(top) = top {
+            self.merge_layer(&mut merged, top)?;
+        }
+        let profiles = merged.remove("profiles");
+        if let Some(name) = &self.profile {
+            match profiles.as_ref().and_then(|p| p.get(name)) {
+                Some(Value::Object(profile)) => {
+                    let profile: ConfigDict = profile.clone().into_iter().collect();
+                    merge_dict(&mut merged, &profile);
+                }
+                _ => return Err(ConfigError::MissingProfile(name.clone())),
+            }
+        }
+        if let Some((prefix, key)) = &self.env_namespace {
+            let vars = env::vars_os()
+                .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+            merge_dict(&mut merged, &env_namespace(prefix, key, vars));
+        }
+        if let Ok(encoded) = env::var(ENV_BLOB_B64) {
+            match from_env_blob_b64(&encoded) {
+                Ok(blob) => merge_dict(&mut merged, &blob),
+                Err(e) => warn(&format!("Ignoring {}: {}", ENV_BLOB_B64, e)),
+            }
+        }
+        let env_file = self.env_file.read().clone();
+        merge_dict(&mut merged, &from_env_vars(ENV_PREFIX, env_file));
+        let vars = env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+        merge_dict(&mut merged, &from_env_vars(ENV_PREFIX, vars));
+        coerce_types(&mut merged, &EMBEDDED_DEFAULT_CONFIG);
+        Ok(merged)
+    }
+
+    // Merges one layer, honouring the merge schema and type conflict policy
+    fn merge_layer(&self, merged: &mut ConfigDict, layer: &ConfigDict) -> Result<(), ConfigError> {
+        match (&self.merge_schema, self.type_conflicts) {
+            (None, TypeConflictPolicy::PreferHigher) => {
+                merge_dict(merged, layer);
+                Ok(())
+            }
+            (schema, policy) => merge_dict_with(merged, layer, schema.as_deref(), policy),
+        }
+    }
+
+    /// Reloads any layer whose file changed on disk and re-stacks, see
+    /// [`Configuration::reload_checked`].
+    ///
+    /// Layer paths are re-resolved from the environment first, after
+    /// [`xdg::refresh_env_cache`], so a process that changes `XDG_CONFIG_HOME`
+    /// or `XDG_CONFIG_DIRS` mid-run picks up the relocated files. This is only
+    /// relevant when those vars change at runtime. While a layer that would
+    /// move has unsaved edits, the paths are left as they are and a warning
+    /// is logged; [`Configuration::store`] the edits and reload again.
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        self.reload_checked().map(|_| ())
+    }
+
+    /// Re-reads only the layers whose files were modified since they were
+    /// last loaded and returns whether the merged config changed. When no
+    /// layer was re-read and no layer path moved, the merge is skipped
+    /// entirely, so env var changes aren't picked up; use
+    /// [`Configuration::load_all`] to re-stack unconditionally. A layer that
+    /// fails to load is logged and keeps its previous data.
+    pub fn reload_checked(&self) -> Result<bool, ConfigError> {
+        xdg::refresh_env_cache();
+        let mut reread = self.refresh_layer_paths();
+        for (layer, conf) in self.layers.read().iter() {
+            match conf.reload_checked() {
+                Ok(true) => {
+                    debug(&format!("Re-read {:?} layer", layer));
+                    reread = true;
+                }
+                Ok(false) => {}
+                Err(e) => error(&e.to_string()),
+            }
+        }
+        if !reread {
+            debug("No configuration layer changed, skipping re-stack");
+            return Ok(false);
+        }
+        self.restack()
+    }
+
+    /// Polls the `mycroft.conf` in every XDG config dir, plus the user config,
+    /// every `interval` on a background thread, including files that don't
+    /// exist yet. Any poll that sees files changed or created triggers one
+    /// [`Configuration::reload`], so a burst of edits across several dirs costs
+    /// a single re-stack; [`Configuration::subscribe`] callbacks see the
+    /// result. Polling stops when the returned watcher is dropped.
+    pub fn watch(&self, interval: Duration) -> Result<ConfigWatcher, ConfigError> {
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let conf = self.clone();
+        // Snapshot before returning so changes made right after are caught
+        let mut seen = watched_files(&conf);
+        let thread = std::thread::Builder::new()
+            .name("ovos-config-watch".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let current = watched_files(&conf);
+                    if current != seen {
+                        seen = current;
+                        debug("Watched configuration changed, reloading");
+                        if let Err(e) = conf.reload() {
+                            error(&e.to_string());
+                        }
+                    }
+                }
+            })?;
+        Ok(ConfigWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Reloads this configuration whenever the process gets `SIGHUP`, as
+    /// daemons conventionally do, notifying [`Configuration::subscribe`]
+    /// callbacks of the changes, until the returned guard is dropped. The
+    /// signal handler only writes a byte to a pipe; the reload runs on a
+    /// helper thread. Installing several configurations reloads each of them.
+    #[cfg(unix)]
+    pub fn install_sighup_reload(&self) -> Result<SighupReload, ConfigError> {
+        let mut targets = SIGHUP_TARGETS.lock();
+        if SIGHUP_PIPE.load(std::sync::atomic::Ordering::Relaxed) < 0 {
+            let read_fd = install_sighup_pipe()?;
+            std::thread::Builder::new()
+                .name("ovos-config-sighup".to_string())
+                .spawn(move || sighup_loop(read_fd))?;
+        }
+        let id = NEXT_SIGHUP_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        targets.push((id, self.clone()));
+        Ok(SighupReload { id })
+    }
+
+    // Rebuilds the layer list if the standard paths moved, keeping the
+    // already-loaded layers whose path is unchanged, and returns whether it did
+    fn refresh_layer_paths(&self) -> bool {
+        let current = layer_paths(&self.order);
+        let mut layers = self.layers.write();
+        // In-memory layers, e.g. from a bundle, have no files to relocate
+        if layers.iter().any(|(_, conf)| conf.path.is_none()) {
+            return false;
+        }
+        let unchanged = layers.len() == current.len()
+            && layers
+                .iter()
+                .zip(&current)
+                .all(|((_, conf), (_, path))| conf.path.as_ref() == Some(path));
+        if unchanged {
+            return false;
+        }
+        let dirty: Vec<&Path> = layers
+            .iter()
+            .filter(|(_, conf)| {
+                !current
+                    .iter()
+                    .any(|(_, path)| conf.path.as_ref() == Some(path))
+            })
+            .filter(|(_, conf)| conf.is_dirty())
+            .filter_map(|(_, conf)| conf.path())
+            .collect();
+        if !dirty.is_empty() {
+            warn(&format!(
+                "Configuration layer paths changed, but {:?} has unsaved edits; store them \
+                 to move to the new paths",
+                dirty
+            ));
+            return false;
+        }
+        debug("Configuration layer paths changed, rebuilding layers");
+        let mut old = std::mem::take(&mut *layers);
+        for (layer, path) in current {
+            let conf = match old.iter().position(|(_, c)| c.path.as_ref() == Some(&path)) {
+                Some(i) => old.swap_remove(i).1,
+                None => LocalConf::unloaded(Some(path)),
+            };
+            layers.push((layer, conf));
+        }
+        true
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.cache.get(&self.data, key)
+    }
+
+    /// Returns the merged value at a dotted path if it has the `expected`
+    /// type; see [`LocalConf::get_typed`].
+    pub fn get_typed(&self, key: &str, expected: JsonType) -> Result<Value, TypeMismatch> {
+        check_type(key, self.get(key), expected)
+    }
+
+    /// Returns the merged array at a dotted path with each element
+    /// deserialized into `T`; see [`LocalConf::get_array`].
+    pub fn get_array<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>> {
+        serde_json::from_value(self.get(key)?).ok()
+    }
+
+    /// Returns the merged value at a dotted path deserialized into `T`, or
+    /// `default`; see [`LocalConf::get_or_typed`].
+    pub fn get_or_typed<T: DeserializeOwned>(&self, key: &str, default: T) -> T {
+        deserialize_or(key, self.get(key), default)
+    }
+
+    /// Sets the value at a dotted path in the user layer and re-stacks. Call
+    /// [`Configuration::store`] to persist it; lower layers are never written.
+    pub fn set(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.set_in(ConfigLayer::User, key, value)
+    }
+
+    /// Like [`Configuration::set`], naming the target layer. Only
+    /// [`ConfigLayer::User`] is writable; the default, distribution, system and
+    /// XDG layers are shared and fail with [`ConfigError::ReadOnly`].
+    pub fn set_in(&self, layer: ConfigLayer, key: &str, value: Value) -> Result<(), ConfigError> {
+        if layer != ConfigLayer::User {
+            return Err(ConfigError::ReadOnly);
+        }
+        self.with_user_layer(|user| user.set(key, value))?;
+        self.load_all()
+    }
+
+    /// Writes the user layer to [`Configuration::active_user_config_path`],
+    /// the only file a `Configuration` ever writes.
+    pub fn store(&self) -> Result<(), ConfigError> {
+        self.with_user_layer(|user| {
+            if let Some(path) = &user.path {
+                ensure_folder_exists(path);
+            }
+            user.store(None)
+        })
+    }
+
+    // Runs `f` on the user layer, failing with ReadOnly when the layer order
+    // leaves it out
+    fn with_user_layer<T>(
+        &self,
+        f: impl FnOnce(&LocalConf) -> Result<T, ConfigError>,
+    ) -> Result<T, ConfigError> {
+        let layers = self.layers.read();
+        let (_, user) = layers
+            .iter()
+            .rev()
+            .find(|(layer, _)| *layer == ConfigLayer::User)
+            .ok_or(ConfigError::ReadOnly)?;
+        f(user)
+    }
+
+    /// Returns the file the writable user layer reads and writes.
+    pub fn active_user_config_path(&self) -> PathBuf {
+        self.layers
+            .read()
+            .iter()
+            .rev()
+            .find(|(layer, _)| *layer == ConfigLayer::User)
+            .and_then(|(_, conf)| conf.path.clone())
+            .unwrap_or_else(|| get_xdg_config_save_path(None).join("mycroft.conf"))
+    }
+
+    /// Returns the files that were actually read, lowest precedence first.
+    pub fn active_layers(&self) -> Vec<PathBuf> {
+        self.layers
+            .read()
+            .iter()
+            .filter(|(_, conf)| conf.last_loaded().is_some())
+            .filter_map(|(_, conf)| conf.path.clone())
+            .collect()
+    }
+
+    /// Returns each layer that was actually read with its file and parsed
+    /// contents, lowest precedence first, for callers that combine the layers
+    /// with their own merge policy. Profiles and env overrides aren't applied.
+    pub fn ordered_dicts(&self) -> Vec<(ConfigLayer, PathBuf, ConfigDict)> {
+        self.layers
+            .read()
+            .iter()
+            .filter(|(_, conf)| conf.last_loaded().is_some())
+            .filter_map(|(layer, conf)| {
+                Some((*layer, conf.path.clone()?, conf.data.read().clone()))
+            })
+            .collect()
+    }
+
+    /// Describes how the merged config came together, for people puzzling
+    /// over precedence: every layer in stacking order with its file, whether
+    /// the file was loaded, and how many of its settings (leaf values, arrays
+    /// counting as one) are in effect rather than overridden by a later layer,
+    /// followed by what's applied on top of the layers.
+    pub fn explain(&self) -> String {
+        let layers = self.layers.read();
+        let merged = self.data.read();
+        let dicts: Vec<ConfigDict> = layers
+            .iter()
+            .map(|(_, conf)| conf.data.read().clone())
+            .collect();
+        // The last layer setting each leaf is the one it comes from
+        let mut winners: HashMap<String, usize> = HashMap::new();
+        for (i, dict) in dicts.iter().enumerate() {
+            for (key, value) in dict {
+                walk_leaves(key, value, false, &mut |path, _| {
+                    winners.insert(path.to_string(), i);
+                });
+            }
+        }
+        let mut report = String::from(
+            "Configuration layers, lowest precedence first. Each layer is \
+             deep-merged over the ones before it, so later layers win:\n",
+        );
+        for (i, ((layer, conf), dict)) in layers.iter().zip(&dicts).enumerate() {
+            let mut total = 0;
+            let mut in_effect = 0;
+            for (key, value) in dict {
+                walk_leaves(key, value, false, &mut |path, leaf| {
+                    total += 1;
+                    if winners.get(path) == Some(&i) && get_path(&merged, path) == Some(leaf) {
+                        in_effect += 1;
+                    }
+                });
+            }
+            let (location, status) = match conf.path.as_deref() {
+                None => ("(in memory)".to_string(), "in memory"),
+                Some(path) if conf.last_loaded().is_some() => (format!("{:?}", path), "loaded"),
+                Some(path) if path.exists() => (format!("{:?}", path), "not loaded"),
+                Some(path) => (format!("{:?}", path), "missing"),
+            };
+            report.push_str(&format!(
+                "  {}. {:?}: {} ({}), {} of {} settings in effect\n",
+                i + 1,
+                layer,
+                location,
+                status,
+                in_effect,
+                total
+            ));
+        }
+        report.push_str("Applied on top, in order:\n");
+        match &self.profile {
+            Some(name) => report.push_str(&format!("  - profile {:?}\n", name)),
+            None => report.push_str("  - no profile (set OVOS_PROFILE to pick one)\n"),
+        }
+        if let Some((prefix, key)) = &self.env_namespace {
+            report.push_str(&format!("  - {}* env vars under {:?}\n", prefix, key));
+        }
+        report.push_str(&format!("  - {} if set\n", ENV_BLOB_B64));
+        if !self.env_file.read().is_empty() {
+            report.push_str("  - variables from the loaded env file\n");
+        }
+        report.push_str(&format!("  - {}__* env vars\n", ENV_PREFIX));
+        report
+    }
+
+    /// Makes sure the user config file exists, seeding it with `{}` on first run,
+    /// and returns its path. Existing files are left untouched.
+    pub fn ensure_user_config() -> Result<PathBuf, ConfigError> {
+        let path = get_xdg_config_save_path(None).join("mycroft.conf");
+        if !path.exists() {
+            ensure_folder_exists(&path);
+            write_atomic(&path, b"{}")?;
+            debug(&format!("Created user configuration {:?}", path));
+        }
+        Ok(path)
+    }
+
+    /// Copies a legacy `~/.mycroft/mycroft.conf` to the XDG user config path so
+    /// it stops shadowing it in [`crate::locations::find_user_config`], and
+    /// returns the new path. A `mycroft.conf.migrated` note pointing there is
+    /// left next to the legacy file, which is kept. Does nothing and returns
+    /// `None` when there's no legacy file or an XDG config already exists.
+    pub fn migrate_legacy_user_config() -> Result<Option<PathBuf>, ConfigError> {
+        let legacy = xdg::home_dir().join(".mycroft/mycroft.conf");
+        let path = get_xdg_config_save_path(None).join("mycroft.conf");
+        if path.exists() || file_metadata(&legacy)?.is_none() {
+            return Ok(None);
+        }
+        let contents = std::fs::read(&legacy).map_err(|e| read_error(&legacy, e))?;
+        ensure_folder_exists(&path);
+        write_atomic(&path, &contents)?;
+        let note = format!("Migrated to {}\n", path.display());
+        if let Err(e) = std::fs::write(legacy.with_extension("conf.migrated"), note) {
+            warn(&format!(
+                "Unable to leave migration note for {:?}: {}",
+                legacy, e
+            ));
+        }
+        debug(&format!(
+            "Migrated user configuration {:?} to {:?}",
+            legacy, path
+        ));
+        Ok(Some(path))
+    }
+
+    /// Applies a `{"type": "configuration.patch", "data": {...}}` bus message by
+    /// deep-merging `data` into the in-memory user layer and re-stacking.
+    /// Messages of any other type are ignored. Fails with
+    /// [`ConfigError::ReadOnly`] if there's no user layer.
+    pub fn apply_bus_patch(&self, message: &Value) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| ConfigError::InvalidMessage(msg.to_string());
+        let message = message
+            .as_object()
+            .ok_or_else(|| invalid("message is not an object"))?;
+        let kind = message
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("message has no 'type'"))?;
+        if kind != "configuration.patch" {
+            debug(&format!("Ignoring {} message", kind));
+            return Ok(());
+        }
+        let data = match message.get("data") {
+            Some(Value::Object(data)) => data.clone().into_iter().collect::<ConfigDict>(),
+            _ => return Err(invalid("'data' is not an object")),
+        };
+        self.with_user_layer(|user| user.merge_deep(&data))?;
+        self.load_all()
+    }
+
+    /// Returns the parts of the user config that differ from the merged lower layers.
+    pub fn overrides(&self) -> ConfigDict {
+        let mut base = ConfigDict::new();
+        let mut user = ConfigDict::new();
+        for (layer, conf) in self.layers.read().iter() {
+            match layer {
+                ConfigLayer::User => merge_dict(&mut user, &conf.data.read()),
+                _ => merge_dict(&mut base, &conf.data.read()),
+            }
+        }
+        overrides_of(&base, &user)
+    }
+
+    /// Returns the dotted leaf paths in the merged config that aren't in
+    /// `known`, sorted, to flag typos and stale settings. A known path also
+    /// covers everything below it, so `"skills"` accepts any skill's settings.
+    /// Arrays are leaves. The paths of [`schema_metadata`] make a good
+    /// starting set.
+    pub fn unknown_keys(&self, known: &[&str]) -> Vec<String> {
+        let is_known = |path: &str| {
+            known.iter().any(|k| {
+                path.strip_prefix(k)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+        };
+        let mut unknown = Vec::new();
+        for (key, value) in self.data.read().iter() {
+            walk_leaves(key, value, false, &mut |path, _| {
+                if !is_known(path) {
+                    unknown.push(path.to_string());
+                }
+            });
+        }
+        unknown.sort();
+        unknown
+    }
+
+    /// Checks that the string values at the dotted `path_keys` name existing
+    /// files or directories, after expanding a leading `~` and `$VAR` /
+    /// `${VAR}` references. Keys that aren't set are skipped; values that
+    /// aren't strings are reported as such rather than as missing.
+    /// [`PATH_KEYS`] lists the path settings of the bundled defaults.
+    pub fn validate_paths(&self, path_keys: &[&str]) -> Vec<PathIssue> {
+        let mut issues = Vec::new();
+        for key in path_keys {
+            match self.get(key) {
+                None => {}
+                Some(Value::String(s)) => {
+                    let path = expand_path(&s);
+                    if !path.exists() {
+                        issues.push(PathIssue::Missing {
+                            key: key.to_string(),
+                            path,
+                        });
+                    }
+                }
+                Some(other) => issues.push(PathIssue::NotAString {
+                    key: key.to_string(),
+                    actual: JsonType::of(&other),
+                }),
+            }
+        }
+        issues
+    }
+
+    /// Lists what the system, XDG and user layers change relative to the
+    /// shipped baseline of the bundled defaults plus the distribution config,
+    /// so vendor customization can be told apart from operator tweaks. Env
+    /// vars and profiles aren't included.
+    pub fn overrides_vs_distribution(&self) -> Vec<ConfigChange> {
+        let mut baseline = ConfigDict::new();
+        let mut customized = ConfigDict::new();
+        for (layer, conf) in self.layers.read().iter() {
+            let data = conf.data.read();
+            if matches!(layer, ConfigLayer::Default | ConfigLayer::Distribution) {
+                merge_dict(&mut baseline, &data);
+            }
+            merge_dict(&mut customized, &data);
+        }
+        diff(&baseline, &customized)
+    }
+
+    /// Renders [`Configuration::overrides`] as YAML for sharing, with sensitive
+    /// values such as `api_key` masked.
+    pub fn export_overrides_yaml(&self) -> String {
+        let mut overrides = self.overrides();
+        redact_sensitive(&mut overrides);
+        let sorted: BTreeMap<String, Value> = overrides.into_iter().collect();
+        serde_yaml::to_string(&sorted).unwrap_or_default()
+    }
+
+    /// Renders the whole merged config as YAML with sorted keys and sensitive
+    /// values masked, whatever format the layers were loaded from.
+    pub fn dump_yaml(&self) -> Result<String, ConfigError> {
+        let mut data = self.data.read().clone();
+        redact_sensitive(&mut data);
+        let sorted: BTreeMap<String, Value> = data.into_iter().collect();
+        serde_yaml::to_string(&sorted)
+            .map_err(|e| ConfigError::Parse(format!("merged config: {}", e)))
+    }
+
+    /// Renders the merged config for pasting into a bug report: YAML as in
+    /// [`Configuration::dump_yaml`], with the XDG base dirs and the home
+    /// directory in string values replaced by `<XDG_CONFIG_HOME>`,
+    /// `<XDG_DATA_HOME>`, `<XDG_CACHE_HOME>`, `<XDG_STATE_HOME>` and `<HOME>`,
+    /// below a comment header listing the layers that contributed settings.
+    pub fn sanitized_report(&self) -> String {
+        let tokens = [
+            ("<XDG_CONFIG_HOME>", xdg::xdg_config_home()),
+            ("<XDG_DATA_HOME>", xdg::xdg_data_home()),
+            ("<XDG_CACHE_HOME>", xdg::xdg_cache_home()),
+            ("<XDG_STATE_HOME>", xdg::xdg_state_home()),
+            ("<HOME>", xdg::home_dir()),
+        ];
+        let mut report = String::from("# Sanitized OVOS configuration\n");
+        report.push_str("# Layers, lowest precedence first:\n");
+        for (layer, conf) in self.layers.read().iter() {
+            if conf.data.read().is_empty() {
+                continue;
+            }
+            let path = conf.path.as_deref().map_or_else(String::new, |p| {
+                tokenize_paths(&p.to_string_lossy(), &tokens)
+            });
+            report.push_str(&format!("#   {:?}: {}\n", layer, path));
+        }
+        if let Some(profile) = &self.profile {
+            report.push_str(&format!("# Profile: {}\n", profile));
+        }
+        let mut data = self.data.read().clone();
+        redact_sensitive(&mut data);
+        for (key, value) in data.iter_mut() {
+            map_leaves_value(key, value, &mut |_, leaf| match leaf {
+                Value::String(s) => Value::String(tokenize_paths(s, &tokens)),
+                other => other.clone(),
+            });
+        }
+        let sorted: BTreeMap<String, Value> = data.into_iter().collect();
+        report.push_str(&serde_yaml::to_string(&sorted).unwrap_or_default());
+        report
+    }
+
+    /// Runs every rule against the merged config, returning all failures.
+    pub fn validate_rules(&self, rules: &[Box<dyn ValidationRule>]) -> Vec<ValidationError> {
+        let data = self.data.read();
+        rules.iter().filter_map(|rule| rule.check(&data)).collect()
+    }
+
+    /// Flattens the merged config into `PREFIX__dotted__path` env var pairs,
+    /// sorted by name. Strings are emitted as-is, other leaves as JSON, so the
+    /// output round-trips through [`from_env_vars`]. Strings that would parse
+    /// as JSON, such as `"16000"`, are emitted JSON-quoted to stay strings.
+    pub fn to_env_vars(&self, prefix: &str) -> Vec<(String, String)> {
+        let data = self.data.read();
+        let mut vars = Vec::new();
+        for (key, value) in data.iter() {
+            walk_leaves(key, value, false, &mut |path, leaf| {
+                let name = format!("{}__{}", prefix, path.replace('.', "__"));
+                let value = match leaf {
+                    Value::String(s) if serde_json::from_str::<Value>(s).is_err() => s.clone(),
+                    other => other.to_string(),
+                };
+                vars.push((name, value));
+            });
+        }
+        vars.sort();
+        vars
+    }
+}
+
+/// Prefix of the env vars applied over the merged [`Configuration`].
+pub const ENV_PREFIX: &str = "OVOS_CONFIG";
+
+/// Builds a config from `PREFIX__dotted__path=value` pairs, ignoring names
+/// without the prefix. Values are parsed as JSON when possible and kept as
+/// strings otherwise.
+pub fn from_env_vars(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> ConfigDict {
+    let prefix = format!("{}__", prefix);
+    let mut config = ConfigDict::new();
+    for (name, raw) in vars {
+        let Some(path) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let value = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+        if let Err(e) = set_path(&mut config, &path.replace("__", "."), value) {
+            warn(&format!("Ignoring {}: {}", name, e));
+        }
+    }
+    config
+}
+
+/// Default key [`Configuration::include_env`] nests env vars under.
+pub const ENV_NAMESPACE_KEY: &str = "env";
+
+/// Builds `{key: {NAME: "value"}}` from the vars whose names start with
+/// `prefix`. Unlike [`from_env_vars`], names are kept whole and values are
+/// always strings.
+pub fn env_namespace(
+    prefix: &str,
+    key: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> ConfigDict {
+    let namespace: serde_json::Map<String, Value> = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, value)| (name, Value::String(value)))
+        .collect();
+    let mut config = ConfigDict::new();
+    config.insert(key.to_string(), Value::Object(namespace));
+    config
+}
+
+// Helper function to parse dotenv lines into name/value pairs, failing with the
+// 1-based line number of a malformed line
+fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, raw)) = line.split_once('=') else {
+            return Err((i + 1, "expected NAME=value".to_string()));
+        };
+        let raw = raw.trim();
+        let value = if let Some(rest) = raw.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = rest.chars();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some(c) => value.push(c),
+                        None => return Err((i + 1, "unterminated quote".to_string())),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err((i + 1, "unterminated quote".to_string())),
+                }
+            }
+            value
+        } else if let Some(rest) = raw.strip_prefix('\'') {
+            match rest.split_once('\'') {
+                Some((value, _)) => value.to_string(),
+                None => return Err((i + 1, "unterminated quote".to_string())),
+            }
+        } else {
+            // An unquoted value ends at a ` #` comment
+            raw.split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_string()
+        };
+        vars.push((name.trim().to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Env var holding a whole base64-encoded JSON or YAML document, applied over
+/// the merged [`Configuration`] just below the `OVOS_CONFIG__` key overrides.
+pub const ENV_BLOB_B64: &str = "OVOS_CONFIG_B64";
+
+/// Decodes a base64 (standard alphabet, padded) JSON or YAML document.
+pub fn from_env_blob_b64(encoded: &str) -> Result<ConfigDict, ConfigError> {
+    let bytes = BASE64_STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| ConfigError::Parse(format!("invalid base64: {}", e)))?;
+    let text = String::from_utf8(bytes)
+        .map_err(|e| ConfigError::Parse(format!("decoded blob is not UTF-8: {}", e)))?;
+    serde_json::from_str(&uncomment_json(&text))
+        .or_else(|_| parse_yaml(&text, Path::new("decoded blob"), NonFinitePolicy::Null))
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Background polling started by [`Configuration::watch`] or
+/// [`LocalConf::poll_reload`]; dropping it stops the thread and waits for it
+/// to exit.
+pub struct ConfigWatcher {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread immediately
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Registration made by [`Configuration::install_sighup_reload`]; dropping it
+/// stops reloading that configuration on `SIGHUP`. The signal handler and its
+/// thread stay installed for the life of the process.
+#[cfg(unix)]
+pub struct SighupReload {
+    id: u64,
+}
+
+#[cfg(unix)]
+impl Drop for SighupReload {
+    fn drop(&mut self) {
+        SIGHUP_TARGETS.lock().retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// A typed snapshot of a config section from [`Configuration::typed_view`],
+/// swapped for a fresh one whenever a reload changes the section. Clones
+/// share the same snapshot.
+pub struct TypedView<T> {
+    current: Arc<RwLock<Arc<T>>>,
+    _subscription: Arc<ViewSubscription>,
+}
+
+// The callback a `TypedView` registered, removed from the configuration's
+// subscribers once the last clone of the view is dropped
+struct ViewSubscription {
+    subscribers: Weak<RwLock<Vec<Subscriber>>>,
+    callback: Subscriber,
+}
+
+impl Drop for ViewSubscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            subscribers
+                .write()
+                .retain(|subscriber| !Arc::ptr_eq(subscriber, &self.callback));
+        }
+    }
+}
+
+impl<T> TypedView<T> {
+    /// Returns the current snapshot. It stays valid, but stale, after the
+    /// view moves on to a newer one.
+    pub fn get(&self) -> Arc<T> {
+        self.current.read().clone()
+    }
+}
+
+impl<T> Clone for TypedView<T> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+            _subscription: self._subscription.clone(),
+        }
+    }
+}
+
+// Helper function to deserialize the section at a dotted path, or `null`
+// when it's missing
+fn deserialize_section<T: DeserializeOwned>(
+    data: &ConfigDict,
+    key: &str,
+) -> Result<T, ConfigError> {
+    let value = get_path(data, key).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(|e| ConfigError::Parse(format!("{}: {}", key, e)))
+}
+
+// Helper function to snapshot the mtime and size of every watched file, or
+// `None` for files that don't exist
+fn watched_files(conf: &Configuration) -> HashMap<PathBuf, Option<(SystemTime, u64)>> {
+    let mut paths = get_xdg_config_locations();
+    paths.push(conf.active_user_config_path());
+    paths
+        .into_iter()
+        .map(|path| {
+            let stamp = std::fs::metadata(&path)
+                .ok()
+                .and_then(|m| Some((m.modified().ok()?, m.len())));
+            (path, stamp)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+lazy_static::lazy_static! {
+    static ref SIGHUP_TARGETS: parking_lot::Mutex<Vec<(u64, Configuration)>> =
+        parking_lot::Mutex::new(Vec::new());
+}
+
+#[cfg(unix)]
+static NEXT_SIGHUP_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// Write end of the SIGHUP self-pipe, read by the signal handler
+#[cfg(unix)]
+static SIGHUP_PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+#[cfg(unix)]
+extern "C" fn on_sighup(_: libc::c_int) {
+    let fd = SIGHUP_PIPE.load(std::sync::atomic::Ordering::Relaxed);
+    // Only async-signal-safe calls here; a full pipe already has a reload pending
+    unsafe {
+        libc::write(fd, [1u8].as_ptr().cast(), 1);
+    }
+}
+
+// Helper function to create the self-pipe and install the handler, returning
+// the read end
+#[cfg(unix)]
+fn install_sighup_pipe() -> Result<libc::c_int, ConfigError> {
+    let mut fds = [0 as libc::c_int; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        // The handler must never block on a full pipe
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+        SIGHUP_PIPE.store(fds[1], std::sync::atomic::Ordering::Relaxed);
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(fds[0])
+}
+
+// Helper function run by the SIGHUP thread, reloading every installed
+// configuration once per wakeup
+#[cfg(unix)]
+fn sighup_loop(read_fd: libc::c_int) {
+    let mut buf = [0u8; 64];
+    loop {
+        let read = unsafe { libc::read(read_fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if read < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            error(&format!("SIGHUP reload thread stopping: {}", e));
+            return;
+        }
+        if read == 0 {
+            return;
+        }
+        debug("SIGHUP received, reloading configuration");
+        let targets: Vec<Configuration> = SIGHUP_TARGETS
+            .lock()
+            .iter()
+            .map(|(_, conf)| conf.clone())
+            .collect();
+        for conf in targets {
+            if let Err(e) = conf.reload() {
+                error(&e.to_string());
+            }
+        }
+    }
+}
+
+// Helper function to list the standard layer files, lowest precedence first
+fn default_layers() -> Vec<(ConfigLayer, PathBuf)> {
+    let user_config = get_xdg_config_save_path(None).join("mycroft.conf");
+    let mut layers = vec![
+        (ConfigLayer::Default, find_default_config()),
+        (ConfigLayer::Distribution, DISTRIBUTION_CONFIG.to_path_buf()),
+        (ConfigLayer::System, SYSTEM_CONFIG.to_path_buf()),
+    ];
+    layers.extend(
+        get_xdg_config_locations()
+            .into_iter()
+            .rev()
+            .filter(|p| *p != user_config)
+            .map(|p| (ConfigLayer::Xdg, p)),
+    );
+    layers.push((ConfigLayer::User, user_config));
+    layers
+}
+
+// Helper function to list the standard layer files stacked in `order`
+fn layer_paths(order: &[ConfigLayer]) -> Vec<(ConfigLayer, PathBuf)> {
+    let layers = default_layers();
+    order
+        .iter()
+        .flat_map(|kind| {
+            layers
+                .iter()
+                .filter(move |(layer, _)| layer == kind)
+                .cloned()
+        })
+        .collect()
+}
+
+// Helper function to read a config file as text, reporting a non-UTF-8 file
+// with its path and the first invalid byte
+pub(crate) fn read_text(path: &Path) -> Result<String, ConfigError> {
+    #[cfg(test)]
+    crate::testing::check_read(path).map_err(|e| read_error(path, e))?;
+    let bytes = std::fs::read(path).map_err(|e| read_error(path, e))?;
+    decode_utf8(path, bytes)
+}
+
+// Helper function to decode file bytes read from `path` as UTF-8
+fn decode_utf8(path: &Path, bytes: Vec<u8>) -> Result<String, ConfigError> {
+    String::from_utf8(bytes).map_err(|e| ConfigError::Encoding {
+        path: path.to_path_buf(),
+        offset: e.utf8_error().valid_up_to(),
+    })
+}
+
+// Helper function to tell permission problems apart from other read failures
+fn read_error(path: &Path, e: std::io::Error) -> ConfigError {
+    match e.kind() {
+        std::io::ErrorKind::PermissionDenied => ConfigError::PermissionDenied(path.to_path_buf()),
+        _ if is_symlink_loop(&e) => ConfigError::SymlinkLoop {
+            path: path.to_path_buf(),
+        },
+        _ => ConfigError::Io(e),
+    }
+}
+
+// Helper function returning the metadata of `path` if it's a regular file.
+// Missing paths are `None`, but a symlink cycle or something other than a
+// file is an error rather than a silently skipped layer.
+fn file_metadata(path: &Path) -> Result<Option<std::fs::Metadata>, ConfigError> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => check_is_file(path, metadata).map(Some),
+        Err(e) if is_symlink_loop(&e) => Err(ConfigError::SymlinkLoop {
+            path: path.to_path_buf(),
+        }),
+        _ => Ok(None),
+    }
+}
+
+// Helper function to reject a config path that exists but isn't a regular file
+fn check_is_file(
+    path: &Path,
+    metadata: std::fs::Metadata,
+) -> Result<std::fs::Metadata, ConfigError> {
+    if metadata.is_file() {
+        return Ok(metadata);
+    }
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        "directory"
+    } else {
+        file_kind(file_type)
+    };
+    Err(ConfigError::NotAFile {
+        path: path.to_path_buf(),
+        kind,
+    })
+}
+
+// Helper function naming the special file types only unix has
+fn file_kind(file_type: std::fs::FileType) -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_socket() {
+            return "socket";
+        } else if file_type.is_fifo() {
+            return "fifo";
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            return "device";
+        }
+    }
+    let _ = file_type;
+    "special file"
+}
+
+// Helper function to spot ELOOP, which has no stable io::ErrorKind
+fn is_symlink_loop(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        e.raw_os_error() == Some(libc::ELOOP)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = e;
+        false
+    }
+}
+
+// Helper function keeping the infallible load methods' behaviour: unreadable
+// files are logged and skipped while malformed ones still panic
+fn handle_load_result(result: Result<(), ConfigError>) {
+    match result {
+        Ok(()) => {}
+        Err(ConfigError::Frozen) => debug("Configuration is frozen, skipping load"),
+        Err(e @ (ConfigError::PermissionDenied(_) | ConfigError::SymlinkLoop { .. })) => {
+            error(&e.to_string())
+        }
+        Err(e @ ConfigError::NotAFile { .. }) => warn(&format!("{}, skipping", e)),
+        Err(e) => panic!("Unable to load configuration: {}", e),
+    }
+}
+
+// Helper function to hash a file's bytes for change detection
+fn file_hash(path: &Path) -> Option<u64> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let bytes = std::fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+// Helper function to parse YAML, applying `policy` to NaN and infinite floats
+// that would otherwise silently become null in the JSON value
+fn parse_yaml(
+    contents: &str,
+    source: &Path,
+    policy: NonFinitePolicy,
+) -> Result<ConfigDict, ConfigError> {
+    let parse_error = |e: serde_yaml::Error| ConfigError::Parse(format!("{:?}: {}", source, e));
+    let yaml: serde_yaml::Value = serde_yaml::from_str(contents).map_err(parse_error)?;
+    let mut non_finite = Vec::new();
+    find_non_finite("", &yaml, &mut non_finite);
+    for path in non_finite {
+        match policy {
+            NonFinitePolicy::Error => return Err(ConfigError::NonFinite { path }),
+            NonFinitePolicy::Null => warn(&format!(
+                "{} in {:?} is NaN or infinite, storing null",
+                path, source
+            )),
+        }
+    }
+    match yaml_to_json(yaml) {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        Value::Null => Ok(ConfigDict::new()),
+        other => Err(ConfigError::Parse(format!(
+            "{:?}: expected a mapping at the top level, found {}",
+            source,
+            JsonType::of(&other)
+        ))),
+    }
+}
+
+/// Converts a parsed YAML value to JSON. `serde_yaml` follows the YAML 1.2
+/// core schema, so the mapping is:
+/// - only `true` and `false` are booleans; YAML 1.1 spellings like `yes`,
+///   `no`, `on` and `off` stay strings
+/// - timestamps such as `2024-01-01T12:00:00Z` stay strings
+/// - integers stay integers, other numbers become floats and NaN or infinite
+///   ones become `null`
+/// - non-string mapping keys like `1` or `true` become their YAML text
+/// - tags such as `!secret` are dropped, keeping the tagged value
+///
+/// [`Configuration`] may still coerce a string such as `"yes"` to the type of
+/// the bundled default for that key.
+pub fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else {
+                n.as_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .map_or(Value::Null, Value::Number)
+            }
+        }
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(items) => {
+            Value::Array(items.into_iter().map(yaml_to_json).collect())
+        }
+        serde_yaml::Value::Mapping(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (yaml_key(key), yaml_to_json(value)))
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+// Helper function to render a YAML mapping key as a JSON object key
+fn yaml_key(key: serde_yaml::Value) -> String {
+    match key {
+        serde_yaml::Value::String(s) => s,
+        other => serde_yaml::to_string(&other)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+// Helper function to collect the dotted paths of non-finite float leaves
+fn find_non_finite(path: &str, value: &serde_yaml::Value, found: &mut Vec<String>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        serde_yaml::Value::Number(n) if n.as_f64().is_some_and(|f| !f.is_finite()) => {
+            found.push(path.to_string())
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (key, value) in map {
+                find_non_finite(&child(&yaml_key(key.clone())), value, found);
+            }
+        }
+        serde_yaml::Value::Sequence(items) => {
+            for (i, value) in items.iter().enumerate() {
+                find_non_finite(&child(&i.to_string()), value, found);
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => find_non_finite(path, &tagged.value, found),
+        _ => {}
+    }
+}
+
+// Helper function to load JSON with comments
+fn load_commented_json(path: &Path) -> Result<ConfigDict, ConfigError> {
+    let contents = read_text(path)?;
+    let json_str = uncomment_json(&contents);
+    serde_json::from_str(&json_str).map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))
+}
+
+// Helper function to replace the scalar at a dotted path in YAML block mapping
+// text, keeping indentation and any trailing comment. Returns `None` if the
+// path isn't a single-line scalar in the text, e.g. a block scalar (`|`, `>`),
+// a flow collection or a value continued on more-indented lines.
+fn set_yaml_scalar(contents: &str, key: &str, value: &Value) -> Option<String> {
+    if value.is_object() || value.is_array() {
+        return None;
+    }
+    let rendered = serde_yaml::to_string(value).ok()?;
+    let rendered = rendered.trim_end();
+    let target: Vec<&str> = key.split('.').collect();
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    for i in 0..lines.len() {
+        let line = &lines[i];
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with('#') || content.starts_with('-') {
+            continue;
+        }
+        let indent = line.len() - content.len();
+        let Some((name, rest)) = content.split_once(':') else {
+            continue;
+        };
+        if !(rest.is_empty() || rest.starts_with(' ')) {
+            continue;
+        }
+        while stack.last().is_some_and(|(i, _)| *i >= indent) {
+            stack.pop();
+        }
+        stack.push((indent, name.trim().trim_matches(['"', '\'']).to_string()));
+        if stack.len() != target.len() || !stack.iter().map(|(_, k)| k).eq(target.iter()) {
+            continue;
+        }
+        let (scalar, comment) = split_yaml_comment(rest);
+        let scalar = scalar.trim();
+        if scalar.is_empty() || scalar.starts_with(['|', '>', '[', '{']) {
+            return None;
+        }
+        let continued = lines[i + 1..]
+            .iter()
+            .map(|next| (next.len() - next.trim_start().len(), next.trim_start()))
+            .find(|(_, next)| !next.is_empty())
+            .is_some_and(|(next_indent, _)| next_indent > indent);
+        if continued {
+            return None;
+        }
+        let comment = if comment.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", comment)
+        };
+        let line = format!("{}{}: {}{}", &line[..indent], name, rendered, comment);
+        lines[i] = line;
+        let mut out = lines.join("\n");
+        if contents.ends_with('\n') {
+            out.push('\n');
+        }
+        return Some(out);
+    }
+    None
+}
+
+// Helper function to split a YAML value from a trailing ` # comment`
+fn split_yaml_comment(rest: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if prev.is_whitespace() => return (&rest[..i], &rest[i..]),
+            _ => {}
+        }
+        prev = c;
+    }
+    (rest, "")
+}
+
+// Advisory exclusive lock on a `<config>.lock` file, released on drop. On
+// non-Unix platforms the lock is not enforced.
+struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    fn acquire(path: &Path, timeout: Duration) -> Result<Self, ConfigError> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        let lock_path = path.with_file_name(name);
+        ensure_folder_exists(&lock_path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if try_lock_exclusive(&file)? {
+                return Ok(Self { _file: file });
+            }
+            if Instant::now() >= deadline {
+                return Err(ConfigError::LockTimeout(lock_path));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn try_lock_exclusive(file: &File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock_exclusive(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+// Helper function to write a file via a temporary sibling and a rename, so
+// readers never observe a partially written file
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+// Helper function to load JSON with comments without buffering the file
+fn load_commented_json_streaming(path: &Path) -> Result<ConfigDict, ConfigError> {
+    let file = File::open(path).map_err(|e| read_error(path, e))?;
+    let reader = UncommentReader::new(BufReader::new(file));
+    serde_json::from_reader(reader).map_err(|e| ConfigError::Parse(format!("{:?}: {}", path, e)))
+}
+
+// Helper function to render `header` as `#` comment lines
+fn comment_header(header: &str) -> String {
+    header
+        .lines()
+        .map(|line| match line.trim_end() {
+            "" => "#\n".to_string(),
+            line => format!("# {}\n", line),
+        })
+        .collect()
+}
+
+// Helper function to strip `//`, `#` and `/* */` comments outside of string literals
+pub(crate) fn uncomment_json(contents: &str) -> String {
+    let mut out = String::with_capacity(contents.len());
+    // Comments are dropped whole, so the output stays valid UTF-8
+    UncommentReader::new(contents.as_bytes())
+        .read_to_string(&mut out)
+        .expect("stripping comments from a str cannot fail");
+    out
+}
+
+// Reader adapter stripping `//`, `#` and `/* */` comments outside of string literals
+struct UncommentReader<R: BufRead> {
+    bytes: std::iter::Peekable<std::io::Bytes<R>>,
+    in_string: bool,
+    escaped: bool,
+    // An error hit after some bytes were already read, returned by the next `read`
+    error: Option<std::io::Error>,
+}
+
+impl<R: BufRead> UncommentReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            bytes: inner.bytes().peekable(),
+            in_string: false,
+            escaped: false,
+            error: None,
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<std::io::Result<u8>> {
+        loop {
+            let b = match self.bytes.next()? {
+                Ok(b) => b,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                return Some(Ok(b));
+            }
+            let next = match self.bytes.peek() {
+                Some(Ok(next)) => Some(*next),
+                _ => None,
+            };
+            match (b, next) {
+                (b'"', _) => {
+                    self.in_string = true;
+                    return Some(Ok(b));
+                }
+                (b'#', _) | (b'/', Some(b'/')) => {
+                    while let Some(Ok(c)) = self.bytes.peek() {
+                        if *c == b'\n' {
+                            break;
+                        }
+                        self.bytes.next();
+                    }
+                }
+                (b'/', Some(b'*')) => {
+                    self.bytes.next();
+                    let mut prev = 0;
+                    loop {
+                        match self.bytes.next() {
+                            Some(Ok(b'/')) if prev == b'*' => break,
+                            Some(Ok(c)) => prev = c,
+                            Some(Err(e)) => return Some(Err(e)),
+                            None => break,
+                        }
+                    }
+                }
+                _ => return Some(Ok(b)),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for UncommentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            match self.next_byte() {
+                Some(Ok(b)) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                Some(Err(e)) if n == 0 => return Err(e),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        Ok(n)
+    }
+}
+
+// Helper function to walk a dotted path through nested objects, treating
+// numeric segments as indices into arrays (e.g. `servers.0.host`)
+pub(crate) fn get_path<'a>(dict: &'a ConfigDict, key: &str) -> Option<&'a Value> {
+    let mut segments = key.split('.');
+    let mut node = dict.get(segments.next()?)?;
+    for segment in segments {
+        node = match node {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(node)
+}
+
+// Helper function to tell whether `change` alters the value at the dotted
+// path `key`, which it does if it's at or below `key`, or if it replaces an
+// ancestor with something holding a different value there
+fn change_alters(change: &ConfigChange, key: &str) -> bool {
+    let path = change.path();
+    if is_under_any(path, &[key]) {
+        return true;
+    }
+    let Some(rest) = key
+        .strip_prefix(path)
+        .and_then(|rest| rest.strip_prefix('.'))
+    else {
+        return false;
+    };
+    let (old, new) = match change {
+        ConfigChange::Added { value, .. } => (None, Some(value)),
+        ConfigChange::Removed { value, .. } => (Some(value), None),
+        ConfigChange::Changed { old, new, .. } => (Some(old), Some(new)),
+    };
+    fn at<'a>(value: Option<&'a Value>, rest: &str) -> Option<&'a Value> {
+        rest.split('.')
+            .try_fold(value?, |node, segment| match node {
+                Value::Object(map) => map.get(segment),
+                Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+                _ => None,
+            })
+    }
+    at(old, rest) != at(new, rest)
+}
+
+// Helper function to remove the value at a dotted path
+fn remove_path(dict: &mut ConfigDict, key: &str) -> Option<Value> {
+    let Some((parent, leaf)) = key.rsplit_once('.') else {
+        return dict.remove(key);
+    };
+    let mut segments = parent.split('.');
+    let mut node = dict.get_mut(segments.next()?)?;
+    for segment in segments {
+        node = match node {
+            Value::Object(map) => map.get_mut(segment)?,
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match node {
+        Value::Object(map) => map.remove(leaf),
+        Value::Array(items) => {
+            let index = leaf.parse::<usize>().ok().filter(|&i| i < items.len())?;
+            Some(items.remove(index))
+        }
+        _ => None,
+    }
+}
+
+// Helper function to set the value at a dotted path, indexing into existing
+// arrays (e.g. `servers.0.host`) and replacing any other non-object
+// intermediate nodes with objects. A segment that isn't the index of an item
+// of the array it meets fails without changing anything.
+fn set_path(dict: &mut ConfigDict, key: &str, value: Value) -> Result<(), ConfigError> {
+    let Some((head, rest)) = key.split_once('.') else {
+        dict.insert(key.to_string(), value);
+        return Ok(());
+    };
+    let mut node = dict.entry(head.to_string()).or_insert(Value::Null);
+    let mut segments = rest.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let child = match node {
+            Value::Array(items) => {
+                let len = items.len();
+                match segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                    Some(item) => item,
+                    None => {
+                        return Err(ConfigError::InvalidIndex {
+                            key: key.to_string(),
+                            segment: segment.to_string(),
+                            len,
+                        })
+                    }
+                }
+            }
+            _ => {
+                if !node.is_object() {
+                    *node = Value::Object(Default::default());
+                }
+                let Value::Object(map) = node else {
+                    unreachable!()
+                };
+                map.entry(segment).or_insert(Value::Null)
+            }
+        };
+        if segments.peek().is_none() {
+            *child = value;
+            break;
+        }
+        node = child;
+    }
+    Ok(())
+}
+
+// Helper function to deserialize a setting, falling back to `default`
+fn deserialize_or<T: DeserializeOwned>(key: &str, value: Option<Value>, default: T) -> T {
+    let Some(value) = value else {
+        return default;
+    };
+    serde_json::from_value(value).unwrap_or_else(|e| {
+        debug(&format!(
+            "{} doesn't fit {}: {}",
+            key,
+            std::any::type_name::<T>(),
+            e
+        ));
+        default
+    })
+}
+
+// Helper function to convert an integer setting, logging rather than
+// truncating when it doesn't fit `kind`
+fn integer_as<T>(
+    key: &str,
+    value: Option<Value>,
+    convert: impl Fn(&Number) -> Option<T>,
+    kind: &str,
+) -> Option<T> {
+    let Value::Number(n) = value? else {
+        return None;
+    };
+    let converted = convert(&n);
+    if converted.is_none() {
+        if n.as_f64().is_some_and(|f| f.fract() != 0.0) {
+            warn(&format!("{} = {} is not an integer", key, n));
+        } else if n.is_f64() {
+            warn(&format!("{} = {} is beyond 64-bit integer range", key, n));
+        } else {
+            warn(&format!("{} = {} is out of range for {}", key, n, kind));
+        }
+    }
+    converted
+}
+
+// Helper function to parse a size string like "16MB" or "16 MiB" into bytes
+pub(crate) fn parse_size_bytes(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+// Helper function to parse a duration string like "30s", "500 ms" or "2h";
+// a bare number is taken as seconds
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds: f64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3_600.0,
+        "d" => 86_400.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number * seconds).ok()
+}
+
+// Helper function to visit every leaf below `path`, descending into arrays
+// with index segments or treating them as leaves
+fn walk_leaves(path: &str, value: &Value, into_arrays: bool, f: &mut impl FnMut(&str, &Value)) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                walk_leaves(&format!("{}.{}", path, key), child, into_arrays, f);
+            }
+        }
+        Value::Array(items) if into_arrays => {
+            for (i, item) in items.iter().enumerate() {
+                walk_leaves(&format!("{}.{}", path, i), item, into_arrays, f);
+            }
+        }
+        leaf => f(path, leaf),
+    }
+}
+
+// Helper function to replace every scalar leaf below `path`
+fn map_leaves_value(path: &str, value: &mut Value, f: &mut impl FnMut(&str, &Value) -> Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                map_leaves_value(&format!("{}.{}", path, key), child, f);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                map_leaves_value(&format!("{}.{}", path, i), item, f);
+            }
+        }
+        leaf => *leaf = f(path, leaf),
+    }
+}
+
+// Helper function to apply an RFC 7386 merge patch to a single value
+fn merge_patch_value(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    if let Value::Object(map) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge_patch_value(map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+// Helper function to deep-merge a single value
+fn merge_value(base: &mut Value, delta: &Value) {
+    match (base, delta) {
+        (Value::Object(base), Value::Object(delta)) => {
+            for (key, value) in delta {
+                match base.get_mut(key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, delta) => *base = delta.clone(),
+    }
+}
+
+// Helper function to merge a single value by the strategy in its schema
+fn merge_value_with(
+    base: &mut Value,
+    delta: &Value,
+    schema: Option<&Value>,
+    policy: TypeConflictPolicy,
+    path: &str,
+) -> Result<(), ConfigError> {
+    let (existing, incoming) = (JsonType::of(base), JsonType::of(delta));
+    let coerced;
+    let delta = match policy {
+        _ if existing == incoming || base.is_null() || delta.is_null() => delta,
+        TypeConflictPolicy::PreferHigher => delta,
+        TypeConflictPolicy::Coerce => match coerce_to(delta, base) {
+            Some(value) => {
+                coerced = value;
+                &coerced
+            }
+            None => {
+                warn(&format!(
+                    "{}: can't convert {} to {}, keeping the {}",
+                    path, delta, existing, incoming
+                ));
+                delta
+            }
+        },
+        TypeConflictPolicy::Error => {
+            return Err(ConfigError::TypeConflict {
+                path: path.to_string(),
+                existing,
+                incoming,
+            })
+        }
+    };
+    let strategy = match schema.and_then(|s| s.get(MERGE_ANNOTATION)) {
+        Some(Value::String(name)) => name.parse().unwrap_or_else(|e: String| {
+            warn(&format!("{} at {}, deep-merging", e, path));
+            MergeStrategy::Deep
+        }),
+        _ => MergeStrategy::Deep,
+    };
+    match (strategy, base, delta) {
+        (MergeStrategy::Deep, Value::Object(base), Value::Object(delta)) => {
+            for (key, value) in delta {
+                let child = schema
+                    .and_then(|s| s.get("properties"))
+                    .and_then(|p| p.get(key));
+                match base.get_mut(key) {
+                    Some(existing) => merge_value_with(
+                        existing,
+                        value,
+                        child,
+                        policy,
+                        &format!("{}.{}", path, key),
+                    )?,
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (MergeStrategy::Append, Value::Array(base), Value::Array(delta)) => {
+            base.extend(delta.iter().cloned())
+        }
+        (MergeStrategy::Error, base, delta) if !values_equal(base, delta, 0.0) => {
+            return Err(ConfigError::MergeConflict {
+                path: path.to_string(),
+            })
+        }
+        (_, base, delta) => *base = delta.clone(),
+    }
+    Ok(())
+}
+
+// Helper function to convert a scalar to the type of `reference`, if it
+// reads as one
+fn coerce_to(value: &Value, reference: &Value) -> Option<Value> {
+    let mut coerced = value.clone();
+    coerce_value(&mut coerced, reference);
+    match (&coerced, reference) {
+        _ if JsonType::of(&coerced) == JsonType::of(reference) => Some(coerced),
+        (Value::Bool(b), Value::Number(_)) => Some(Value::from(u8::from(*b))),
+        (Value::Number(n), Value::Bool(_)) => match n.as_f64() {
+            Some(0.0) => Some(Value::Bool(false)),
+            Some(1.0) => Some(Value::Bool(true)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Helper function to resolve `$from_file` directives below a value
+fn resolve_file_refs_value(value: &mut Value, policy: FileRefPolicy) -> Result<(), ConfigError> {
+    match value {
+        Value::Object(map) => {
+            if let (1, Some(Value::String(path))) = (map.len(), map.get("$from_file")) {
+                let path = PathBuf::from(path);
+                match std::fs::read_to_string(&path) {
+                    Ok(contents) => *value = Value::String(contents.trim().to_string()),
+                    Err(source) => match policy {
+                        FileRefPolicy::Error => return Err(ConfigError::FileRef { path, source }),
+                        FileRefPolicy::Warn => {
+                            warn(&format!("Unable to read $from_file {:?}: {}", path, source))
+                        }
+                    },
+                }
+            } else {
+                for child in map.values_mut() {
+                    resolve_file_refs_value(child, policy)?;
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                resolve_file_refs_value(item, policy)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Helper function to coerce a value towards the type of its reference value
+fn coerce_value(value: &mut Value, reference: &Value) {
+    match (&mut *value, reference) {
+        (Value::Object(map), Value::Object(reference)) => {
+            for (key, child) in map.iter_mut() {
+                if let Some(reference) = reference.get(key) {
+                    coerce_value(child, reference);
+                }
+            }
+        }
+        (Value::String(s), Value::Bool(_)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" => *value = Value::Bool(true),
+            "false" => *value = Value::Bool(false),
+            _ => {}
+        },
+        (Value::String(s), Value::Number(n)) => {
+            let s = s.trim();
+            let number = if n.is_f64() {
+                s.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+            } else if let Ok(i) = s.parse::<i64>() {
+                Some(i.into())
+            } else {
+                s.parse::<u64>().ok().map(Into::into)
+            };
+            if let Some(number) = number {
+                *value = Value::Number(number);
+            }
+        }
+        (Value::Number(_) | Value::Bool(_), Value::String(_)) => {
+            *value = Value::String(value.to_string());
+        }
+        _ => {}
+    }
+}
+
+// Helper function to mask a value if its key is sensitive, recursing otherwise
+fn redact_value(key: &str, value: &mut Value) {
+    let key = key.to_lowercase();
+    if SENSITIVE_KEYS.iter().any(|s| key.contains(s)) {
+        *value = Value::String("***".to_string());
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                redact_value(key, child);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_value("", item);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Helper function to keep only the entries of `top` that differ from `base`
+fn overrides_of(base: &ConfigDict, top: &ConfigDict) -> ConfigDict {
+    let mut delta = ConfigDict::new();
+    for (key, value) in top {
+        match (base.get(key), value) {
+            (Some(old), new) if old == new => {}
+            (Some(Value::Object(old)), Value::Object(new)) => {
+                let old: ConfigDict = old.clone().into_iter().collect();
+                let new: ConfigDict = new.clone().into_iter().collect();
+                let nested = overrides_of(&old, &new);
+                if !nested.is_empty() {
+                    delta.insert(key.clone(), Value::Object(nested.into_iter().collect()));
+                }
+            }
+            _ => {
+                delta.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    delta
+}
+
+// Helper function to compare values deeply with a numeric tolerance
+fn values_equal(a: &Value, b: &Value, epsilon: f64) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            x == y
+                || match (x.as_f64(), y.as_f64()) {
+                    (Some(x), Some(y)) => (x - y).abs() <= epsilon,
+                    _ => false,
+                }
+        }
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| values_equal(x, y, epsilon))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter().all(|(key, value)| {
+                    y.get(key)
+                        .is_some_and(|other| values_equal(value, other, epsilon))
+                })
+        }
+        _ => a == b,
+    }
+}
+
+// Helper function to recursively diff two values, collecting dotted-path changes
+fn diff_value(
+    path: String,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<ConfigChange>,
+) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_value(
+                    format!("{}.{}", path, key),
+                    old.get(key),
+                    new.get(key),
+                    changes,
+                );
+            }
+        }
+        (Some(old), Some(new)) if old != new => changes.push(ConfigChange::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        (Some(Value::Object(old)), None) if !old.is_empty() => {
+            for (key, value) in old {
+                diff_value(format!("{}.{}", path, key), Some(value), None, changes);
+            }
+        }
+        (None, Some(Value::Object(new))) if !new.is_empty() => {
+            for (key, value) in new {
+                diff_value(format!("{}.{}", path, key), None, Some(value), changes);
+            }
+        }
+        (Some(old), None) => changes.push(ConfigChange::Removed {
+            path,
+            value: old.clone(),
+        }),
+        (None, Some(new)) => changes.push(ConfigChange::Added {
+            path,
+            value: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::testing::{deny_reads, isolated, user_config, without_home, write_file};
+
+    // Helper function to build an in-memory config from a JSON object
+    fn conf(value: Value) -> LocalConf {
+        LocalConf::in_memory(serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn size_bytes_decimal_and_binary_units() {
+        let conf = conf(json!({"cache": {"decimal": "16MB", "binary": "16MiB", "lower": "2 kib"}}));
+        assert_eq!(conf.get_size_bytes("cache.decimal"), Some(16_000_000));
+        assert_eq!(conf.get_size_bytes("cache.binary"), Some(16 * 1024 * 1024));
+        assert_eq!(conf.get_size_bytes("cache.lower"), Some(2048));
+    }
+
+    #[test]
+    fn size_bytes_bare_integer() {
+        let conf = conf(json!({"buffer": 4096, "as_string": "512"}));
+        assert_eq!(conf.get_size_bytes("buffer"), Some(4096));
+        assert_eq!(conf.get_size_bytes("as_string"), Some(512));
+    }
+
+    #[test]
+    fn size_bytes_unparseable() {
+        let conf = conf(json!({"bad": "sixteen MB", "unit": "16XB", "negative": -1, "flag": true}));
+        assert_eq!(conf.get_size_bytes("bad"), None);
+        assert_eq!(conf.get_size_bytes("unit"), None);
+        assert_eq!(conf.get_size_bytes("negative"), None);
+        assert_eq!(conf.get_size_bytes("flag"), None);
+        assert_eq!(conf.get_size_bytes("missing"), None);
+    }
+
+    #[test]
+    fn format_diff_formats_each_kind() {
+        let added = ConfigChange::Added {
+            path: "listener.sample_rate".to_string(),
+            value: json!(16000),
+        };
+        let removed = ConfigChange::Removed {
+            path: "tts.engine".to_string(),
+            value: json!("mimic"),
+        };
+        let changed = ConfigChange::Changed {
+            path: "foo.bar".to_string(),
+            old: json!(1),
+            new: json!(2),
+        };
+        assert_eq!(format_diff(&[added]), "+ listener.sample_rate = 16000\n");
+        assert_eq!(format_diff(&[removed]), "- tts.engine = \"mimic\"\n");
+        assert_eq!(format_diff(&[changed]), "~ foo.bar: 1 -> 2\n");
+        assert_eq!(format_diff(&[]), "");
+    }
+
+    #[test]
+    fn format_diff_groups_then_sorts_by_path() {
+        let changes = vec![
+            ConfigChange::Changed {
+                path: "a.changed".to_string(),
+                old: json!(true),
+                new: json!(false),
+            },
+            ConfigChange::Removed {
+                path: "z.removed".to_string(),
+                value: json!([1]),
+            },
+            ConfigChange::Added {
+                path: "b.added".to_string(),
+                value: json!({"x": 1}),
+            },
+            ConfigChange::Removed {
+                path: "c.removed".to_string(),
+                value: json!(null),
+            },
+            ConfigChange::Added {
+                path: "a.added".to_string(),
+                value: json!("x"),
+            },
+        ];
+        assert_eq!(
+            format_diff(&changes),
+            "+ a.added = \"x\"\n\
+             + b.added = {\"x\":1}\n\
+             - c.removed = null\n\
+             - z.removed = [1]\n\
+             ~ a.changed: true -> false\n"
+        );
+    }
+
+    const PROFILES: &str = r#"{
+        "lang": "en-us",
+        "log_level": "INFO",
+        "profiles": {
+            "dev": {"log_level": "DEBUG"},
+            "prod": {"log_level": "WARNING", "lang": "de-de"}
+        }
+    }"#;
+
+    #[test]
+    fn load_profile_selects_each_profile() {
+        isolated(|root| {
+            write_file(&user_config(root), PROFILES);
+
+            let dev = Configuration::load_profile("dev").unwrap();
+            assert_eq!(dev.get("log_level"), Some(json!("DEBUG")));
+            assert_eq!(dev.get("lang"), Some(json!("en-us")));
+            assert_eq!(dev.get("profiles"), None);
+
+            let prod = Configuration::load_profile("prod").unwrap();
+            assert_eq!(prod.get("log_level"), Some(json!("WARNING")));
+            assert_eq!(prod.get("lang"), Some(json!("de-de")));
+            assert_eq!(prod.get("profiles"), None);
+        });
+    }
+
+    #[test]
+    fn load_profile_missing_profile_errors() {
+        isolated(|root| {
+            write_file(&user_config(root), PROFILES);
+            match Configuration::load_profile("staging") {
+                Err(ConfigError::MissingProfile(name)) => assert_eq!(name, "staging"),
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("missing profile loaded"),
+            }
+        });
+    }
+
+    #[test]
+    fn ovos_profile_env_selects_profile() {
+        isolated(|root| {
+            write_file(&user_config(root), PROFILES);
+            env::set_var("OVOS_PROFILE", "prod");
+            let conf = Configuration::new();
+            env::remove_var("OVOS_PROFILE");
+            assert_eq!(conf.get("log_level"), Some(json!("WARNING")));
+            assert_eq!(conf.get("profiles"), None);
+        });
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf.enc");
+        let key = [7u8; 32];
+        let original = conf(json!({"tts": {"api_key": "s3cret"}, "lang": "en-us"}));
+        original.store_encrypted(&path, &key).unwrap();
+
+        let on_disk = std::fs::read(&path).unwrap();
+        assert!(on_disk.starts_with(ENCRYPTED_MAGIC));
+        assert!(!String::from_utf8_lossy(&on_disk).contains("s3cret"));
+
+        let loaded = LocalConf::in_memory(ConfigDict::new());
+        loaded.load_encrypted(&path, &key).unwrap();
+        assert_eq!(loaded.get("tts.api_key"), Some(json!("s3cret")));
+        assert_eq!(loaded.get("lang"), Some(json!("en-us")));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn encrypted_wrong_key_fails_authentication() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf.enc");
+        conf(json!({"lang": "en-us"}))
+            .store_encrypted(&path, &[1u8; 32])
+            .unwrap();
+
+        let loaded = LocalConf::in_memory(ConfigDict::new());
+        assert!(matches!(
+            loaded.load_encrypted(&path, &[2u8; 32]),
+            Err(ConfigError::AuthenticationFailed)
+        ));
+        assert!(loaded.get("lang").is_none());
+        assert!(matches!(
+            loaded.load_encrypted(&path, &[1u8; 16]),
+            Err(ConfigError::InvalidKey)
+        ));
+    }
+
+    const NESTED: &str = r#"{
+        "lang": "en-us",
+        "listener": {"sample_rate": 16000, "wake_word": {"enabled": true, "name": "hey mycroft"}},
+        "skills": {"blacklist": ["a", "b"], "version": "2"},
+        "threshold": 0.5
+    }"#;
+
+    #[test]
+    fn to_env_vars_flattens_nested_config() {
+        isolated(|root| {
+            write_file(&user_config(root), NESTED);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            let vars = conf.to_env_vars("OVOS_CONFIG");
+            let expected: Vec<(String, String)> = [
+                ("OVOS_CONFIG__lang", "en-us"),
+                ("OVOS_CONFIG__listener__sample_rate", "16000"),
+                ("OVOS_CONFIG__listener__wake_word__enabled", "true"),
+                ("OVOS_CONFIG__listener__wake_word__name", "hey mycroft"),
+                ("OVOS_CONFIG__skills__blacklist", r#"["a","b"]"#),
+                ("OVOS_CONFIG__skills__version", r#""2""#),
+                ("OVOS_CONFIG__threshold", "0.5"),
+            ]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+            assert_eq!(vars, expected);
+        });
+    }
+
+    #[test]
+    fn to_env_vars_round_trips_through_env_layer() {
+        isolated(|root| {
+            write_file(&user_config(root), NESTED);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            let vars = conf.to_env_vars(ENV_PREFIX);
+            assert_eq!(from_env_vars(ENV_PREFIX, vars.clone()), *conf.data.read());
+
+            for (name, value) in &vars {
+                env::set_var(name, value);
+            }
+            let from_env = Configuration::with_layer_order(Vec::new());
+            for (name, _) in &vars {
+                env::remove_var(name);
+            }
+            assert_eq!(*from_env.data.read(), *conf.data.read());
+        });
+    }
+
+    #[test]
+    fn reload_replace_drops_keys_removed_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us", "stale": true}"#);
+        let additive = LocalConf::new(Some(path.clone()));
+        let replacing = LocalConf::new(Some(path.clone()));
+        write_file(&path, r#"{"lang": "de-de"}"#);
+
+        additive.load_local(None);
+        assert_eq!(additive.get("lang"), Some(json!("de-de")));
+        assert_eq!(additive.get("stale"), Some(json!(true)));
+
+        replacing.reload_replace();
+        assert_eq!(replacing.get("lang"), Some(json!("de-de")));
+        assert_eq!(replacing.get("stale"), None);
+    }
+
+    #[test]
+    fn last_loaded_set_for_file_and_none_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us"}"#);
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let loaded = LocalConf::new(Some(path.clone()));
+        assert_eq!(loaded.last_loaded(), Some(mtime));
+        assert_eq!(loaded.file_modified(), Some(mtime));
+
+        let in_memory = LocalConf::in_memory(ConfigDict::new());
+        assert_eq!(in_memory.last_loaded(), None);
+        assert_eq!(in_memory.file_modified(), None);
+    }
+
+    #[test]
+    fn file_modified_is_freshly_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, "{}");
+        let conf = LocalConf::new(Some(path.clone()));
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(conf.file_modified(), Some(later));
+        assert_ne!(conf.last_loaded(), Some(later));
+    }
+
+    #[test]
+    fn get_indexes_into_arrays() {
+        let conf = conf(json!({
+            "servers": [{"host": "a.local"}, {"host": "b.local", "ports": [80, 443]}],
+            "lang": "en-us"
+        }));
+        assert_eq!(conf.get("servers.0.host"), Some(json!("a.local")));
+        assert_eq!(conf.get("servers.1.ports.1"), Some(json!(443)));
+        assert_eq!(conf.get("servers.2.host"), None);
+        assert_eq!(conf.get("servers.1.ports.9"), None);
+        assert_eq!(conf.get("lang.0"), None);
+        assert_eq!(conf.get("servers.first.host"), None);
+    }
+
+    #[test]
+    fn validate_rules_collects_failures() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{"lang": "en-us", "listener": {"sample_rate": 22050}}"#,
+            );
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            let rules = vec![
+                crate::validation::required("lang"),
+                crate::validation::one_of("lang", ["en-us", "de-de"]),
+                crate::validation::one_of("listener.sample_rate", [8000, 16000, 48000]),
+                crate::validation::required("tts.module"),
+            ];
+            let failures = conf.validate_rules(&rules);
+            assert_eq!(
+                failures,
+                vec![
+                    ValidationError {
+                        path: "listener.sample_rate".to_string(),
+                        message: "22050 is not one of 8000, 16000, 48000".to_string(),
+                    },
+                    ValidationError {
+                        path: "tts.module".to_string(),
+                        message: "required value is missing".to_string(),
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn ensure_user_config_seeds_fresh_home() {
+        isolated(|root| {
+            let path = Configuration::ensure_user_config().unwrap();
+            assert_eq!(path, user_config(root));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        });
+    }
+
+    #[test]
+    fn ensure_user_config_keeps_existing_file() {
+        isolated(|root| {
+            write_file(&user_config(root), r#"{"lang": "pt-pt"}"#);
+            let path = Configuration::ensure_user_config().unwrap();
+            assert_eq!(path, user_config(root));
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                r#"{"lang": "pt-pt"}"#
+            );
+            assert_eq!(Configuration::ensure_user_config().unwrap(), path);
+        });
+    }
+
+    // Helper function to parse a JSON object into a config dict
+    fn dict(text: &str) -> ConfigDict {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn configs_equal_ignores_key_order() {
+        let a = dict(r#"{"lang": "en-us", "listener": {"rate": 16000, "mute": false}}"#);
+        let b = dict(r#"{"listener": {"mute": false, "rate": 16000.0}, "lang": "en-us"}"#);
+        assert!(configs_equal(&a, &b));
+        let c = dict(r#"{"listener": {"mute": true, "rate": 16000}, "lang": "en-us"}"#);
+        assert!(!configs_equal(&a, &c));
+        let d = dict(r#"{"lang": "en-us", "listener": {"rate": 16000}}"#);
+        assert!(!configs_equal(&a, &d));
+    }
+
+    #[test]
+    fn configs_equal_with_float_tolerance() {
+        let a = dict(r#"{"threshold": 0.3, "weights": [0.1, 0.2]}"#);
+        let within = dict(r#"{"threshold": 0.30000001, "weights": [0.1, 0.20000001]}"#);
+        let without = dict(r#"{"threshold": 0.31, "weights": [0.1, 0.2]}"#);
+        assert!(!configs_equal(&a, &within));
+        assert!(configs_equal_with_tolerance(&a, &within, 1e-6));
+        assert!(!configs_equal_with_tolerance(&a, &without, 1e-6));
+    }
+
+    #[test]
+    fn from_file_reference_resolves_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret = dir.path().join("api_key");
+        write_file(&secret, "  hunter2\n");
+        let path = dir.path().join("mycroft.conf");
+        let text = json!({"tts": {"api_key": {"$from_file": secret}}}).to_string();
+        write_file(&path, &text);
+
+        let conf = LocalConf::new(Some(path));
+        assert_eq!(conf.get("tts.api_key"), Some(json!("hunter2")));
+    }
+
+    #[test]
+    fn from_file_reference_to_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let path = dir.path().join("mycroft.conf");
+        let text = json!({"tts": {"api_key": {"$from_file": missing}}}).to_string();
+        write_file(&path, &text);
+
+        let strict = LocalConf::in_memory(ConfigDict::new());
+        match strict.try_load_local(Some(&path)) {
+            Err(ConfigError::FileRef { path, source }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected a FileRef error, got {:?}", other.err()),
+        }
+
+        let lenient = LocalConf::new(Some(path));
+        assert_eq!(
+            lenient.get("tts.api_key"),
+            Some(json!({"$from_file": missing}))
+        );
+    }
+
+    #[test]
+    fn resolve_file_refs_applies_policy() {
+        let mut config = dict(r#"{"key": {"$from_file": "/nonexistent/ovos-secret"}}"#);
+        assert!(resolve_file_refs(&mut config, FileRefPolicy::Error).is_err());
+        assert!(resolve_file_refs(&mut config, FileRefPolicy::Warn).is_ok());
+        assert_eq!(
+            config["key"],
+            json!({"$from_file": "/nonexistent/ovos-secret"})
+        );
+    }
+
+    #[test]
+    fn export_overrides_yaml_contains_only_user_changes() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{
+                    "lang": "en-us",
+                    "confirm_listening": false,
+                    "tts": {"ovos-tts-plugin-example": {"api_key": "hunter2", "voice": "alan"}}
+                }"#,
+            );
+            let conf = Configuration::new();
+            let exported: ConfigDict = serde_yaml::from_str(&conf.export_overrides_yaml()).unwrap();
+            let expected = dict(
+                r#"{
+                    "confirm_listening": false,
+                    "tts": {"ovos-tts-plugin-example": {"api_key": "***", "voice": "alan"}}
+                }"#,
+            );
+            assert_eq!(exported, expected);
+        });
+    }
+
+    // Helper function to run `f` with XDG_CONFIG_HOME set to `dir`, restoring
+    // the previous value afterwards. Callers must hold the env lock.
+    fn with_config_home<R>(dir: &Path, f: impl FnOnce() -> R) -> R {
+        let previous = env::var_os("XDG_CONFIG_HOME");
+        env::set_var("XDG_CONFIG_HOME", dir);
+        xdg::refresh_env_cache();
+        let result = f();
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_HOME", value),
+            None => env::remove_var("XDG_CONFIG_HOME"),
+        }
+        xdg::refresh_env_cache();
+        result
+    }
+
+    #[test]
+    fn reload_follows_relocated_config_home() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let (old_home, new_home) = (dir.path().join("old"), dir.path().join("new"));
+        write_file(
+            &old_home.join("mycroft/mycroft.conf"),
+            r#"{"lang": "en-us"}"#,
+        );
+        write_file(
+            &new_home.join("mycroft/mycroft.conf"),
+            r#"{"lang": "de-de"}"#,
+        );
+
+        let conf = with_config_home(&old_home, || {
+            Configuration::with_layer_order(vec![ConfigLayer::User])
+        });
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+
+        with_config_home(&new_home, || {
+            // Set without refreshing, as a session manager would
+            env::set_var("XDG_CONFIG_HOME", &new_home);
+            conf.reload().unwrap();
+            assert_eq!(xdg::xdg_config_home(), new_home);
+            assert_eq!(
+                conf.active_user_config_path(),
+                new_home.join("mycroft/mycroft.conf")
+            );
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        });
+    }
+
+    #[test]
+    fn reload_keeps_layer_paths_while_edits_are_unsaved() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let (old_home, new_home) = (dir.path().join("old"), dir.path().join("new"));
+        let old_file = old_home.join("mycroft/mycroft.conf");
+        write_file(&old_file, r#"{"lang": "en-us"}"#);
+        write_file(
+            &new_home.join("mycroft/mycroft.conf"),
+            r#"{"lang": "de-de"}"#,
+        );
+
+        let conf = with_config_home(&old_home, || {
+            Configuration::with_layer_order(vec![ConfigLayer::User])
+        });
+        conf.set("volume", json!(11)).unwrap();
+
+        with_config_home(&new_home, || {
+            conf.reload().unwrap();
+            assert_eq!(conf.active_user_config_path(), old_file);
+            assert_eq!(conf.get("volume"), Some(json!(11)));
+
+            conf.store().unwrap();
+            conf.reload().unwrap();
+            assert_eq!(
+                conf.active_user_config_path(),
+                new_home.join("mycroft/mycroft.conf")
+            );
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+            assert_eq!(conf.get("volume"), None);
+        });
+        let stored: ConfigDict =
+            serde_json::from_str(&std::fs::read_to_string(&old_file).unwrap()).unwrap();
+        assert_eq!(stored["volume"], json!(11));
+    }
+
+    #[test]
+    fn streaming_load_matches_buffered_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("catalog.json");
+        let skills: serde_json::Map<String, Value> = (0..5000)
+            .map(|i| {
+                (
+                    format!("skill-{}.openvoiceos", i),
+                    json!({"enabled": i % 3 != 0, "priority": i, "tags": ["a", "b"], "ratio": i as f64 / 7.0}),
+                )
+            })
+            .collect();
+        write_file(
+            &path,
+            &json!({"skills": skills, "lang": "en-us"}).to_string(),
+        );
+
+        let buffered = LocalConf::in_memory(ConfigDict::new());
+        buffered.try_load_local(Some(&path)).unwrap();
+        let streamed = LocalConf::in_memory(ConfigDict::new());
+        streamed.load_local_streaming(Some(&path)).unwrap();
+        assert_eq!(streamed.to_btree(), buffered.to_btree());
+        assert_eq!(streamed.get("lang"), Some(json!("en-us")));
+        assert_eq!(
+            streamed.to_btree()["skills"].as_object().unwrap().len(),
+            5000
+        );
+    }
+
+    #[test]
+    fn uncomment_reader_reports_errors_after_partial_reads() {
+        // Fails once, then reports end of file like a retried read would
+        struct FailOnce(bool);
+
+        impl Read for FailOnce {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Ok(0);
+                }
+                Err(std::io::Error::other("disk gone"))
+            }
+        }
+
+        let inner = BufReader::new(&b"{\"lang\": \"en-us\"} // tail"[..]).chain(FailOnce(false));
+        let mut reader = UncommentReader::new(BufReader::new(inner));
+        let mut buf = [0u8; 64];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"{\"lang\": \"en-us\"} ");
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "disk gone");
+
+        let inner = BufReader::new(&b"{\"lang\": "[..]).chain(FailOnce(false));
+        let mut out = String::new();
+        assert!(UncommentReader::new(BufReader::new(inner))
+            .read_to_string(&mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn freeze_rejects_mutations_but_allows_reads() {
+        let conf = conf(json!({"lang": "en-us", "listener": {"sample_rate": 16000}}));
+        conf.freeze(false);
+        assert!(conf.is_frozen());
+        assert!(matches!(
+            conf.set("lang", json!("de-de")),
+            Err(ConfigError::Frozen)
+        ));
+        assert!(matches!(
+            conf.merge(&dict(r#"{"lang": "de-de"}"#)),
+            Err(ConfigError::Frozen)
+        ));
+        assert!(matches!(conf.remove("lang"), Err(ConfigError::Frozen)));
+        assert!(matches!(conf.clear(), Err(ConfigError::Frozen)));
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(16000)));
+    }
+
+    #[test]
+    fn freeze_allow_reload_decides_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us"}"#);
+        let reloadable = LocalConf::new(Some(path.clone()));
+        let pinned = LocalConf::new(Some(path.clone()));
+        reloadable.freeze(true);
+        pinned.freeze(false);
+        write_file(&path, r#"{"lang": "de-de"}"#);
+
+        reloadable.reload_replace();
+        assert_eq!(reloadable.get("lang"), Some(json!("de-de")));
+        pinned.reload_replace();
+        assert!(matches!(
+            pinned.try_load_local(None),
+            Err(ConfigError::Frozen)
+        ));
+        assert_eq!(pinned.get("lang"), Some(json!("en-us")));
+    }
+
+    #[test]
+    fn coerce_types_follows_reference_types() {
+        let reference =
+            dict(r#"{"rate": 16000, "ratio": 0.5, "on": true, "name": "x", "nested": {"n": 1}}"#);
+        let mut config = dict(
+            r#"{"rate": "48000", "ratio": " 0.25 ", "on": "False", "name": 7, "nested": {"n": "2"}, "other": "3"}"#,
+        );
+        coerce_types(&mut config, &reference);
+        assert_eq!(
+            config,
+            dict(
+                r#"{"rate": 48000, "ratio": 0.25, "on": false, "name": "7", "nested": {"n": 2}, "other": "3"}"#
+            )
+        );
+
+        let mut unparseable = dict(r#"{"rate": "fast", "on": "maybe"}"#);
+        coerce_types(&mut unparseable, &reference);
+        assert_eq!(unparseable, dict(r#"{"rate": "fast", "on": "maybe"}"#));
+    }
+
+    #[test]
+    fn string_env_override_of_numeric_default_is_coerced() {
+        isolated(|_| {
+            env::set_var("OVOS_CONFIG__listener__sample_rate", r#""48000""#);
+            env::set_var("OVOS_CONFIG__confirm_listening", r#""false""#);
+            let conf = Configuration::new();
+            env::remove_var("OVOS_CONFIG__listener__sample_rate");
+            env::remove_var("OVOS_CONFIG__confirm_listening");
+            assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+            assert_eq!(conf.get("confirm_listening"), Some(json!(false)));
+        });
+    }
+
+    #[test]
+    fn set_preserving_keeps_yaml_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.yaml");
+        write_file(
+            &path,
+            "# Local overrides\nlistener:\n  sample_rate: 16000  # Hz\n  # keep mic on\n  mute: false\n",
+        );
+        let conf = LocalConf::new(Some(path.clone()));
+        conf.set_preserving("listener.sample_rate", json!(48000))
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("# Local overrides"));
+        assert!(contents.contains("# Hz"));
+        assert!(contents.contains("# keep mic on"));
+        let reloaded = LocalConf::new(Some(path.clone()));
+        assert_eq!(reloaded.get("listener.sample_rate"), Some(json!(48000)));
+        assert_eq!(reloaded.get("listener.mute"), Some(json!(false)));
+    }
+
+    #[test]
+    fn set_preserving_keeps_json_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(
+            &path,
+            "{\n  // Wake word engine\n  \"listener\": {\"sample_rate\": 16000}\n}\n",
+        );
+        let conf = LocalConf::new(Some(path.clone()));
+        conf.set_preserving("listener.sample_rate", json!(48000))
+            .unwrap();
+        conf.set_preserving("lang", json!("de-de")).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("// Wake word engine"));
+        let reloaded = LocalConf::new(Some(path.clone()));
+        assert_eq!(reloaded.get("listener.sample_rate"), Some(json!(48000)));
+        assert_eq!(reloaded.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn set_preserving_refuses_to_drop_yaml_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.yaml");
+        let original = "# Local overrides\nlang: en-us\n";
+        write_file(&path, original);
+        let conf = LocalConf::new(Some(path.clone()));
+
+        let err = conf
+            .set_preserving("listener", json!({"sample_rate": 48000}))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::CommentsNotPreserved { ref key, path: ref p }
+                if key == "listener" && *p == path
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+    }
+
+    // Helper function to check `set_preserving` leaves YAML it can't edit in
+    // place untouched on disk
+    fn assert_yaml_not_edited_in_place(original: &str, key: &str, value: Value) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.yaml");
+        write_file(&path, original);
+        let conf = LocalConf::new(Some(path.clone()));
+
+        let err = conf.set_preserving(key, value.clone()).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::CommentsNotPreserved { key: ref k, .. } if k == key
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(conf.get(key), Some(value));
+    }
+
+    #[test]
+    fn set_preserving_refuses_block_scalars() {
+        assert_yaml_not_edited_in_place(
+            "# Message of the day\nmotd: |\n  line one\n  line two\nlang: en-us\n",
+            "motd",
+            json!("short"),
+        );
+        assert_yaml_not_edited_in_place(
+            "motd: >-  # folded\n  line one\n  line two\n",
+            "motd",
+            json!("short"),
+        );
+    }
+
+    #[test]
+    fn set_preserving_refuses_flow_collections() {
+        assert_yaml_not_edited_in_place(
+            "# Wake words\nhotwords: [hey_mycroft, hey_neon]\n",
+            "hotwords",
+            json!("hey_mycroft"),
+        );
+        assert_yaml_not_edited_in_place(
+            "listener: {sample_rate: 16000}  # Hz\n",
+            "listener",
+            json!(false),
+        );
+    }
+
+    #[test]
+    fn set_preserving_refuses_continued_scalars() {
+        assert_yaml_not_edited_in_place(
+            "# Greeting\ngreeting: hello\n  there\nlang: en-us\n",
+            "greeting",
+            json!("hi"),
+        );
+        assert_yaml_not_edited_in_place("greeting: \"hello\n  there\"\n", "greeting", json!("hi"));
+    }
+
+    #[test]
+    fn active_paths_match_loaded_layers() {
+        isolated(|root| {
+            let xdg_conf = root.join("etc/xdg/mycroft/mycroft.conf");
+            write_file(&xdg_conf, r#"{"lang": "de-de"}"#);
+            write_file(&user_config(root), r#"{"lang": "fr-fr"}"#);
+
+            let conf = Configuration::new();
+            assert_eq!(conf.active_user_config_path(), user_config(root));
+            let active = conf.active_layers();
+            assert_eq!(active.len(), 3);
+            assert_eq!(active[0], find_default_config());
+            assert_eq!(active[1..], [xdg_conf, user_config(root)]);
+            assert_eq!(conf.get("lang"), Some(json!("fr-fr")));
+        });
+    }
+
+    #[test]
+    fn active_layers_skips_missing_files() {
+        isolated(|root| {
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            assert_eq!(conf.active_user_config_path(), user_config(root));
+            assert!(conf.active_layers().is_empty());
+
+            write_file(&user_config(root), r#"{"lang": "fr-fr"}"#);
+            conf.reload().unwrap();
+            assert_eq!(conf.active_layers(), [user_config(root)]);
+        });
+    }
+
+    #[test]
+    fn read_error_reports_permission_denied() {
+        let path = Path::new("/etc/mycroft/mycroft.conf");
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            read_error(path, denied),
+            ConfigError::PermissionDenied(ref p) if p == path
+        ));
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(matches!(read_error(path, missing), ConfigError::Io(_)));
+    }
+
+    #[test]
+    fn unreadable_layer_is_reported_and_others_load() {
+        isolated(|root| {
+            let xdg_conf = root.join("etc/xdg/mycroft/mycroft.conf");
+            write_file(&xdg_conf, r#"{"lang": "de-de"}"#);
+            write_file(
+                &user_config(root),
+                r#"{"listener": {"sample_rate": 48000}}"#,
+            );
+
+            let conf = Configuration::from_layers(vec![ConfigLayer::Xdg, ConfigLayer::User], None);
+            let errors = deny_reads(&xdg_conf, || conf.try_load_all()).unwrap_err();
+            assert!(matches!(
+                errors[..],
+                [ConfigError::PermissionDenied(ref p)] if *p == xdg_conf
+            ));
+            assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+            assert_eq!(conf.get("lang"), None);
+        });
+    }
+
+    #[test]
+    fn for_each_leaf_visits_every_scalar() {
+        let conf = conf(json!({
+            "lang": "en-us",
+            "listener": {"sample_rate": 16000, "wake_word": {"name": "hey mycroft"}},
+            "servers": [{"host": "a"}, "b"],
+            "empty": {}
+        }));
+        let mut leaves = Vec::new();
+        conf.for_each_leaf(|path, value| leaves.push((path.to_string(), value.clone())));
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            leaves,
+            [
+                ("lang".to_string(), json!("en-us")),
+                ("listener.sample_rate".to_string(), json!(16000)),
+                ("listener.wake_word.name".to_string(), json!("hey mycroft")),
+                ("servers.0.host".to_string(), json!("a")),
+                ("servers.1".to_string(), json!("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_leaves_uppercases_strings() {
+        let conf = conf(json!({
+            "lang": "en-us",
+            "listener": {"sample_rate": 16000, "wake_word": "hey mycroft"},
+            "servers": ["a", "b"]
+        }));
+        conf.map_leaves(|_, value| match value {
+            Value::String(s) => Value::String(s.to_uppercase()),
+            other => other.clone(),
+        })
+        .unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("EN-US")));
+        assert_eq!(conf.get("listener.wake_word"), Some(json!("HEY MYCROFT")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(16000)));
+        assert_eq!(conf.get("servers"), Some(json!(["A", "B"])));
+    }
+
+    #[test]
+    fn apply_bus_patch_merges_into_user_layer() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{"listener": {"sample_rate": 16000, "mute": false}}"#,
+            );
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            conf.apply_bus_patch(&json!({
+                "type": "configuration.patch",
+                "data": {"listener": {"sample_rate": 48000}, "lang": "de-de"}
+            }))
+            .unwrap();
+            assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+            assert_eq!(conf.get("listener.mute"), Some(json!(false)));
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        });
+    }
+
+    #[test]
+    fn apply_bus_patch_ignores_other_types() {
+        isolated(|root| {
+            write_file(&user_config(root), r#"{"lang": "en-us"}"#);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            conf.apply_bus_patch(&json!({
+                "type": "configuration.updated",
+                "data": {"lang": "de-de"}
+            }))
+            .unwrap();
+            assert_eq!(conf.get("lang"), Some(json!("en-us")));
+        });
+    }
+
+    #[test]
+    fn apply_bus_patch_without_user_layer_is_read_only() {
+        isolated(|root| {
+            let system = root.join("etc/mycroft/mycroft.conf");
+            write_file(&system, r#"{"lang": "en-us"}"#);
+            let conf = Configuration::with_layers(
+                vec![ConfigLayer::System],
+                vec![(ConfigLayer::System, LocalConf::unloaded(Some(system)))],
+                None,
+            );
+            conf.try_load_all().unwrap();
+
+            assert!(matches!(
+                conf.apply_bus_patch(&json!({
+                    "type": "configuration.patch",
+                    "data": {"lang": "de-de"}
+                })),
+                Err(ConfigError::ReadOnly)
+            ));
+            assert_eq!(conf.get("lang"), Some(json!("en-us")));
+        });
+    }
+
+    #[test]
+    fn apply_bus_patch_rejects_malformed_envelopes() {
+        isolated(|_| {
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            for message in [
+                json!("configuration.patch"),
+                json!({"data": {"lang": "de-de"}}),
+                json!({"type": "configuration.patch"}),
+                json!({"type": "configuration.patch", "data": ["lang"]}),
+            ] {
+                assert!(matches!(
+                    conf.apply_bus_patch(&message),
+                    Err(ConfigError::InvalidMessage(_))
+                ));
+            }
+            assert_eq!(conf.get("lang"), None);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn set_locked_keeps_concurrent_writers_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, "{}");
+        // Loaded up front, as only set_locked reads under the lock
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| (name, LocalConf::new(Some(path.clone()))))
+            .map(|(name, conf)| {
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        conf.set_locked(
+                            &format!("{}{}", name, i),
+                            json!(i),
+                            Duration::from_secs(10),
+                        )
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let stored = LocalConf::new(Some(path));
+        for i in 0..20 {
+            assert_eq!(stored.get(&format!("a{}", i)), Some(json!(i)));
+            assert_eq!(stored.get(&format!("b{}", i)), Some(json!(i)));
+        }
+    }
+
+    #[test]
+    fn set_locked_does_not_resurrect_deleted_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us", "old": true}"#);
+        let conf = LocalConf::new(Some(path.clone()));
+        // Another process drops "old"
+        write_file(&path, r#"{"lang": "en-us"}"#);
+
+        conf.set_locked("lang", json!("de-de"), Duration::from_secs(1))
+            .unwrap();
+        let stored = LocalConf::new(Some(path));
+        assert_eq!(stored.get("lang"), Some(json!("de-de")));
+        assert_eq!(stored.get("old"), None);
+    }
+
+    #[test]
+    fn preview_with_leaves_live_config_alone() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{"listener": {"sample_rate": 16000, "mute": false}}"#,
+            );
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+
+            let preview = conf.preview_with(dict(
+                r#"{"listener": {"sample_rate": 48000}, "lang": "de-de"}"#,
+            ));
+            assert_eq!(
+                preview["listener"],
+                json!({"sample_rate": 48000, "mute": false})
+            );
+            assert_eq!(preview["lang"], json!("de-de"));
+
+            assert_eq!(conf.get("listener.sample_rate"), Some(json!(16000)));
+            assert_eq!(conf.get("lang"), None);
+            assert_eq!(
+                std::fs::read_to_string(user_config(root)).unwrap(),
+                r#"{"listener": {"sample_rate": 16000, "mute": false}}"#
+            );
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn load_local_async_matches_sync_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("mycroft.conf");
+        write_file(
+            &json_path,
+            "{\n  // Wake word engine\n  \"listener\": {\"sample_rate\": 16000},\n  \"lang\": \"en-us\"\n}\n",
+        );
+        let yaml_path = dir.path().join("mycroft.yaml");
+        write_file(&yaml_path, "listener:\n  sample_rate: 48000\nlang: de-de\n");
+
+        for path in [json_path, yaml_path] {
+            let sync = LocalConf::new(Some(path.clone()));
+            let loaded = LocalConf::unloaded(Some(path));
+            loaded.load_local_async(None).await.unwrap();
+            assert_eq!(*loaded.data.read(), *sync.data.read());
+            assert!(loaded.last_loaded().is_some());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn load_all_async_matches_sync_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.conf");
+        write_file(
+            &system,
+            r#"{"lang": "en-us", "listener": {"sample_rate": 16000}}"#,
+        );
+        let user = dir.path().join("user.conf");
+        write_file(&user, r#"{"listener": {"sample_rate": 48000}}"#);
+        let build = || {
+            Configuration::with_layers(
+                vec![ConfigLayer::System, ConfigLayer::User],
+                vec![
+                    (
+                        ConfigLayer::System,
+                        LocalConf::unloaded(Some(system.clone())),
+                    ),
+                    (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+                ],
+                None,
+            )
+        };
+
+        let sync = build();
+        sync.try_load_all().unwrap();
+        let loaded = build();
+        loaded.load_all_async().await.unwrap();
+        assert_eq!(*loaded.data.read(), *sync.data.read());
+        assert_eq!(loaded.get("listener.sample_rate"), Some(json!(48000)));
+        assert_eq!(loaded.get("lang"), Some(json!("en-us")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle_is_reported_as_a_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.conf");
+        let b = dir.path().join("b.conf");
+        std::os::unix::fs::symlink(&b, &a).unwrap();
+        std::os::unix::fs::symlink(&a, &b).unwrap();
+
+        let conf = LocalConf::unloaded(Some(a.clone()));
+        assert!(matches!(
+            conf.try_load_local(None),
+            Err(ConfigError::SymlinkLoop { ref path }) if *path == a
+        ));
+
+        let user = dir.path().join("user.conf");
+        write_file(&user, r#"{"lang": "de-de"}"#);
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::System, ConfigLayer::User],
+            vec![
+                (ConfigLayer::System, LocalConf::unloaded(Some(a.clone()))),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user))),
+            ],
+            None,
+        );
+        let errors = conf.try_load_all().unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [ConfigError::SymlinkLoop { ref path }] if *path == a
+        ));
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn get_array_deserializes_each_element() {
+        let conf = conf(json!({
+            "skills": {"blacklist": ["skill-a", "skill-b"]},
+            "ports": [8181, 8080],
+            "mixed": ["skill-a", 3],
+            "lang": "en-us"
+        }));
+        assert_eq!(
+            conf.get_array::<String>("skills.blacklist"),
+            Some(vec!["skill-a".to_string(), "skill-b".to_string()])
+        );
+        assert_eq!(conf.get_array::<u32>("ports"), Some(vec![8181, 8080]));
+        assert_eq!(conf.get_array::<String>("mixed"), None);
+        assert_eq!(conf.get_array::<u32>("mixed"), None);
+        assert_eq!(conf.get_array::<String>("lang"), None);
+        assert_eq!(conf.get_array::<String>("missing"), None);
+    }
+
+    #[test]
+    fn deprecated_keys_flags_only_deprecated_paths() {
+        register_deprecation("listener.wake_word_name", "listener.wake_word");
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default.conf");
+        write_file(&default, r#"{"padatious": {"cache": "/tmp"}}"#);
+        let user = dir.path().join("user.conf");
+        let layers = |user_contents: &str| {
+            write_file(&user, user_contents);
+            let conf = Configuration::with_layers(
+                vec![ConfigLayer::Default, ConfigLayer::User],
+                vec![
+                    (
+                        ConfigLayer::Default,
+                        LocalConf::unloaded(Some(default.clone())),
+                    ),
+                    (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+                ],
+                None,
+            );
+            conf.try_load_all().unwrap();
+            conf
+        };
+
+        let modern = layers(r#"{"intents": {"padatious": {}}, "listener": {"wake_word": "hey"}}"#);
+        assert!(modern.deprecated_keys().is_empty());
+
+        let old = layers(r#"{"padatious": {}, "listener": {"wake_word_name": "hey"}}"#);
+        assert_eq!(
+            old.deprecated_keys(),
+            [
+                ("padatious".to_string(), "intents.padatious".to_string()),
+                (
+                    "listener.wake_word_name".to_string(),
+                    "listener.wake_word".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_config_for_applies_platform_overlays() {
+        let generic = EMBEDDED_DEFAULT_CONFIG.clone();
+        assert_eq!(
+            get_path(&generic, "listener.hybrid_listen"),
+            Some(&json!(false))
+        );
+        assert_eq!(
+            get_path(&generic, "websocket.host"),
+            Some(&json!("127.0.0.1"))
+        );
+        assert_eq!(generic.get("play_wav_cmdline"), None);
+
+        let mark2 = default_config_for("mark2");
+        assert_eq!(
+            get_path(&mark2, "listener.hybrid_listen"),
+            Some(&json!(true))
+        );
+        assert_eq!(
+            get_path(&mark2, "listener.listen_timeout"),
+            get_path(&generic, "listener.listen_timeout")
+        );
+
+        let raspbian = default_config_for("raspbian");
+        assert_eq!(raspbian.get("play_wav_cmdline"), Some(&json!("aplay %1")));
+
+        let docker = default_config_for("docker");
+        assert_eq!(get_path(&docker, "websocket.host"), Some(&json!("0.0.0.0")));
+        assert_eq!(
+            get_path(&docker, "websocket.port"),
+            get_path(&generic, "websocket.port")
+        );
+    }
+
+    #[test]
+    fn default_config_for_unknown_platform_is_generic() {
+        assert_eq!(default_config_for("generic"), *EMBEDDED_DEFAULT_CONFIG);
+        assert_eq!(default_config_for("amiga"), *EMBEDDED_DEFAULT_CONFIG);
+    }
+
+    #[test]
+    fn current_platform_prefers_ovos_platform() {
+        let _env = crate::testing::env_lock();
+        let saved = env::var_os("OVOS_PLATFORM");
+        env::set_var("OVOS_PLATFORM", "mark2");
+        let platform = current_platform();
+        match saved {
+            Some(value) => env::set_var("OVOS_PLATFORM", value),
+            None => env::remove_var("OVOS_PLATFORM"),
+        }
+        assert_eq!(platform, "mark2");
+    }
+
+    #[test]
+    fn replace_all_is_never_seen_half_done() {
+        let conf = Arc::new(conf(json!({"a": 0, "b": 0})));
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let conf = Arc::clone(&conf);
+                let stop = Arc::clone(&stop);
+                std::thread::spawn(move || loop {
+                    let snapshot = conf.to_btree();
+                    assert_eq!(snapshot.len(), 2);
+                    assert_eq!(snapshot["a"], snapshot["b"]);
+                    if stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        let writers: Vec<_> = (0..2)
+            .map(|w| {
+                let conf = Arc::clone(&conf);
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        let n = i * 2 + w;
+                        conf.replace_all(dict(&format!(r#"{{"a": {n}, "b": {n}}}"#)))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn replace_all_notifies_the_diff() {
+        let conf = conf(json!({"lang": "en-us", "old": true}));
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        conf.subscribe(move |changes| sink.write().extend(changes.iter().cloned()));
+
+        conf.replace_all(dict(r#"{"lang": "de-de", "new": 1}"#))
+            .unwrap();
+        let mut paths: Vec<String> = seen.read().iter().map(|c| c.path().to_string()).collect();
+        paths.sort();
+        assert_eq!(paths, ["lang", "new", "old"]);
+        assert_eq!(conf.get("old"), None);
+    }
+
+    #[test]
+    fn from_env_blob_b64_decodes_json_and_yaml() {
+        let json_blob =
+            BASE64_STANDARD.encode(r#"{"lang": "de-de", "listener": {"sample_rate": 48000}}"#);
+        assert_eq!(
+            from_env_blob_b64(&json_blob).unwrap(),
+            dict(r#"{"lang": "de-de", "listener": {"sample_rate": 48000}}"#)
+        );
+        let yaml_blob = BASE64_STANDARD.encode("lang: de-de\n");
+        assert_eq!(
+            from_env_blob_b64(&yaml_blob).unwrap(),
+            dict(r#"{"lang": "de-de"}"#)
+        );
+        assert!(matches!(
+            from_env_blob_b64("not base64!"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn env_blob_b64_applies_or_is_skipped() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{"lang": "en-us", "listener": {"mute": false}}"#,
+            );
+            let encoded = BASE64_STANDARD.encode(r#"{"lang": "de-de"}"#);
+            env::set_var(ENV_BLOB_B64, &encoded);
+            let valid = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            env::set_var(ENV_BLOB_B64, "%%% not base64 %%%");
+            let invalid = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            env::remove_var(ENV_BLOB_B64);
+
+            assert_eq!(valid.get("lang"), Some(json!("de-de")));
+            assert_eq!(valid.get("listener.mute"), Some(json!(false)));
+            assert_eq!(invalid.get("lang"), Some(json!("en-us")));
+            assert_eq!(invalid.get("listener.mute"), Some(json!(false)));
+        });
+    }
+
+    #[test]
+    fn get_typed_reports_expected_and_actual_types() {
+        let conf = conf(json!({"listener": {"mute": "yes", "sample_rate": 16000}}));
+        assert_eq!(
+            conf.get_typed("listener.sample_rate", JsonType::Number),
+            Ok(json!(16000))
+        );
+
+        let err = conf.get_typed("listener.mute", JsonType::Bool).unwrap_err();
+        assert_eq!(err.expected, JsonType::Bool);
+        assert_eq!(err.actual, Some(JsonType::String));
+        assert_eq!(err.to_string(), "listener.mute: expected bool, got string");
+
+        let err = conf
+            .get_typed("listener.muted", JsonType::Bool)
+            .unwrap_err();
+        assert_eq!(err.actual, None);
+        assert_eq!(
+            err.to_string(),
+            "listener.muted: expected bool, but it is not set"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_paths_converts_windows_separators() {
+        let conf = conf(json!({
+            "data_dir": "C:\\ovos\\data",
+            "listener": {"save_path": "D:\\recordings", "wake_word": "hey\\mycroft"},
+            "extra_paths": {"skills": ["C:\\skills", "D:\\more\\skills"]},
+            "lang": "en\\us"
+        }));
+        conf.normalize_paths(&["data_dir", "listener.save_path", "extra_paths"])
+            .unwrap();
+        assert_eq!(conf.get("data_dir"), Some(json!("C:/ovos/data")));
+        assert_eq!(conf.get("listener.save_path"), Some(json!("D:/recordings")));
+        assert_eq!(
+            conf.get("extra_paths.skills"),
+            Some(json!(["C:/skills", "D:/more/skills"]))
+        );
+        // Keys outside the list are left alone, as is a key that only shares
+        // a prefix with a listed one
+        assert_eq!(conf.get("listener.wake_word"), Some(json!("hey\\mycroft")));
+        assert_eq!(conf.get("lang"), Some(json!("en\\us")));
+        assert!(!is_under_any("data_directory", &["data_dir"]));
+    }
+
+    #[test]
+    fn in_memory_serves_seed_data_without_a_location() {
+        let conf = LocalConf::in_memory(dict(
+            r#"{"lang": "de-de", "listener": {"sample_rate": 48000}}"#,
+        ));
+        assert_eq!(conf.path(), None);
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+
+        conf.set("lang", json!("fr-fr")).unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("fr-fr")));
+        assert!(matches!(
+            conf.store(None),
+            Err(ConfigError::MissingLocation)
+        ));
+    }
+
+    #[test]
+    fn migrate_legacy_user_config_copies_to_xdg() {
+        isolated(|root| {
+            let legacy = root.join("home/.mycroft/mycroft.conf");
+            write_file(&legacy, r#"{"lang": "de-de"}"#);
+
+            let migrated = without_home(Configuration::migrate_legacy_user_config).unwrap();
+            assert_eq!(migrated, Some(user_config(root)));
+            assert_eq!(
+                std::fs::read_to_string(user_config(root)).unwrap(),
+                r#"{"lang": "de-de"}"#
+            );
+            assert!(legacy.exists());
+            let note =
+                std::fs::read_to_string(root.join("home/.mycroft/mycroft.conf.migrated")).unwrap();
+            assert!(note.contains(&user_config(root).display().to_string()));
+        });
+    }
+
+    #[test]
+    fn migrate_legacy_user_config_keeps_existing_xdg_config() {
+        isolated(|root| {
+            assert_eq!(
+                without_home(Configuration::migrate_legacy_user_config).unwrap(),
+                None
+            );
+
+            write_file(
+                &root.join("home/.mycroft/mycroft.conf"),
+                r#"{"lang": "de-de"}"#,
+            );
+            write_file(&user_config(root), r#"{"lang": "fr-fr"}"#);
+            assert_eq!(
+                without_home(Configuration::migrate_legacy_user_config).unwrap(),
+                None
+            );
+            assert_eq!(
+                std::fs::read_to_string(user_config(root)).unwrap(),
+                r#"{"lang": "fr-fr"}"#
+            );
+            assert!(!root.join("home/.mycroft/mycroft.conf.migrated").exists());
+        });
+    }
+
+    #[test]
+    fn store_to_unwritable_path_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // A regular file where a directory is needed fails even for root
+        let blocker = dir.path().join("not-a-dir");
+        write_file(&blocker, "");
+        let path = blocker.join("mycroft.conf");
+
+        let conf = LocalConf::in_memory(dict(r#"{"lang": "de-de"}"#));
+        assert!(matches!(conf.store(Some(&path)), Err(ConfigError::Io(_))));
+
+        let writable = ReadOnlyConfig::new(path.clone(), true);
+        writable.inner.set("lang", json!("de-de")).unwrap();
+        assert!(matches!(writable.store(None), Err(ConfigError::Io(_))));
+        let read_only = ReadOnlyConfig::new(path, false);
+        assert!(matches!(read_only.store(None), Err(ConfigError::ReadOnly)));
+    }
+
+    #[test]
+    fn merge_struct_round_trips_through_get_section() {
+        #[derive(Debug, Default, PartialEq, Serialize, serde::Deserialize)]
+        struct Settings {
+            volume: u8,
+            stations: Vec<String>,
+        }
+
+        let conf = conf(json!({"skills": {"my": {"skill": {"enabled": true}}}}));
+        let settings = Settings {
+            volume: 7,
+            stations: vec!["radio one".to_string()],
+        };
+        conf.merge_struct("skills.my.skill", &settings).unwrap();
+
+        assert_eq!(
+            conf.get_section::<Settings>("skills.my.skill"),
+            Some(settings)
+        );
+        assert_eq!(conf.get("skills.my.skill.enabled"), Some(json!(true)));
+    }
+
+    #[test]
+    fn unchanged_values_fire_no_change_event() {
+        let conf = conf(json!({"lang": "en-us", "listener": {"sample_rate": 16000}}));
+        let events = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        conf.subscribe(move |changes| sink.write().push(changes.to_vec()));
+
+        conf.set("lang", json!("en-us")).unwrap();
+        conf.merge(&dict(r#"{"lang": "en-us"}"#)).unwrap();
+        conf.merge_deep(&dict(r#"{"listener": {"sample_rate": 16000}}"#))
+            .unwrap();
+        assert!(events.read().is_empty());
+
+        conf.set("lang", json!("de-de")).unwrap();
+        assert_eq!(
+            *events.read(),
+            [vec![ConfigChange::Changed {
+                path: "lang".to_string(),
+                old: json!("en-us"),
+                new: json!("de-de"),
+            }]]
+        );
+    }
+
+    #[test]
+    fn xdg_tokens_resolve_to_xdg_dirs() {
+        isolated(|root| {
+            let mut config = dict(
+                r#"{
+                    "intents": {"cache": "{xdg_cache_home}/intents"},
+                    "data_dir": "{xdg_data_home}/mycroft",
+                    "paths": ["{xdg_config_home}/mycroft", "{xdg_state_home}/logs"],
+                    "literal": "{xdg_music_dir}/{home}",
+                    "port": 8181
+                }"#,
+            );
+            resolve_xdg_tokens(&mut config);
+            let path = |p: &str| json!(root.join(p).to_string_lossy());
+            assert_eq!(
+                get_path(&config, "intents.cache"),
+                Some(&path("cache/intents"))
+            );
+            assert_eq!(config["data_dir"], path("data/mycroft"));
+            assert_eq!(
+                config["paths"],
+                json!([path("config/mycroft"), path("state/logs")])
+            );
+            assert_eq!(config["literal"], json!("{xdg_music_dir}/{home}"));
+            assert_eq!(config["port"], json!(8181));
+        });
+    }
+
+    #[test]
+    fn xdg_tokens_resolve_at_load_time() {
+        isolated(|root| {
+            write_file(
+                &user_config(root),
+                r#"{"data_dir": "{xdg_data_home}/mycroft"}"#,
+            );
+            let conf = LocalConf::new(Some(user_config(root)));
+            assert_eq!(
+                conf.get("data_dir"),
+                Some(json!(root.join("data/mycroft").to_string_lossy()))
+            );
+        });
+    }
+
+    #[test]
+    fn require_file_fails_for_a_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("mycroft.conf");
+        assert!(matches!(
+            LocalConf::builder(Some(missing.clone()))
+                .require_file(true)
+                .build(),
+            Err(ConfigError::MissingLocation)
+        ));
+        assert!(matches!(
+            LocalConf::builder(None).require_file(true).build(),
+            Err(ConfigError::MissingLocation)
+        ));
+
+        write_file(&missing, r#"{"lang": "de-de"}"#);
+        let conf = LocalConf::builder(Some(missing))
+            .require_file(true)
+            .build()
+            .unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn missing_file_starts_empty_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = LocalConf::builder(Some(dir.path().join("mycroft.conf")))
+            .build()
+            .unwrap();
+        assert!(conf.data.read().is_empty());
+    }
+
+    #[test]
+    fn get_section_or_default_falls_back() {
+        #[derive(Debug, Default, PartialEq, serde::Deserialize)]
+        struct Listener {
+            sample_rate: u32,
+            mute: bool,
+        }
+
+        let conf = conf(json!({
+            "listener": {"sample_rate": 48000, "mute": true},
+            "broken": {"sample_rate": "fast", "mute": true}
+        }));
+        assert_eq!(
+            conf.get_section_or_default::<Listener>("listener"),
+            Listener {
+                sample_rate: 48000,
+                mute: true
+            }
+        );
+        assert_eq!(
+            conf.get_section_or_default::<Listener>("missing"),
+            Listener::default()
+        );
+        assert_eq!(
+            conf.get_section_or_default::<Listener>("broken"),
+            Listener::default()
+        );
+    }
+
+    #[test]
+    fn with_layer_order_changes_precedence() {
+        isolated(|root| {
+            write_file(
+                &root.join("etc/xdg/mycroft/mycroft.conf"),
+                r#"{"lang": "de-de", "kiosk": true}"#,
+            );
+            write_file(&user_config(root), r#"{"lang": "fr-fr"}"#);
+
+            let standard =
+                Configuration::with_layer_order(vec![ConfigLayer::Xdg, ConfigLayer::User]);
+            assert_eq!(standard.get("lang"), Some(json!("fr-fr")));
+
+            let kiosk = Configuration::with_layer_order(vec![
+                ConfigLayer::User,
+                ConfigLayer::Xdg,
+                ConfigLayer::User,
+            ]);
+            assert_eq!(kiosk.layer_order(), [ConfigLayer::User, ConfigLayer::Xdg]);
+            assert_eq!(kiosk.get("lang"), Some(json!("de-de")));
+            assert_eq!(kiosk.get("kiosk"), Some(json!(true)));
+        });
+    }
+
+    #[test]
+    fn load_from_reader_parses_and_merges() {
+        let conf = conf(json!({"lang": "en-us", "listener": {"mute": false}}));
+        let json_input =
+            "// piped in\n{\"lang\": \"de-de\", \"listener\": {\"sample_rate\": 48000}}\n";
+        conf.load_from_reader(json_input.as_bytes(), Format::Json)
+            .unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+
+        conf.load_from_reader("tts:\n  module: mimic\n".as_bytes(), Format::Yaml)
+            .unwrap();
+        assert_eq!(conf.get("tts.module"), Some(json!("mimic")));
+
+        let before = conf.to_btree();
+        conf.load_from_reader(" \n".as_bytes(), Format::Json)
+            .unwrap();
+        assert_eq!(conf.to_btree(), before);
+        assert!(matches!(
+            conf.load_from_reader("{lang".as_bytes(), Format::Json),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn get_glob_matches_single_and_recursive_segments() {
+        let conf = conf(json!({
+            "lang": "en-us",
+            "tts": {
+                "module": "mimic",
+                "mimic": {"voice": "ap"},
+                "piper": {"voice": "alan", "model": {"voice": "nested"}}
+            },
+            "skills": {"weather": {"lang": "de-de"}}
+        }));
+        let paths = |pattern: &str| -> Vec<String> {
+            conf.get_glob(pattern)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect()
+        };
+
+        assert_eq!(
+            conf.get_glob("tts.*.voice"),
+            [
+                ("tts.mimic.voice".to_string(), json!("ap")),
+                ("tts.piper.voice".to_string(), json!("alan")),
+            ]
+        );
+        assert_eq!(paths("**.lang"), ["lang", "skills.weather.lang"]);
+        assert_eq!(
+            paths("tts.**.voice"),
+            [
+                "tts.mimic.voice",
+                "tts.piper.model.voice",
+                "tts.piper.voice"
+            ]
+        );
+        assert!(conf.get_glob("stt.*.voice").is_empty());
+    }
 
-    let config: ConfigDict = serde_json::from_str(&json_str)?;
-    Ok(config)
+    #[test]
+    fn non_finite_yaml_floats_follow_the_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("mycroft.yaml");
+        write_file(&source, "listener:\n  gain: .nan\n  levels: [1.5, -.inf]\n");
+
+        let nulled = LocalConf::new(Some(source.clone()));
+        assert_eq!(nulled.get("listener.gain"), Some(Value::Null));
+        assert_eq!(nulled.get("listener.levels"), Some(json!([1.5, null])));
+        let stored = dir.path().join("stored.json");
+        nulled.store(Some(&stored)).unwrap();
+        assert_eq!(
+            LocalConf::new(Some(stored)).get("listener"),
+            Some(json!({"gain": null, "levels": [1.5, null]}))
+        );
+
+        let strict = LocalConf::builder(Some(source))
+            .non_finite(NonFinitePolicy::Error)
+            .build();
+        assert!(matches!(
+            strict,
+            Err(ConfigError::NonFinite { ref path }) if path == "listener.gain"
+        ));
+    }
+
+    #[test]
+    fn hash_reload_skips_touched_but_unchanged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us"}"#);
+        let conf = LocalConf::builder(Some(path.clone()))
+            .reload_by_hash(true)
+            .build()
+            .unwrap();
+        let touch = |secs| {
+            let file = File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        touch(10);
+        assert!(!conf.reload_checked().unwrap());
+        // A second touch is compared against the first one's mtime
+        touch(20);
+        assert!(!conf.reload_checked().unwrap());
+
+        write_file(&path, r#"{"lang": "de-de"}"#);
+        touch(30);
+        assert!(conf.reload_checked().unwrap());
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn sorted_copies_iterate_in_key_order() {
+        let text = r#"{"zeta": 1, "alpha": {"y": [{"b": 1, "a": 2}], "x": 0}, "mid": true}"#;
+        for _ in 0..5 {
+            // Fresh HashMaps get fresh random hash seeds
+            let conf = LocalConf::in_memory(dict(text));
+            let keys: Vec<String> = conf.to_btree().into_keys().collect();
+            assert_eq!(keys, ["alpha", "mid", "zeta"]);
+            assert_eq!(
+                serde_json::to_string(&conf.to_sorted_value()).unwrap(),
+                r#"{"alpha":{"x":0,"y":[{"a":2,"b":1}]},"mid":true,"zeta":1}"#
+            );
+        }
+    }
+
+    #[test]
+    fn merge_reporting_lists_overwritten_values() {
+        let conf =
+            conf(json!({"lang": "en-us", "listener": {"sample_rate": 16000, "mute": false}}));
+        let conflicts = conf
+            .merge_reporting(&dict(
+                r#"{"lang": "de-de", "listener": {"sample_rate": 16000, "mute": true, "vad": "silero"}}"#,
+            ))
+            .unwrap();
+        let mut conflicts: Vec<_> = conflicts
+            .into_iter()
+            .map(|c| (c.path, c.old, c.new))
+            .collect();
+        conflicts.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            conflicts,
+            [
+                ("lang".to_string(), json!("en-us"), json!("de-de")),
+                ("listener.mute".to_string(), json!(false), json!(true)),
+            ]
+        );
+        assert_eq!(conf.get("listener.vad"), Some(json!("silero")));
+
+        let disjoint = conf
+            .merge_reporting(&dict(
+                r#"{"tts": {"module": "mimic"}, "listener": {"wake_word": "hey"}}"#,
+            ))
+            .unwrap();
+        assert!(disjoint.is_empty());
+        assert_eq!(conf.get("tts.module"), Some(json!("mimic")));
+    }
+
+    #[test]
+    fn with_override_is_visible_inside_and_reverted_after() {
+        let conf = conf(json!({"lang": "en-us", "listener": {"mute": false}}));
+        let inside = conf.with_override("lang", json!("de-de"), || conf.get("lang"));
+        assert_eq!(inside, Some(json!("de-de")));
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+
+        // A key that didn't exist, below a parent that didn't either, is
+        // removed along with the parent
+        conf.with_override("tts.module", json!("mimic"), || {
+            assert_eq!(conf.get("tts.module"), Some(json!("mimic")));
+        });
+        assert_eq!(conf.get("tts"), None);
+        assert_eq!(conf.get("listener"), Some(json!({"mute": false})));
+    }
+
+    #[test]
+    fn with_override_is_reverted_on_panic() {
+        let conf = conf(json!({"listener": {"mute": false}}));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            conf.with_override("listener.mute", json!(true), || {
+                assert_eq!(conf.get("listener.mute"), Some(json!(true)));
+                panic!("test panic");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(conf.get("listener.mute"), Some(json!(false)));
+    }
+
+    #[test]
+    fn schema_metadata_describes_known_keys() {
+        let fields = schema_metadata();
+        let field = |path: &str| fields.iter().find(|f| f.path == path).cloned().unwrap();
+
+        assert_eq!(
+            field("lang"),
+            FieldMeta {
+                path: "lang".to_string(),
+                kind: JsonType::String,
+                default: json!("en-us"),
+            }
+        );
+        assert_eq!(field("listener.sample_rate").kind, JsonType::Number);
+        assert_eq!(field("listener.sample_rate").default, json!(16000));
+        assert_eq!(field("listener.hybrid_listen").kind, JsonType::Bool);
+        assert_eq!(field("ready_settings").kind, JsonType::Array);
+        assert_eq!(field("ready_settings").default, json!(["skills"]));
+        // Objects are walked into rather than listed
+        assert!(fields.iter().all(|f| f.path != "listener"));
+        assert!(fields.windows(2).all(|w| w[0].path < w[1].path));
+    }
+
+    #[test]
+    fn try_reload_skips_when_the_lock_is_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us"}"#);
+        let conf = LocalConf::new(Some(path.clone()));
+        write_file(&path, r#"{"lang": "de-de"}"#);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+
+        let held = conf.data.write();
+        let contended = std::thread::scope(|s| s.spawn(|| conf.try_reload()).join().unwrap());
+        assert!(contended.is_none());
+        drop(held);
+
+        assert!(matches!(conf.try_reload(), Some(Ok(true))));
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn dump_yaml_is_sorted_valid_yaml_with_secrets_masked() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("mycroft.conf");
+        write_file(
+            &user,
+            r#"{"tts": {"module": "azure", "azure": {"api_key": "s3cret", "voice": "jenny"}}, "lang": "en-us"}"#,
+        );
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::User],
+            vec![(ConfigLayer::User, LocalConf::unloaded(Some(user)))],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        let dump = conf.dump_yaml().unwrap();
+        assert!(!dump.contains("s3cret"));
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&dump).unwrap();
+        assert_eq!(
+            parsed["tts"]["azure"]["api_key"],
+            serde_yaml::Value::from("***")
+        );
+        assert_eq!(
+            parsed["tts"]["azure"]["voice"],
+            serde_yaml::Value::from("jenny")
+        );
+        let top_level: Vec<&str> = dump
+            .lines()
+            .filter(|line| !line.starts_with(' '))
+            .map(|line| line.split(':').next().unwrap())
+            .collect();
+        let mut sorted = top_level.clone();
+        sorted.sort();
+        assert_eq!(top_level, sorted);
+        assert!(dump.find("  azure:").unwrap() < dump.find("  module:").unwrap());
+    }
+
+    #[test]
+    fn writes_only_reach_the_user_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer_file = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            write_file(&path, contents);
+            path
+        };
+        let default = layer_file("default.conf", r#"{"lang": "en-us"}"#);
+        let system = layer_file("system.conf", r#"{"listener": {"mute": false}}"#);
+        let user = dir.path().join("user/mycroft.conf");
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::Default, ConfigLayer::System, ConfigLayer::User],
+            vec![
+                (
+                    ConfigLayer::Default,
+                    LocalConf::unloaded(Some(default.clone())),
+                ),
+                (
+                    ConfigLayer::System,
+                    LocalConf::unloaded(Some(system.clone())),
+                ),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+            ],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        conf.set("lang", json!("de-de")).unwrap();
+        conf.set("listener.mute", json!(true)).unwrap();
+        conf.store().unwrap();
+        for layer in [ConfigLayer::Default, ConfigLayer::System] {
+            assert!(matches!(
+                conf.set_in(layer, "lang", json!("fr-fr")),
+                Err(ConfigError::ReadOnly)
+            ));
+        }
+
+        assert_eq!(
+            LocalConf::new(Some(user)).to_sorted_value(),
+            json!({"lang": "de-de", "listener": {"mute": true}})
+        );
+        assert_eq!(
+            std::fs::read_to_string(default).unwrap(),
+            r#"{"lang": "en-us"}"#
+        );
+        assert_eq!(
+            std::fs::read_to_string(system).unwrap(),
+            r#"{"listener": {"mute": false}}"#
+        );
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn env_namespace_keeps_matching_vars_as_strings() {
+        let vars = [
+            ("OVOS_LANG".to_string(), "de-de".to_string()),
+            ("OVOS_PORT".to_string(), "8181".to_string()),
+            ("HOME".to_string(), "/home/ovos".to_string()),
+        ];
+        assert_eq!(
+            env_namespace("OVOS_", "settings", vars),
+            dict(r#"{"settings": {"OVOS_LANG": "de-de", "OVOS_PORT": "8181"}}"#)
+        );
+    }
+
+    #[test]
+    fn include_env_exposes_vars_under_the_namespace() {
+        isolated(|_| {
+            env::set_var("OVOSTEST_LANG", "de-de");
+            env::set_var("OVOSTEST_PORT", "8181");
+            let conf =
+                Configuration::with_layer_order(vec![ConfigLayer::User]).include_env("OVOSTEST_");
+            let custom = Configuration::with_layer_order(vec![ConfigLayer::User])
+                .include_env_under("OVOSTEST_", "vars");
+            env::remove_var("OVOSTEST_LANG");
+            env::remove_var("OVOSTEST_PORT");
+
+            assert_eq!(
+                conf.get(ENV_NAMESPACE_KEY),
+                Some(json!({"OVOSTEST_LANG": "de-de", "OVOSTEST_PORT": "8181"}))
+            );
+            assert_eq!(custom.get("vars.OVOSTEST_PORT"), Some(json!("8181")));
+            assert_eq!(custom.get(ENV_NAMESPACE_KEY), None);
+        });
+    }
+
+    #[test]
+    fn reads_are_not_stale_after_writes_or_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us", "listener": {"mute": false}}"#);
+        let conf = LocalConf::new(Some(path.clone()));
+        let touch = |contents: &str, secs| {
+            write_file(&path, contents);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+        conf.set("lang", json!("de-de")).unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        conf.merge(&dict(r#"{"lang": "fr-fr"}"#)).unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("fr-fr")));
+        assert_eq!(conf.get("listener.mute"), Some(json!(false)));
+        conf.remove("listener").unwrap();
+        assert_eq!(conf.get("listener.mute"), None);
+
+        touch(r#"{"lang": "it-it"}"#, 10);
+        conf.reload();
+        assert_eq!(conf.get("lang"), Some(json!("it-it")));
+
+        touch(r#"{"lang": "es-es"}"#, 20);
+        assert!(matches!(conf.try_reload(), Some(Ok(true))));
+        assert_eq!(conf.get("lang"), Some(json!("es-es")));
+    }
+
+    #[test]
+    fn from_first_existing_adopts_the_first_file_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a/mycroft.conf");
+        let second = dir.path().join("b/mycroft.conf");
+        let third = dir.path().join("c/mycroft.conf");
+        write_file(&second, r#"{"lang": "de-de"}"#);
+        write_file(&third, r#"{"lang": "fr-fr"}"#);
+        // A directory at a config path is skipped like a missing file
+        std::fs::create_dir_all(&first).unwrap();
+
+        let conf = LocalConf::from_first_existing(&[first.clone(), second.clone(), third]).unwrap();
+        assert_eq!(conf.path(), Some(second.as_path()));
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+
+        assert!(matches!(
+            LocalConf::from_first_existing(&[first, dir.path().join("missing.conf")]),
+            Err(ConfigError::MissingLocation)
+        ));
+    }
+
+    #[test]
+    fn store_header_is_written_and_skipped_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["mycroft.conf", "mycroft.yaml"] {
+            let path = dir.path().join(name);
+            let conf = LocalConf::builder(Some(path.clone()))
+                .store_header("Managed by OVOS - edit with care\n\nSee the docs")
+                .build()
+                .unwrap();
+            conf.set("lang", json!("de-de")).unwrap();
+            conf.store(None).unwrap();
+
+            let contents = std::fs::read_to_string(&path).unwrap();
+            assert!(contents.starts_with("# Managed by OVOS - edit with care\n#\n# See the docs\n"));
+            let reloaded = LocalConf::new(Some(path));
+            assert_eq!(reloaded.to_sorted_value(), json!({"lang": "de-de"}));
+        }
+    }
+
+    #[test]
+    fn big_integers_are_not_truncated() {
+        let conf = LocalConf::in_memory(dict(
+            r#"{"max": 18446744073709551615, "past_i64": 9223372036854775808, "huge": 100000000000000000000, "negative": -1, "fraction": 1.5}"#,
+        ));
+        assert_eq!(conf.get_u64("max"), Some(u64::MAX));
+        assert_eq!(conf.get_i64("max"), None);
+        assert_eq!(conf.get_i128("max"), Some(u64::MAX as i128));
+        assert_eq!(conf.get_u128("max"), Some(u64::MAX as u128));
+
+        assert_eq!(conf.get_i64("past_i64"), None);
+        assert_eq!(conf.get_u64("past_i64"), Some(i64::MAX as u64 + 1));
+        assert_eq!(conf.get_i128("past_i64"), Some(i64::MAX as i128 + 1));
+
+        // Parsed as a float, so no integer getter can return it exactly
+        assert_eq!(conf.get_u64("huge"), None);
+        assert_eq!(conf.get_u128("huge"), None);
+
+        assert_eq!(conf.get_i64("negative"), Some(-1));
+        assert_eq!(conf.get_u64("negative"), None);
+        assert_eq!(conf.get_i128("negative"), Some(-1));
+        assert_eq!(conf.get_u128("negative"), None);
+        assert_eq!(conf.get_i64("fraction"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sighup_reloads_until_the_guard_is_dropped() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        with_config_home(dir.path(), || {
+            let path = dir.path().join("mycroft/mycroft.conf");
+            let rewrite = |contents: &str, secs| {
+                write_file(&path, contents);
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(SystemTime::now() + Duration::from_secs(secs))
+                    .unwrap();
+            };
+            write_file(&path, r#"{"lang": "en-us"}"#);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            let events = Arc::new(RwLock::new(Vec::new()));
+            let sink = Arc::clone(&events);
+            conf.subscribe(move |changes| sink.write().extend(changes.iter().cloned()));
+            let guard = conf.install_sighup_reload().unwrap();
+
+            rewrite(r#"{"lang": "de-de"}"#, 10);
+            unsafe { libc::raise(libc::SIGHUP) };
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while conf.get("lang") != Some(json!("de-de")) && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+            assert_eq!(
+                *events.read(),
+                [ConfigChange::Changed {
+                    path: "lang".to_string(),
+                    old: json!("en-us"),
+                    new: json!("de-de"),
+                }]
+            );
+
+            drop(guard);
+            assert!(SIGHUP_TARGETS.lock().is_empty());
+            rewrite(r#"{"lang": "fr-fr"}"#, 20);
+            unsafe { libc::raise(libc::SIGHUP) };
+            std::thread::sleep(Duration::from_millis(200));
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        });
+    }
+
+    #[test]
+    fn is_dirty_tracks_unsaved_edits_and_outside_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        write_file(&path, r#"{"lang": "en-us"}"#);
+        let conf = LocalConf::new(Some(path.clone()));
+        assert!(!conf.is_dirty());
+
+        conf.set("lang", json!("de-de")).unwrap();
+        assert!(conf.is_dirty());
+        conf.store(None).unwrap();
+        assert!(!conf.is_dirty());
+
+        write_file(&path, r#"{"lang": "fr-fr"}"#);
+        assert!(conf.is_dirty());
+        assert!(!LocalConf::in_memory(dict(r#"{"lang": "en-us"}"#)).is_dirty());
+    }
+
+    #[test]
+    fn load_env_file_applies_dotenv_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("mycroft.conf");
+        write_file(&user, r#"{"lang": "en-us", "listener": {"mute": false}}"#);
+        let env_file = dir.path().join(".env");
+        write_file(
+            &env_file,
+            "# operator overrides\n\
+             OVOS_CONFIG__lang=\"de-de # not a comment\"\n\
+             export OVOS_CONFIG__listener__sample_rate=48000 # Hz\n\
+             OVOS_CONFIG__tts__voice='alan'\n\
+             UNRELATED=1\n",
+        );
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::User],
+            vec![(ConfigLayer::User, LocalConf::unloaded(Some(user)))],
+            None,
+        );
+        conf.try_load_all().unwrap();
+        conf.load_env_file(&env_file).unwrap();
+
+        assert_eq!(conf.get("lang"), Some(json!("de-de # not a comment")));
+        assert_eq!(conf.get("listener.sample_rate"), Some(json!(48000)));
+        assert_eq!(conf.get("listener.mute"), Some(json!(false)));
+        assert_eq!(conf.get("tts.voice"), Some(json!("alan")));
+        assert_eq!(conf.get("UNRELATED"), None);
+
+        write_file(&env_file, "# fine\nOVOS_CONFIG__lang=\"unterminated\n");
+        let err = conf.load_env_file(&env_file).unwrap_err();
+        assert!(err.to_string().contains(":2: unterminated quote"));
+    }
+
+    // Checks that `schema` only uses the draft-07 keywords the generator emits,
+    // with well-formed values
+    fn check_schema(schema: &Value, path: &str) {
+        let schema = schema
+            .as_object()
+            .unwrap_or_else(|| panic!("{}: not an object", path));
+        for (keyword, value) in schema {
+            match keyword.as_str() {
+                "$schema" | "title" => assert!(value.is_string(), "{}: {}", path, keyword),
+                "default" => {}
+                "type" => assert!(
+                    ["null", "boolean", "object", "array", "number", "string", "integer"]
+                        .contains(&value.as_str().unwrap_or_default()),
+                    "{}: bad type {}",
+                    path,
+                    value
+                ),
+                "properties" => {
+                    for (key, child) in value.as_object().unwrap() {
+                        check_schema(child, &format!("{}.{}", path, key));
+                    }
+                }
+                other => panic!("{}: unexpected keyword {}", path, other),
+            }
+        }
+    }
+
+    // Validates `value` against the subset of draft-07 checked above
+    fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        let type_ok = match schema.get("type").and_then(Value::as_str) {
+            None => true,
+            Some("boolean") => value.is_boolean(),
+            Some("number") => value.is_number(),
+            Some("string") => value.is_string(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            Some(other) => return Err(format!("{}: unsupported type {}", path, other)),
+        };
+        if !type_ok {
+            return Err(format!("{}: expected {}", path, schema["type"]));
+        }
+        if let (Some(properties), Some(object)) = (
+            schema.get("properties").and_then(Value::as_object),
+            value.as_object(),
+        ) {
+            for (key, child) in object {
+                if let Some(child_schema) = properties.get(key) {
+                    validate(child_schema, child, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generated_json_schema_is_well_formed_and_accepts_defaults() {
+        let schema = generate_json_schema();
+        assert_eq!(
+            schema["$schema"],
+            json!("http://json-schema.org/draft-07/schema#")
+        );
+        check_schema(&schema, "$");
+
+        let defaults = serde_json::to_value(&*EMBEDDED_DEFAULT_CONFIG).unwrap();
+        validate(&schema, &defaults, "$").unwrap();
+        assert_eq!(
+            schema["properties"]["listener"]["properties"]["sample_rate"]["type"],
+            json!("number")
+        );
+
+        let mut wrong = defaults.clone();
+        wrong["listener"]["sample_rate"] = json!("16000");
+        assert_eq!(
+            validate(&schema, &wrong, "$"),
+            Err("$.listener.sample_rate: expected \"number\"".to_string())
+        );
+        // Unknown keys are allowed
+        wrong["listener"]["sample_rate"] = json!(16000);
+        wrong["my_skill"] = json!({"anything": [1, "two"]});
+        validate(&schema, &wrong, "$").unwrap();
+    }
+
+    #[test]
+    fn yaml_scalars_convert_predictably() {
+        let yaml: serde_yaml::Value = serde_yaml::from_str(
+            "enabled: yes\n\
+             disabled: no\n\
+             on_switch: on\n\
+             real_bool: true\n\
+             updated: 2024-01-01T12:00:00Z\n\
+             day: 2024-01-01\n\
+             big: 18446744073709551615\n\
+             rate: 1.5\n\
+             1: one\n\
+             password: !secret hunter2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            yaml_to_json(yaml),
+            json!({
+                "enabled": "yes",
+                "disabled": "no",
+                "on_switch": "on",
+                "real_bool": true,
+                "updated": "2024-01-01T12:00:00Z",
+                "day": "2024-01-01",
+                "big": u64::MAX,
+                "rate": 1.5,
+                "1": "one",
+                "password": "hunter2"
+            })
+        );
+    }
+
+    #[test]
+    fn apply_merge_patch_follows_rfc_7386() {
+        let conf = conf(json!({
+            "lang": "en-us",
+            "listener": {"sample_rate": 16000, "mute": false, "vad": {"module": "silero"}},
+            "hotwords": ["hey mycroft"],
+            "obsolete": {"a": 1}
+        }));
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        conf.subscribe(move |changes| sink.write().extend(changes.iter().cloned()));
+
+        conf.apply_merge_patch(&json!({
+            "lang": "de-de",
+            "listener": {"mute": null, "vad": {"threshold": 0.5}, "new": {"x": null, "y": 1}},
+            "hotwords": ["wake up"],
+            "obsolete": null,
+            "missing": null
+        }))
+        .unwrap();
+
+        // Objects merge recursively, null deletes, everything else replaces
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        assert_eq!(
+            conf.get("listener"),
+            Some(json!({
+                "sample_rate": 16000,
+                "vad": {"module": "silero", "threshold": 0.5},
+                "new": {"y": 1}
+            }))
+        );
+        assert_eq!(conf.get("hotwords"), Some(json!(["wake up"])));
+        assert_eq!(conf.get("obsolete"), None);
+        assert_eq!(conf.get("missing"), None);
+        assert!(!seen.read().is_empty());
+    }
+
+    #[test]
+    fn apply_merge_patch_replaces_scalars_with_objects_and_back() {
+        let conf = conf(json!({"tts": "mimic", "stt": {"module": "vosk"}}));
+        conf.apply_merge_patch(&json!({"tts": {"module": "piper"}, "stt": "none"}))
+            .unwrap();
+        assert_eq!(conf.get("tts"), Some(json!({"module": "piper"})));
+        assert_eq!(conf.get("stt"), Some(json!("none")));
+
+        // A patch that isn't an object is rejected and changes nothing
+        assert!(matches!(
+            conf.apply_merge_patch(&json!(["tts"])),
+            Err(ConfigError::Parse(_))
+        ));
+        assert_eq!(conf.get("stt"), Some(json!("none")));
+    }
+
+    #[test]
+    fn overrides_vs_distribution_isolates_operator_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer_file = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            write_file(&path, contents);
+            path
+        };
+        let layers = [
+            (
+                ConfigLayer::Default,
+                layer_file(
+                    "default.conf",
+                    r#"{"lang": "en-us", "tts": {"module": "mimic"}}"#,
+                ),
+            ),
+            (
+                ConfigLayer::Distribution,
+                layer_file(
+                    "distribution.conf",
+                    r#"{"tts": {"module": "piper"}, "listener": {"sample_rate": 16000}}"#,
+                ),
+            ),
+            (
+                ConfigLayer::System,
+                layer_file(
+                    "system.conf",
+                    r#"{"listener": {"sample_rate": 48000}, "tts": {"module": "piper"}}"#,
+                ),
+            ),
+            (
+                ConfigLayer::User,
+                layer_file(
+                    "user.conf",
+                    r#"{"lang": "de-de", "hotwords": ["hey ovos"]}"#,
+                ),
+            ),
+        ];
+        let conf = Configuration::with_layers(
+            layers.iter().map(|(layer, _)| *layer).collect(),
+            layers
+                .iter()
+                .map(|(layer, path)| (*layer, LocalConf::unloaded(Some(path.clone()))))
+                .collect(),
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        let mut changes = conf.overrides_vs_distribution();
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        // The distribution's own tts choice, repeated by the system layer,
+        // isn't an override; the sample rate and the user's edits are.
+        assert_eq!(
+            changes,
+            [
+                ConfigChange::Added {
+                    path: "hotwords".to_string(),
+                    value: json!(["hey ovos"]),
+                },
+                ConfigChange::Changed {
+                    path: "lang".to_string(),
+                    old: json!("en-us"),
+                    new: json!("de-de"),
+                },
+                ConfigChange::Changed {
+                    path: "listener.sample_rate".to_string(),
+                    old: json!(16000),
+                    new: json!(48000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_store_is_one_line_and_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dict(
+            r#"{"lang": "en-us", "listener": {"sample_rate": 16000, "mute": false}, "hotwords": ["hey mycroft"]}"#,
+        );
+        let store = |name: &str, pretty: bool| {
+            let path = dir.path().join(name);
+            let conf = LocalConf::builder(Some(path.clone()))
+                .pretty(pretty)
+                .build()
+                .unwrap();
+            conf.replace_all(data.clone()).unwrap();
+            conf.store(None).unwrap();
+            path
+        };
+        let pretty = store("pretty.json", true);
+        let compact = store("compact.json", false);
+
+        let compact_text = std::fs::read_to_string(&compact).unwrap();
+        assert!(!compact_text.trim_end().contains('\n'));
+        assert!(std::fs::read_to_string(&pretty).unwrap().contains('\n'));
+        assert!(compact_text.len() < std::fs::metadata(&pretty).unwrap().len() as usize);
+        assert_eq!(
+            LocalConf::new(Some(compact)).to_sorted_value(),
+            LocalConf::new(Some(pretty)).to_sorted_value()
+        );
+    }
+
+    #[test]
+    fn latin1_file_reports_encoding_error_with_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        // "café" saved as Latin-1: é is the single byte 0xE9
+        std::fs::write(&path, b"{\"location\": \"caf\xe9\"}").unwrap();
+
+        let err = match LocalConf::builder(Some(path.clone())).build() {
+            Err(err) => err,
+            Ok(_) => panic!("latin-1 file loaded"),
+        };
+        assert!(
+            matches!(&err, ConfigError::Encoding { path: p, offset: 17 } if p == &path),
+            "{:?}",
+            err
+        );
+        let message = err.to_string();
+        assert!(message.contains("mycroft.conf"), "{}", message);
+        assert!(message.contains("offset 17"), "{}", message);
+        assert!(message.contains("UTF-8"), "{}", message);
+    }
+
+    #[test]
+    fn ordered_dicts_lists_loaded_layers_in_precedence_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default.conf");
+        let system = dir.path().join("system/mycroft.conf");
+        let user = dir.path().join("user/mycroft.conf");
+        write_file(&default, r#"{"lang": "en-us"}"#);
+        write_file(&user, r#"{"lang": "de-de", "hotwords": ["hey ovos"]}"#);
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::Default, ConfigLayer::System, ConfigLayer::User],
+            vec![
+                (
+                    ConfigLayer::Default,
+                    LocalConf::unloaded(Some(default.clone())),
+                ),
+                // Missing, so it's left out
+                (ConfigLayer::System, LocalConf::unloaded(Some(system))),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+            ],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        assert_eq!(
+            conf.ordered_dicts(),
+            [
+                (ConfigLayer::Default, default, dict(r#"{"lang": "en-us"}"#)),
+                (
+                    ConfigLayer::User,
+                    user,
+                    dict(r#"{"lang": "de-de", "hotwords": ["hey ovos"]}"#)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn watch_picks_up_a_new_fragment_in_a_secondary_config_dir() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let (primary, secondary) = (dir.path().join("etc/xdg"), dir.path().join("opt/xdg"));
+        let previous = env::var_os("XDG_CONFIG_DIRS");
+        env::set_var(
+            "XDG_CONFIG_DIRS",
+            env::join_paths([&primary, &secondary]).unwrap(),
+        );
+        // The watcher thread reads the real environment, not an override
+        with_config_home(&dir.path().join("config"), || {
+            write_file(
+                &primary.join("mycroft/mycroft.conf"),
+                r#"{"lang": "en-us"}"#,
+            );
+            write_file(
+                &dir.path().join("config/mycroft/mycroft.conf"),
+                r#"{"hotwords": ["hey ovos"]}"#,
+            );
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::Xdg, ConfigLayer::User]);
+            assert_eq!(conf.get("tts"), None);
+            let _watcher = conf.watch(Duration::from_millis(20)).unwrap();
+
+            write_file(
+                &secondary.join("mycroft/mycroft.conf"),
+                r#"{"tts": {"module": "piper"}}"#,
+            );
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while conf.get("tts").is_none() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(conf.get("tts.module"), Some(json!("piper")));
+            assert_eq!(conf.get("lang"), Some(json!("en-us")));
+            assert_eq!(conf.get("hotwords"), Some(json!(["hey ovos"])));
+        });
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_DIRS", value),
+            None => env::remove_var("XDG_CONFIG_DIRS"),
+        }
+        xdg::refresh_env_cache();
+    }
+
+    #[test]
+    fn get_or_typed_falls_back_on_mismatch_or_missing_key() {
+        let conf = conf(json!({
+            "listener": {"sample_rate": 16000, "channels": "stereo"},
+            "hotwords": ["hey mycroft", "wake up"]
+        }));
+        assert_eq!(conf.get_or_typed("listener.sample_rate", 48000u32), 16000);
+        assert_eq!(
+            conf.get_or_typed("hotwords", Vec::<String>::new()),
+            ["hey mycroft", "wake up"]
+        );
+        // Mismatching type
+        assert_eq!(conf.get_or_typed("listener.channels", 1u8), 1);
+        assert!(!conf.get_or_typed("listener.sample_rate", false));
+        // Absent key
+        assert_eq!(
+            conf.get_or_typed("listener.device", "default".to_string()),
+            "default"
+        );
+    }
+
+    #[test]
+    fn dropin_fragments_including_each_other_are_a_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let dropin = dir.path().join("mycroft.conf.d");
+        write_file(
+            &dropin.join("10-a.conf"),
+            r#"{"$include": "20-b.conf", "lang": "en-us"}"#,
+        );
+        write_file(
+            &dropin.join("20-b.conf"),
+            r#"{"$include": "10-a.conf", "lang": "de-de"}"#,
+        );
+        let a = dropin.join("10-a.conf").canonicalize().unwrap();
+        let b = dropin.join("20-b.conf").canonicalize().unwrap();
+
+        match load_dropin_dir(&dropin) {
+            Err(ConfigError::IncludeCycle { chain }) => assert_eq!(chain, [a.clone(), b, a]),
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dropin_includes_resolve_before_fragments_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let dropin = dir.path().join("mycroft.conf.d");
+        write_file(
+            &dir.path().join("common/base.json"),
+            r#"{"$include": "audio.yaml", "lang": "en-us", "tts": {"module": "mimic"}}"#,
+        );
+        write_file(
+            &dir.path().join("common/audio.yaml"),
+            "listener:\n  sample_rate: 16000\n  mute: false\n",
+        );
+        write_file(
+            &dropin.join("10-device.conf"),
+            r#"{"$include": "../common/base.json", "listener": {"sample_rate": 48000}}"#,
+        );
+        write_file(
+            &dropin.join("20-user.conf"),
+            r#"{"tts": {"module": "piper"}}"#,
+        );
+
+        assert_eq!(
+            load_dropin_dir(&dropin).unwrap(),
+            dict(
+                r#"{
+                    "lang": "en-us",
+                    "tts": {"module": "piper"},
+                    "listener": {"sample_rate": 48000, "mute": false}
+                }"#
+            )
+        );
+        assert_eq!(
+            load_dropin_dir(&dir.path().join("missing")).unwrap(),
+            ConfigDict::new()
+        );
+    }
+
+    #[test]
+    fn unknown_keys_flags_typos_but_not_known_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("mycroft.conf");
+        write_file(
+            &user,
+            r#"{
+                "lang": "en-us",
+                "listener": {"sample_rate": 16000, "sampel_rate": 48000},
+                "skills": {"weather": {"units": "metric"}},
+                "hotwords": ["hey mycroft"],
+                "bogus": {"nested": true}
+            }"#,
+        );
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::User],
+            vec![(ConfigLayer::User, LocalConf::unloaded(Some(user)))],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        let known = ["lang", "listener.sample_rate", "skills", "hotwords", "list"];
+        assert_eq!(
+            conf.unknown_keys(&known),
+            ["bogus.nested", "listener.sampel_rate"]
+        );
+        assert!(conf
+            .unknown_keys(&["lang", "listener", "skills", "hotwords", "bogus"])
+            .is_empty());
+    }
+
+    #[test]
+    fn validate_paths_reports_missing_files_and_non_strings() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let model = dir.path().join("models/vosk");
+        std::fs::create_dir_all(&model).unwrap();
+        write_file(&dir.path().join("voices/alan.onnx"), "");
+        env::set_var("OVOS_TEST_VOICES", dir.path().join("voices"));
+        let user = dir.path().join("mycroft.conf");
+        write_file(
+            &user,
+            &json!({
+                "stt": {"vosk": {"model": model}},
+                "tts": {"piper": {"voice": "${OVOS_TEST_VOICES}/alan.onnx"}},
+                "listener": {"wake_word_model": "$OVOS_TEST_VOICES/missing.tflite"},
+                "play_wav_cmdline": ["aplay"]
+            })
+            .to_string(),
+        );
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::User],
+            vec![(ConfigLayer::User, LocalConf::unloaded(Some(user)))],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        let issues = conf.validate_paths(&[
+            "stt.vosk.model",
+            "tts.piper.voice",
+            "listener.wake_word_model",
+            "play_wav_cmdline",
+            "unset.path",
+        ]);
+        env::remove_var("OVOS_TEST_VOICES");
+        assert_eq!(
+            issues,
+            [
+                PathIssue::Missing {
+                    key: "listener.wake_word_model".to_string(),
+                    path: dir.path().join("voices/missing.tflite"),
+                },
+                PathIssue::NotAString {
+                    key: "play_wav_cmdline".to_string(),
+                    actual: JsonType::Array,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_schema_appends_or_replaces_per_key_across_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.conf");
+        let user = dir.path().join("user.conf");
+        write_file(
+            &system,
+            r#"{"hotwords": ["hey mycroft"], "listener": {"channels": [0, 1], "rate": 16000},
+                "tts": {"module": "mimic", "mimic": {"voice": "ap"}}, "uuid": "abc"}"#,
+        );
+        write_file(
+            &user,
+            r#"{"hotwords": ["hey ovos"], "listener": {"channels": [2]},
+                "tts": {"module": "piper"}, "uuid": "abc"}"#,
+        );
+        let schema = json!({"properties": {
+            "hotwords": {"x-merge": "append"},
+            "listener": {"properties": {"channels": {"x-merge": "replace"}}},
+            "uuid": {"x-merge": "error"}
+        }});
+        let layers = || {
+            vec![
+                (
+                    ConfigLayer::System,
+                    LocalConf::unloaded(Some(system.clone())),
+                ),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+            ]
+        };
+        let order = vec![ConfigLayer::System, ConfigLayer::User];
+        let conf = Configuration::with_layers(order.clone(), layers(), None)
+            .with_merge_schema(schema.clone());
+        conf.try_load_all().unwrap();
+
+        assert_eq!(
+            conf.get("hotwords"),
+            Some(json!(["hey mycroft", "hey ovos"]))
+        );
+        assert_eq!(
+            conf.get("listener"),
+            Some(json!({"channels": [2], "rate": 16000}))
+        );
+        // Unannotated keys still deep-merge
+        assert_eq!(
+            conf.get("tts"),
+            Some(json!({"module": "piper", "mimic": {"voice": "ap"}}))
+        );
+        assert_eq!(conf.get("uuid"), Some(json!("abc")));
+
+        // Without the schema, arrays replace
+        let plain = Configuration::with_layers(order.clone(), layers(), None);
+        plain.try_load_all().unwrap();
+        assert_eq!(plain.get("hotwords"), Some(json!(["hey ovos"])));
+
+        write_file(&user, r#"{"uuid": "def"}"#);
+        let conflicting =
+            Configuration::with_layers(order, layers(), None).with_merge_schema(schema);
+        let errors = conflicting.try_load_all().unwrap_err();
+        assert!(
+            matches!(&errors[..], [ConfigError::MergeConflict { path }] if path == "uuid"),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn rename_key_moves_values_into_new_objects() {
+        let conf = conf(json!({"listener": {"wake_word": "hey mycroft", "rate": 16000}}));
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        conf.subscribe(move |changes| sink.write().extend(changes.iter().cloned()));
+
+        assert!(conf
+            .rename_key("listener.wake_word", "hotwords.default.name")
+            .unwrap());
+        assert_eq!(conf.get("listener"), Some(json!({"rate": 16000})));
+        assert_eq!(
+            conf.get("hotwords.default.name"),
+            Some(json!("hey mycroft"))
+        );
+        assert_eq!(
+            *seen.read(),
+            [
+                ConfigChange::Removed {
+                    path: "listener.wake_word".to_string(),
+                    value: json!("hey mycroft"),
+                },
+                ConfigChange::Added {
+                    path: "hotwords.default.name".to_string(),
+                    value: json!("hey mycroft"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_key_with_missing_source_moves_nothing() {
+        let conf = conf(json!({"lang": "en-us"}));
+        assert!(!conf
+            .rename_key("listener.wake_word", "hotwords.name")
+            .unwrap());
+        assert_eq!(conf.get("hotwords"), None);
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+    }
+
+    #[test]
+    fn rename_key_onto_a_set_key_needs_overwriting() {
+        let conf = conf(json!({"old_lang": "de-de", "lang": "en-us"}));
+        assert!(matches!(
+            conf.rename_key("old_lang", "lang"),
+            Err(ConfigError::KeyExists(key)) if key == "lang"
+        ));
+        assert_eq!(conf.get("old_lang"), Some(json!("de-de")));
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+
+        assert!(conf.rename_key_overwriting("old_lang", "lang").unwrap());
+        assert_eq!(conf.get("old_lang"), None);
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    // Rewrites `path` and gives it the modification time `modified`
+    fn rewrite_with_mtime(path: &Path, contents: &str, modified: SystemTime) {
+        write_file(path, contents);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn reload_checked_rereads_only_modified_layers() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let previous = env::var_os("XDG_CONFIG_DIRS");
+        env::set_var("XDG_CONFIG_DIRS", dir.path().join("etc/xdg"));
+        with_config_home(&dir.path().join("config"), || {
+            let xdg_conf = dir.path().join("etc/xdg/mycroft/mycroft.conf");
+            let user = dir.path().join("config/mycroft/mycroft.conf");
+            let start = SystemTime::now();
+            rewrite_with_mtime(&xdg_conf, r#"{"lang": "en-us"}"#, start);
+            rewrite_with_mtime(&user, r#"{"tts": {"module": "mimic"}}"#, start);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::Xdg, ConfigLayer::User]);
+            assert!(!conf.reload_checked().unwrap());
+
+            // The XDG file changes but keeps its mtime, so re-reading it
+            // would show up as a changed lang
+            rewrite_with_mtime(&xdg_conf, r#"{"lang": "de-de"}"#, start);
+            rewrite_with_mtime(
+                &user,
+                r#"{"tts": {"module": "piper"}}"#,
+                start + Duration::from_secs(10),
+            );
+            assert!(conf.reload_checked().unwrap());
+            assert_eq!(conf.get("tts.module"), Some(json!("piper")));
+            assert_eq!(conf.get("lang"), Some(json!("en-us")));
+            assert!(!conf.reload_checked().unwrap());
+        });
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_DIRS", value),
+            None => env::remove_var("XDG_CONFIG_DIRS"),
+        }
+        xdg::refresh_env_cache();
+    }
+
+    #[test]
+    fn relocating_layers_keeps_unmoved_layers_and_unsaved_edits() {
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let previous = env::var_os("XDG_CONFIG_DIRS");
+        env::set_var("XDG_CONFIG_DIRS", dir.path().join("etc/xdg"));
+        let xdg_conf = dir.path().join("etc/xdg/mycroft/mycroft.conf");
+        let start = SystemTime::now();
+        rewrite_with_mtime(&xdg_conf, r#"{"lang": "en-us"}"#, start);
+        write_file(
+            &dir.path().join("old/mycroft/mycroft.conf"),
+            r#"{"tts": {"module": "mimic"}}"#,
+        );
+        write_file(
+            &dir.path().join("new/mycroft/mycroft.conf"),
+            r#"{"tts": {"module": "piper"}}"#,
+        );
+        let conf = with_config_home(&dir.path().join("old"), || {
+            Configuration::with_layer_order(vec![ConfigLayer::Xdg, ConfigLayer::User])
+        });
+        assert_eq!(conf.get("tts.module"), Some(json!("mimic")));
+        // Changed behind the same mtime, so only a fresh read would see it
+        rewrite_with_mtime(&xdg_conf, r#"{"lang": "de-de"}"#, start);
+
+        with_config_home(&dir.path().join("new"), || {
+            // Unsaved edits pin the user layer to its old file
+            conf.set("listener.mute", json!(true)).unwrap();
+            assert!(!conf.reload_checked().unwrap());
+            assert_eq!(
+                conf.active_user_config_path(),
+                dir.path().join("old/mycroft/mycroft.conf")
+            );
+            assert_eq!(conf.get("listener.mute"), Some(json!(true)));
+
+            // Once stored, the user layer moves and the XDG layer, whose
+            // path didn't change, keeps the data it already loaded
+            conf.store().unwrap();
+            assert!(conf.reload_checked().unwrap());
+            assert_eq!(
+                conf.active_user_config_path(),
+                dir.path().join("new/mycroft/mycroft.conf")
+            );
+            assert_eq!(conf.get("tts.module"), Some(json!("piper")));
+            assert_eq!(conf.get("listener.mute"), None);
+            assert_eq!(conf.get("lang"), Some(json!("en-us")));
+        });
+        assert_eq!(
+            LocalConf::new(Some(dir.path().join("old/mycroft/mycroft.conf"))).get("listener.mute"),
+            Some(json!(true))
+        );
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_DIRS", value),
+            None => env::remove_var("XDG_CONFIG_DIRS"),
+        }
+        xdg::refresh_env_cache();
+    }
+
+    #[test]
+    fn sanitized_report_redacts_tokenizes_and_lists_layers() {
+        isolated(|root| {
+            without_home(|| {
+                let system = root.join("etc/mycroft/mycroft.conf");
+                let distribution = root.join("share/mycroft/mycroft.conf");
+                let user = user_config(root);
+                write_file(&system, r#"{"lang": "en-us"}"#);
+                write_file(&distribution, "{}");
+                write_file(
+                    &user,
+                    &json!({
+                        "data_dir": root.join("data/mycroft"),
+                        "listener": {"recording_path": root.join("home/recordings/x.wav")},
+                        "tts": {"azure": {"api_key": "s3cret", "cache": root.join("cache/tts")}}
+                    })
+                    .to_string(),
+                );
+                let conf = Configuration::with_layers(
+                    vec![
+                        ConfigLayer::Distribution,
+                        ConfigLayer::System,
+                        ConfigLayer::User,
+                    ],
+                    vec![
+                        (
+                            ConfigLayer::Distribution,
+                            LocalConf::unloaded(Some(distribution)),
+                        ),
+                        (
+                            ConfigLayer::System,
+                            LocalConf::unloaded(Some(system.clone())),
+                        ),
+                        (ConfigLayer::User, LocalConf::unloaded(Some(user))),
+                    ],
+                    None,
+                );
+                conf.try_load_all().unwrap();
+
+                assert_eq!(
+                    conf.sanitized_report(),
+                    format!(
+                        "# Sanitized OVOS configuration\n\
+                         # Layers, lowest precedence first:\n\
+                         #   System: {}\n\
+                         #   User: <XDG_CONFIG_HOME>/mycroft/mycroft.conf\n\
+                         data_dir: <XDG_DATA_HOME>/mycroft\n\
+                         lang: en-us\n\
+                         listener:\n  \
+                         recording_path: <HOME>/recordings/x.wav\n\
+                         tts:\n  \
+                         azure:\n    \
+                         api_key: '***'\n    \
+                         cache: <XDG_CACHE_HOME>/tts\n",
+                        system.display()
+                    )
+                );
+            })
+        });
+    }
+
+    #[cfg(feature = "archive")]
+    fn tar_bundle(path: &Path, members: &[(&str, &str)]) {
+        let mut tar = tar::Builder::new(File::create(path).unwrap());
+        for (name, contents) in members {
+            let mut header = tar::Header::new_gnu();
+            // Written raw, as `Builder::append_data` refuses `..`
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append(&header, contents.as_bytes()).unwrap();
+        }
+        tar.finish().unwrap();
+    }
+
+    #[cfg(feature = "archive")]
+    fn zip_bundle(path: &Path, members: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in members {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn load_bundle_merges_fragments_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let members = [
+            (
+                "conf/20-lang.yaml",
+                "lang: de-de\nlistener:\n  mute: true\n",
+            ),
+            ("README.md", "not config"),
+            (
+                "conf/10-base.json",
+                r#"{"lang": "en-us", "listener": {"sample_rate": 16000}}"#,
+            ),
+        ];
+        let expected =
+            dict(r#"{"lang": "de-de", "listener": {"sample_rate": 16000, "mute": true}}"#);
+        for (name, write) in [
+            ("bundle.zip", zip_bundle as fn(&Path, &[(&str, &str)])),
+            ("bundle.tar", tar_bundle),
+        ] {
+            let path = dir.path().join(name);
+            write(&path, &members);
+            let conf = Configuration::load_bundle(&path).unwrap();
+            assert_eq!(*conf.data.read(), expected, "{}", name);
+            assert_eq!(conf.layer_order(), [ConfigLayer::Distribution]);
+        }
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn load_bundle_rejects_members_escaping_the_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, member) in [
+            ("dotdot.zip", "conf/../../etc/mycroft.json"),
+            ("absolute.zip", "/etc/mycroft/mycroft.conf"),
+            ("dotdot.tar", "../mycroft.conf"),
+            ("absolute.tar", "/etc/mycroft/mycroft.conf"),
+        ] {
+            let path = dir.path().join(name);
+            let members = [("10-base.json", r#"{"lang": "en-us"}"#), (member, "{}")];
+            if name.ends_with(".zip") {
+                zip_bundle(&path, &members);
+            } else {
+                tar_bundle(&path, &members);
+            }
+            match Configuration::load_bundle(&path) {
+                Err(ConfigError::UnsafeArchiveMember { archive, member: m }) => {
+                    assert_eq!((archive, m.as_str()), (path, member));
+                }
+                Err(e) => panic!("{}: unexpected error {}", name, e),
+                Ok(_) => panic!("{}: loaded an unsafe bundle", name),
+            }
+        }
+    }
+
+    #[test]
+    fn poll_reload_calls_back_once_per_change_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        let start = SystemTime::now();
+        rewrite_with_mtime(&path, r#"{"lang": "en-us"}"#, start);
+        let conf = LocalConf::new(Some(path.clone()));
+        let calls = Arc::new(RwLock::new(Vec::new()));
+        let sink = Arc::clone(&calls);
+        let guard = conf
+            .poll_reload(Duration::from_millis(10), move |changes| {
+                sink.write().push(changes.to_vec())
+            })
+            .unwrap();
+
+        // Touched without changing anything: reloaded, but no callback
+        rewrite_with_mtime(
+            &path,
+            r#"{"lang": "en-us"}"#,
+            start + Duration::from_secs(10),
+        );
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(calls.read().is_empty());
+
+        rewrite_with_mtime(
+            &path,
+            r#"{"lang": "de-de"}"#,
+            start + Duration::from_secs(20),
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while calls.read().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            *calls.read(),
+            [vec![ConfigChange::Changed {
+                path: "lang".to_string(),
+                old: json!("en-us"),
+                new: json!("de-de"),
+            }]]
+        );
+
+        drop(guard);
+        rewrite_with_mtime(
+            &path,
+            r#"{"lang": "fr-fr"}"#,
+            start + Duration::from_secs(30),
+        );
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(calls.read().len(), 1);
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+    }
+
+    #[test]
+    fn locked_key_rejects_every_edit_that_changes_it() {
+        let conf = conf(json!({
+            "device": {"id": "abc-123", "name": "kitchen"},
+            "lang": "en-us"
+        }));
+        conf.lock_key("device.id");
+        let locked = |result: Result<(), ConfigError>| matches!(result, Err(ConfigError::KeyLocked(key)) if key == "device.id");
+
+        assert!(locked(conf.set("device.id", json!("xyz"))));
+        assert!(locked(conf.set("device", json!({"name": "attic"}))));
+        assert!(locked(conf.remove("device.id").map(|_| ())));
+        assert!(locked(conf.remove("device").map(|_| ())));
+        assert!(locked(conf.merge(&dict(r#"{"device": "gone"}"#))));
+        assert!(locked(
+            conf.apply_merge_patch(&json!({"device": {"id": null}}))
+        ));
+        assert!(locked(conf.clear()));
+        assert!(locked(conf.replace_all(dict(r#"{"lang": "de-de"}"#))));
+        assert!(locked(
+            conf.rename_key("device.id", "device.serial").map(|_| ())
+        ));
+        assert_eq!(
+            conf.get("device"),
+            Some(json!({"id": "abc-123", "name": "kitchen"}))
+        );
+
+        // Other keys, siblings included, stay writable, as do edits that
+        // leave the locked value as it is
+        conf.set("lang", json!("de-de")).unwrap();
+        conf.set("device.name", json!("attic")).unwrap();
+        conf.set("device", json!({"id": "abc-123", "room": 2}))
+            .unwrap();
+        conf.set("device.id", json!("abc-123")).unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("de-de")));
+        assert_eq!(
+            conf.get("device"),
+            Some(json!({"id": "abc-123", "room": 2}))
+        );
+    }
+
+    #[test]
+    fn explain_lists_layers_with_status_and_settings_in_effect() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default.conf");
+        let system = dir.path().join("system.conf");
+        let user = dir.path().join("user.conf");
+        write_file(
+            &default,
+            r#"{"lang": "en-us", "listener": {"sample_rate": 16000, "mute": false}}"#,
+        );
+        write_file(&user, r#"{"lang": "de-de"}"#);
+        let conf = Configuration::with_layers(
+            vec![ConfigLayer::Default, ConfigLayer::System, ConfigLayer::User],
+            vec![
+                (
+                    ConfigLayer::Default,
+                    LocalConf::unloaded(Some(default.clone())),
+                ),
+                (
+                    ConfigLayer::System,
+                    LocalConf::unloaded(Some(system.clone())),
+                ),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+            ],
+            None,
+        );
+        conf.try_load_all().unwrap();
+
+        let line = |n: usize, layer: &str, path: &Path, status: &str, settings: &str| {
+            format!(
+                "  {}. {}: {:?} ({}), {} settings in effect\n",
+                n, layer, path, status, settings
+            )
+        };
+        let report = conf.explain();
+        assert!(report.starts_with("Configuration layers, lowest precedence first."));
+        assert!(report.contains("later layers win"));
+        assert!(report.contains(&format!(
+            "{}{}{}",
+            line(1, "Default", &default, "loaded", "2 of 3"),
+            line(2, "System", &system, "missing", "0 of 0"),
+            line(3, "User", &user, "loaded", "1 of 1")
+        )));
+        assert!(report.contains("  - no profile"));
+
+        write_file(&system, r#"{"listener": {"mute": true}}"#);
+        conf.try_load_all().unwrap();
+        let report = conf.explain();
+        assert!(report.contains(&line(1, "Default", &default, "loaded", "1 of 3")));
+        assert!(report.contains(&line(2, "System", &system, "loaded", "1 of 1")));
+    }
+
+    #[test]
+    fn store_falls_back_when_the_primary_is_unwritable() {
+        let dir = tempfile::tempdir().unwrap();
+        // A regular file where a directory should be can't be written below,
+        // even by root
+        let blocker = dir.path().join("readonly");
+        write_file(&blocker, "");
+        let primary = blocker.join("mycroft.conf");
+        let blocked_fallback = blocker.join("state/mycroft.conf");
+        let fallback = dir.path().join("tmp/mycroft/mycroft.conf");
+        let conf = LocalConf::builder(Some(primary.clone()))
+            .store_fallbacks(vec![blocked_fallback.clone(), fallback.clone()])
+            .build()
+            .unwrap();
+        conf.set("lang", json!("de-de")).unwrap();
+
+        assert_eq!(conf.store_with_fallbacks().unwrap(), fallback);
+        assert_eq!(
+            LocalConf::new(Some(fallback.clone())).get("lang"),
+            Some(json!("de-de"))
+        );
+        std::fs::remove_file(&fallback).unwrap();
+        conf.store(None).unwrap();
+        assert!(fallback.exists());
+        assert_eq!(conf.path(), Some(primary.as_path()));
+
+        // With every location unwritable the last error is returned
+        let stuck = LocalConf::builder(Some(primary.clone()))
+            .store_fallbacks(vec![blocked_fallback])
+            .build()
+            .unwrap();
+        assert!(stuck.store_with_fallbacks().is_err());
+        let no_fallbacks = LocalConf::new(Some(primary));
+        assert!(no_fallbacks.store(None).is_err());
+    }
+
+    #[test]
+    fn typed_view_refreshes_only_when_its_section_changes() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Listener {
+            sample_rate: u32,
+            mute: bool,
+        }
+
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        with_config_home(dir.path(), || {
+            let path = dir.path().join("mycroft/mycroft.conf");
+            let start = SystemTime::now();
+            let rewrite = |contents: &str, secs| {
+                rewrite_with_mtime(&path, contents, start + Duration::from_secs(secs))
+            };
+            rewrite(
+                r#"{"lang": "en-us", "listener": {"sample_rate": 16000, "mute": false}}"#,
+                0,
+            );
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            assert!(matches!(
+                conf.typed_view::<Listener>("lang"),
+                Err(ConfigError::Parse(_))
+            ));
+            let view = conf.typed_view::<Listener>("listener").unwrap();
+            let first = view.get();
+            assert_eq!(
+                *first,
+                Listener {
+                    sample_rate: 16000,
+                    mute: false
+                }
+            );
+
+            rewrite(
+                r#"{"lang": "de-de", "listener": {"sample_rate": 16000, "mute": false}}"#,
+                10,
+            );
+            conf.reload().unwrap();
+            assert_eq!(conf.get("lang"), Some(json!("de-de")));
+            assert!(Arc::ptr_eq(&first, &view.get()));
+
+            rewrite(
+                r#"{"lang": "de-de", "listener": {"sample_rate": 48000, "mute": false}}"#,
+                20,
+            );
+            conf.reload().unwrap();
+            assert_eq!(view.get().sample_rate, 48000);
+            assert_eq!(first.sample_rate, 16000);
+
+            // A section that no longer fits keeps the last good value
+            rewrite(r#"{"listener": {"sample_rate": "fast"}}"#, 30);
+            conf.reload().unwrap();
+            assert_eq!(view.get().sample_rate, 48000);
+        });
+    }
+
+    #[test]
+    fn dropping_typed_views_unsubscribes_them() {
+        isolated(|root| {
+            write_file(&user_config(root), r#"{"lang": "en-us"}"#);
+            let conf = Configuration::with_layer_order(vec![ConfigLayer::User]);
+            let subscribers = || conf.subscribers.read().len();
+            let before = subscribers();
+
+            let view = conf.typed_view::<String>("lang").unwrap();
+            let other = conf.typed_view::<String>("lang").unwrap();
+            assert_eq!(subscribers(), before + 2);
+            let clone = view.clone();
+            drop(view);
+            assert_eq!(subscribers(), before + 2);
+            drop(clone);
+            assert_eq!(subscribers(), before + 1);
+
+            conf.apply_bus_patch(&json!({
+                "type": "configuration.patch",
+                "data": {"lang": "de-de"}
+            }))
+            .unwrap();
+            assert_eq!(*other.get(), "de-de");
+            drop(other);
+            assert_eq!(subscribers(), before);
+        });
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_bounded_history() {
+        let conf = LocalConf::builder(None).history(3).build().unwrap();
+        conf.set("lang", json!("de-de")).unwrap();
+        conf.set("lang", json!("fr-fr")).unwrap();
+        conf.merge(&dict(r#"{"listener": {"mute": true}}"#))
+            .unwrap();
+        conf.remove("lang").unwrap();
+        let state = || (conf.get("lang"), conf.get("listener.mute"));
+        assert_eq!(state(), (None, Some(json!(true))));
+
+        assert!(conf.undo().unwrap());
+        assert_eq!(state(), (Some(json!("fr-fr")), Some(json!(true))));
+        assert!(conf.undo().unwrap());
+        assert_eq!(state(), (Some(json!("fr-fr")), None));
+        assert!(conf.undo().unwrap());
+        assert_eq!(state(), (Some(json!("de-de")), None));
+        // Only three edits are kept, so the first set can't be undone
+        assert!(!conf.undo().unwrap());
+        assert_eq!(state(), (Some(json!("de-de")), None));
+
+        assert!(conf.redo().unwrap());
+        assert_eq!(state(), (Some(json!("fr-fr")), None));
+        assert!(conf.redo().unwrap());
+        assert_eq!(state(), (Some(json!("fr-fr")), Some(json!(true))));
+
+        // A new edit drops what's left to redo
+        conf.set("lang", json!("es-es")).unwrap();
+        assert!(!conf.redo().unwrap());
+        assert!(conf.undo().unwrap());
+        assert_eq!(state(), (Some(json!("fr-fr")), Some(json!(true))));
+
+        let untracked = LocalConf::in_memory(dict(r#"{"lang": "en-us"}"#));
+        untracked.set("lang", json!("de-de")).unwrap();
+        assert!(!untracked.undo().unwrap());
+        assert_eq!(untracked.get("lang"), Some(json!("de-de")));
+    }
+
+    // Merges `delta` over `base` with `policy`, returning the result
+    fn merge_with(
+        base: Value,
+        delta: Value,
+        policy: TypeConflictPolicy,
+    ) -> Result<Value, ConfigError> {
+        let mut merged: ConfigDict = serde_json::from_value(base).unwrap();
+        merge_dict_with_policy(&mut merged, &serde_json::from_value(delta).unwrap(), policy)?;
+        Ok(serde_json::to_value(merged).unwrap())
+    }
+
+    #[test]
+    fn prefer_higher_takes_the_incoming_type() {
+        let policy = TypeConflictPolicy::PreferHigher;
+        for (base, delta) in [
+            (json!({"rate": 16000}), json!({"rate": "48000"})),
+            (json!({"tts": {"module": "piper"}}), json!({"tts": "mimic"})),
+            (
+                json!({"hotwords": "hey"}),
+                json!({"hotwords": ["hey", "ok"]}),
+            ),
+            (json!({"mute": false}), json!({"mute": 1})),
+        ] {
+            assert_eq!(merge_with(base, delta.clone(), policy).unwrap(), delta);
+        }
+    }
+
+    #[test]
+    fn coerce_converts_scalars_and_keeps_what_cannot_convert() {
+        let policy = TypeConflictPolicy::Coerce;
+        for (base, delta, expected) in [
+            (
+                json!({"listener": {"rate": 16000}}),
+                json!({"listener": {"rate": "48000"}}),
+                json!({"listener": {"rate": 48000}}),
+            ),
+            (
+                json!({"lang": "en"}),
+                json!({"lang": 42}),
+                json!({"lang": "42"}),
+            ),
+            (
+                json!({"mute": false}),
+                json!({"mute": "true"}),
+                json!({"mute": true}),
+            ),
+            (
+                json!({"mute": false}),
+                json!({"mute": 1}),
+                json!({"mute": true}),
+            ),
+            (
+                json!({"volume": 0}),
+                json!({"volume": true}),
+                json!({"volume": 1}),
+            ),
+            // No conversion exists, so the incoming value is taken as is
+            (
+                json!({"rate": 16000}),
+                json!({"rate": "fast"}),
+                json!({"rate": "fast"}),
+            ),
+            (
+                json!({"tts": {"module": "piper"}}),
+                json!({"tts": "mimic"}),
+                json!({"tts": "mimic"}),
+            ),
+            (
+                json!({"hotwords": "hey"}),
+                json!({"hotwords": ["hey", "ok"]}),
+                json!({"hotwords": ["hey", "ok"]}),
+            ),
+        ] {
+            assert_eq!(merge_with(base, delta, policy).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn error_policy_reports_the_conflicting_path_and_types() {
+        let policy = TypeConflictPolicy::Error;
+        for (base, delta, path, existing, incoming) in [
+            (
+                json!({"listener": {"rate": 16000}}),
+                json!({"listener": {"rate": "48000"}}),
+                "listener.rate",
+                JsonType::Number,
+                JsonType::String,
+            ),
+            (
+                json!({"tts": {"module": "piper"}}),
+                json!({"tts": "mimic"}),
+                "tts",
+                JsonType::Object,
+                JsonType::String,
+            ),
+            (
+                json!({"hotwords": "hey"}),
+                json!({"hotwords": ["hey"]}),
+                "hotwords",
+                JsonType::String,
+                JsonType::Array,
+            ),
+        ] {
+            match merge_with(base, delta, policy) {
+                Err(ConfigError::TypeConflict {
+                    path: p,
+                    existing: e,
+                    incoming: i,
+                }) => assert_eq!((p.as_str(), e, i), (path, existing, incoming)),
+                other => panic!("expected a type conflict at {}, got {:?}", path, other),
+            }
+        }
+        // Same types and nulls never conflict
+        assert_eq!(
+            merge_with(
+                json!({"rate": 16000, "device": null, "lang": "en"}),
+                json!({"rate": 48000, "device": "hw:0", "lang": null}),
+                policy
+            )
+            .unwrap(),
+            json!({"rate": 48000, "device": "hw:0", "lang": null})
+        );
+    }
+
+    #[test]
+    fn type_conflict_policy_applies_between_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.conf");
+        let user = dir.path().join("user.conf");
+        write_file(&system, r#"{"my_skill": {"rate": 16000}}"#);
+        write_file(&user, r#"{"my_skill": {"rate": "48000"}}"#);
+        let configuration = |policy| {
+            let conf = Configuration::with_layers(
+                vec![ConfigLayer::System, ConfigLayer::User],
+                vec![
+                    (
+                        ConfigLayer::System,
+                        LocalConf::unloaded(Some(system.clone())),
+                    ),
+                    (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+                ],
+                None,
+            )
+            .with_type_conflict_policy(policy);
+            let loaded = conf.try_load_all();
+            (conf, loaded)
+        };
+
+        // Keys in the bundled defaults are coerced to their type regardless,
+        // so this uses one that isn't
+        let (conf, loaded) = configuration(TypeConflictPolicy::PreferHigher);
+        loaded.unwrap();
+        assert_eq!(conf.get("my_skill.rate"), Some(json!("48000")));
+        let (conf, loaded) = configuration(TypeConflictPolicy::Coerce);
+        loaded.unwrap();
+        assert_eq!(conf.get("my_skill.rate"), Some(json!(48000)));
+        let (_, loaded) = configuration(TypeConflictPolicy::Error);
+        assert!(matches!(
+            &loaded.unwrap_err()[..],
+            [ConfigError::TypeConflict { path, .. }] if path == "my_skill.rate"
+        ));
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn dbus_reply_converts_to_a_config_dict() {
+        let bus = "org.OpenVoiceOS.Config";
+        assert_eq!(
+            dbus_reply_to_dict(
+                bus,
+                r#"{"lang": "pt-pt", "listener": {"sample_rate": 16000}}"#
+            )
+            .unwrap(),
+            dict(r#"{"lang": "pt-pt", "listener": {"sample_rate": 16000}}"#)
+        );
+        assert_eq!(dbus_reply_to_dict(bus, "{}").unwrap(), ConfigDict::new());
+        for reply in ["", "not json", r#"["lang"]"#, r#""pt-pt""#, "null"] {
+            match dbus_reply_to_dict(bus, reply) {
+                Err(ConfigError::Parse(message)) => {
+                    assert!(message.contains(bus), "{}", message)
+                }
+                other => panic!("{:?} converted to {:?}", reply, other),
+            }
+        }
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn load_from_dbus_with_passes_transport_errors_through() {
+        struct Mock(Result<&'static str, &'static str>);
+
+        impl DbusTransport for Mock {
+            fn get_config(
+                &self,
+                bus_name: &str,
+                object_path: &str,
+                interface: &str,
+            ) -> Result<String, ConfigError> {
+                assert_eq!(
+                    (bus_name, object_path, interface),
+                    (
+                        "org.OpenVoiceOS.Config",
+                        "/config",
+                        "org.OpenVoiceOS.Config"
+                    )
+                );
+                self.0
+                    .map(str::to_string)
+                    .map_err(|e| ConfigError::Dbus(e.to_string()))
+            }
+        }
+
+        let load = |mock: Mock| {
+            Configuration::load_from_dbus_with(
+                &mock,
+                "org.OpenVoiceOS.Config",
+                "/config",
+                "org.OpenVoiceOS.Config",
+            )
+        };
+        let conf = load(Mock(Ok(r#"{"lang": "pt-pt"}"#))).unwrap();
+        assert_eq!(conf.get("lang"), Some(json!("pt-pt")));
+        assert!(matches!(
+            load(Mock(Err("org.freedesktop.DBus.Error.ServiceUnknown"))),
+            Err(ConfigError::Dbus(message)) if message.contains("ServiceUnknown")
+        ));
+        assert!(matches!(load(Mock(Ok("[]"))), Err(ConfigError::Parse(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn try_load_all_reports_directories_and_fifos_as_not_a_file() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default.conf");
+        write_file(&default, r#"{"lang": "en-us"}"#);
+        let system = dir.path().join("etc/mycroft.conf");
+        std::fs::create_dir_all(&system).unwrap();
+        let user = dir.path().join("mycroft.conf");
+        let fifo = std::ffi::CString::new(user.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        let socket = dir.path().join("xdg.conf");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let conf = Configuration::with_layers(
+            vec![
+                ConfigLayer::Default,
+                ConfigLayer::System,
+                ConfigLayer::Xdg,
+                ConfigLayer::User,
+            ],
+            vec![
+                (ConfigLayer::Default, LocalConf::unloaded(Some(default))),
+                (
+                    ConfigLayer::System,
+                    LocalConf::unloaded(Some(system.clone())),
+                ),
+                (ConfigLayer::Xdg, LocalConf::unloaded(Some(socket.clone()))),
+                (ConfigLayer::User, LocalConf::unloaded(Some(user.clone()))),
+            ],
+            None,
+        );
+        // Opening the FIFO for reading would block, so this also checks it's
+        // never opened
+        let errors = conf.try_load_all().unwrap_err();
+        let reported: Vec<(PathBuf, &str)> = errors
+            .iter()
+            .map(|e| match e {
+                ConfigError::NotAFile { path, kind } => (path.clone(), *kind),
+                other => panic!("unexpected error {}", other),
+            })
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (system.clone(), "directory"),
+                (socket, "socket"),
+                (user, "fifo")
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            format!("{:?} is a directory, not a config file", system)
+        );
+        assert_eq!(conf.get("lang"), Some(json!("en-us")));
+    }
+
+    #[test]
+    fn set_indexes_into_arrays_and_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mycroft.conf");
+        let conf = LocalConf::new(Some(path.clone()));
+        conf.set("servers", json!([{"host": "a"}, {"host": "b"}]))
+            .unwrap();
+        conf.set("servers.0.host", json!("z")).unwrap();
+        conf.set("servers.1", json!({"host": "y", "port": 8181}))
+            .unwrap();
+        conf.set("servers.0.tls.enabled", json!(true)).unwrap();
+        let expected = json!([
+            {"host": "z", "tls": {"enabled": true}},
+            {"host": "y", "port": 8181}
+        ]);
+        assert_eq!(conf.get("servers"), Some(expected.clone()));
+
+        conf.store(None).unwrap();
+        let reloaded = LocalConf::new(Some(path));
+        assert_eq!(reloaded.get("servers"), Some(expected.clone()));
+        assert_eq!(reloaded.get("servers.1.port"), Some(json!(8181)));
+
+        #[derive(Serialize)]
+        struct Server {
+            port: u16,
+        }
+        reloaded
+            .merge_struct("servers.0", &Server { port: 443 })
+            .unwrap();
+        assert_eq!(
+            reloaded.get("servers.0"),
+            Some(json!({"host": "z", "tls": {"enabled": true}, "port": 443}))
+        );
+        reloaded.with_override("servers.1.host", json!("tmp"), || {
+            assert_eq!(reloaded.get("servers.1.host"), Some(json!("tmp")));
+        });
+        assert_eq!(reloaded.get("servers.1.host"), Some(json!("y")));
+    }
+
+    #[test]
+    fn set_past_the_end_of_an_array_fails_without_changes() {
+        let conf = conf(json!({"servers": [{"host": "a"}, {"host": "b"}]}));
+        for (key, segment) in [
+            ("servers.2.host", "2"),
+            ("servers.2", "2"),
+            ("servers.host", "host"),
+            ("servers.-1", "-1"),
+        ] {
+            match conf.set(key, json!("z")) {
+                Err(ConfigError::InvalidIndex {
+                    key: k,
+                    segment: s,
+                    len: 2,
+                }) => assert_eq!((k.as_str(), s.as_str()), (key, segment)),
+                other => panic!("{}: expected an invalid index, got {:?}", key, other),
+            }
+        }
+        assert!(matches!(
+            conf.rename_key("servers.0", "servers.5"),
+            Err(ConfigError::InvalidIndex { .. })
+        ));
+        assert_eq!(
+            conf.get("servers"),
+            Some(json!([{"host": "a"}, {"host": "b"}]))
+        );
+    }
 }
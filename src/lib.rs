@@ -16,7 +16,11 @@
 //! println!("XDG config home: {:?}", config_home);
 //! ```
 
+pub mod commented;
 pub mod config;
 pub mod locations;
 pub mod log;
+#[cfg(test)]
+mod testing;
+pub mod validation;
 pub mod xdg;
@@ -1,4 +1,6 @@
+use crate::log::warn;
 use crate::xdg;
+use parking_lot::RwLock;
 use std::env;
 use std::path::{Path, PathBuf};
 
@@ -11,9 +13,9 @@ use std::path::{Path, PathBuf};
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let config_dirs = config_locations::get_xdg_config_dirs(None);
+/// let config_dirs = locations::get_xdg_config_dirs(None);
 /// println!("XDG config dirs: {:?}", config_dirs);
 /// ```
 pub fn get_xdg_config_dirs(folder: Option<&str>) -> Vec<PathBuf> {
@@ -32,9 +34,9 @@ pub fn get_xdg_config_dirs(folder: Option<&str>) -> Vec<PathBuf> {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let data_dirs = config_locations::get_xdg_data_dirs(None);
+/// let data_dirs = locations::get_xdg_data_dirs(None);
 /// println!("XDG data dirs: {:?}", data_dirs);
 /// ```
 pub fn get_xdg_data_dirs(folder: Option<&str>) -> Vec<PathBuf> {
@@ -54,9 +56,9 @@ pub fn get_xdg_data_dirs(folder: Option<&str>) -> Vec<PathBuf> {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let config_save_path = config_locations::get_xdg_config_save_path(None);
+/// let config_save_path = locations::get_xdg_config_save_path(None);
 /// println!("XDG config save path: {:?}", config_save_path);
 /// ```
 pub fn get_xdg_config_save_path(folder: Option<&str>) -> PathBuf {
@@ -64,6 +66,119 @@ pub fn get_xdg_config_save_path(folder: Option<&str>) -> PathBuf {
     xdg::xdg_config_home().join(folder)
 }
 
+/// Returns a nested path under the XDG config home, e.g. for
+/// `["mycroft", "skills", "my.skill"]`.
+///
+/// Segments are sanitized so the result can't escape the config home: `.`,
+/// `..` and empty segments are dropped and path separators inside a segment
+/// are replaced with `_`, each with a warning.
+///
+/// # Arguments
+///
+/// * `segments` - Folder names to join, outermost first.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::{locations, xdg};
+///
+/// let skill_path = locations::get_xdg_config_save_path_sub(&["mycroft", "skills", "my.skill"]);
+/// assert_eq!(skill_path, xdg::xdg_config_home().join("mycroft/skills/my.skill"));
+///
+/// let escaped = locations::get_xdg_config_save_path_sub(&["mycroft", "..", "/etc"]);
+/// assert_eq!(escaped, xdg::xdg_config_home().join("mycroft/_etc"));
+/// ```
+pub fn get_xdg_config_save_path_sub(segments: &[&str]) -> PathBuf {
+    let mut path = xdg::xdg_config_home();
+    for segment in segments {
+        push_segment(&mut path, segment);
+    }
+    path
+}
+
+/// Helper function to append one untrusted path segment, sanitized so it
+/// can't climb out of or skip past `path`.
+fn push_segment(path: &mut PathBuf, segment: &str) {
+    if matches!(segment, "" | "." | "..") {
+        warn(&format!("Dropping path segment {:?}", segment));
+    } else if segment.contains(['/', '\\']) {
+        warn(&format!(
+            "Replacing separators in path segment {:?}",
+            segment
+        ));
+        path.push(segment.replace(['/', '\\'], "_"));
+    } else {
+        path.push(segment);
+    }
+}
+
+/// Returns the data folder for a skill, `$XDG_DATA_HOME/mycroft/skills/<skill_id>`.
+///
+/// Nothing is created; use [`ensure_folder_exists`] before writing. The skill
+/// id is sanitized as in [`get_xdg_config_save_path_sub`].
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::{locations, xdg};
+///
+/// let data_path = locations::skill_data_path("skill-weather.openvoiceos");
+/// assert_eq!(data_path, xdg::xdg_data_home().join("mycroft/skills/skill-weather.openvoiceos"));
+/// ```
+pub fn skill_data_path(skill_id: &str) -> PathBuf {
+    let mut path = get_xdg_data_save_path(None).join("skills");
+    push_segment(&mut path, skill_id);
+    path
+}
+
+/// Returns the cache folder for a skill, `$XDG_CACHE_HOME/mycroft/skills/<skill_id>`.
+///
+/// Like [`skill_data_path`], nothing is created.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::{locations, xdg};
+///
+/// let cache_path = locations::skill_cache_path("skill-weather.openvoiceos");
+/// assert_eq!(cache_path, xdg::xdg_cache_home().join("mycroft/skills/skill-weather.openvoiceos"));
+/// ```
+pub fn skill_cache_path(skill_id: &str) -> PathBuf {
+    let mut path = get_xdg_cache_save_path(None).join("skills");
+    push_segment(&mut path, skill_id);
+    path
+}
+
+/// Lists the skills that have settings, as `(skill_id, settings_path)` pairs
+/// for each folder under `$XDG_CONFIG_HOME/mycroft/skills` holding a
+/// `settings.json`, sorted by skill id. Empty when there is no `skills` folder.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::locations;
+///
+/// for (skill_id, settings) in locations::existing_skill_settings() {
+///     println!("{}: {:?}", skill_id, settings);
+/// }
+/// ```
+pub fn existing_skill_settings() -> Vec<(String, PathBuf)> {
+    let skills = get_xdg_config_save_path(None).join("skills");
+    let Ok(entries) = std::fs::read_dir(&skills) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let skill_id = entry.file_name().into_string().ok()?;
+            let settings = entry.path().join("settings.json");
+            settings.is_file().then_some((skill_id, settings))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
 /// Returns the XDG data save path for the given folder.
 ///
 /// # Arguments
@@ -73,9 +188,9 @@ pub fn get_xdg_config_save_path(folder: Option<&str>) -> PathBuf {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let data_save_path = config_locations::get_xdg_data_save_path(None);
+/// let data_save_path = locations::get_xdg_data_save_path(None);
 /// println!("XDG data save path: {:?}", data_save_path);
 /// ```
 pub fn get_xdg_data_save_path(folder: Option<&str>) -> PathBuf {
@@ -92,9 +207,9 @@ pub fn get_xdg_data_save_path(folder: Option<&str>) -> PathBuf {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let cache_save_path = config_locations::get_xdg_cache_save_path(None);
+/// let cache_save_path = locations::get_xdg_cache_save_path(None);
 /// println!("XDG cache save path: {:?}", cache_save_path);
 /// ```
 pub fn get_xdg_cache_save_path(folder: Option<&str>) -> PathBuf {
@@ -107,9 +222,9 @@ pub fn get_xdg_cache_save_path(folder: Option<&str>) -> PathBuf {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let user_config = config_locations::find_user_config();
+/// let user_config = locations::find_user_config();
 /// println!("User config path: {:?}", user_config);
 /// ```
 pub fn find_user_config() -> PathBuf {
@@ -117,8 +232,7 @@ pub fn find_user_config() -> PathBuf {
     if path.is_file() {
         path
     } else {
-        let old_path = Path::new(&env::var("HOME").unwrap_or_else(|_| String::from("/")))
-            .join(".mycroft/mycroft.conf");
+        let old_path = xdg::home_dir().join(".mycroft/mycroft.conf");
         if old_path.is_file() {
             old_path
         } else {
@@ -132,36 +246,57 @@ pub fn find_user_config() -> PathBuf {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let config_locations = config_locations::get_config_locations();
+/// let config_locations = locations::get_config_locations();
 /// println!("Config locations: {:?}", config_locations);
 /// ```
 pub fn get_config_locations() -> Vec<PathBuf> {
-    let mut locs = Vec::new();
-
-    // Default config
-    locs.push(PathBuf::from("/etc/mycroft/mycroft.conf"));
-
-    // Distribution config
-    locs.push(PathBuf::from("/usr/share/mycroft/mycroft.conf"));
-
-    // System config
-    locs.push(PathBuf::from("/etc/mycroft/mycroft.conf"));
-
-    // Web cache
-    locs.push(get_webcache_location());
-
-    // Old user config
-    locs.push(
-        Path::new(&env::var("HOME").unwrap_or_else(|_| String::from("/")))
-            .join(".mycroft/mycroft.conf"),
-    );
-
-    // User config
-    locs.push(get_xdg_config_save_path(None).join("mycroft.conf"));
+    #[allow(unused_mut)]
+    let mut locations = vec![
+        // Default config
+        PathBuf::from("/etc/mycroft/mycroft.conf"),
+        // Distribution config
+        PathBuf::from("/usr/share/mycroft/mycroft.conf"),
+        // System config
+        PathBuf::from("/etc/mycroft/mycroft.conf"),
+        // Web cache
+        get_webcache_location(),
+        // Old user config
+        xdg::home_dir().join(".mycroft/mycroft.conf"),
+        // User config
+        get_xdg_config_save_path(None).join("mycroft.conf"),
+    ];
+    #[cfg(windows)]
+    locations.extend(windows_config_locations());
+    locations
+}
 
-    locs
+/// Returns the Windows config file candidates, machine-wide first.
+///
+/// Installers on Windows place config under `%ProgramData%\OVOS` (machine)
+/// or `%APPDATA%\OVOS` (roaming user) rather than the XDG paths. Unset
+/// variables are skipped.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(windows)]
+/// # {
+/// use ovos_config::locations;
+///
+/// for path in locations::windows_config_locations() {
+///     assert!(path.ends_with("OVOS/mycroft.conf"));
+/// }
+/// # }
+/// ```
+#[cfg(windows)]
+pub fn windows_config_locations() -> Vec<PathBuf> {
+    ["ProgramData", "APPDATA"]
+        .iter()
+        .filter_map(|var| env::var_os(var))
+        .map(|base| PathBuf::from(base).join("OVOS").join("mycroft.conf"))
+        .collect()
 }
 
 /// Returns the webcache location.
@@ -169,9 +304,9 @@ pub fn get_config_locations() -> Vec<PathBuf> {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let webcache_location = config_locations::get_webcache_location();
+/// let webcache_location = locations::get_webcache_location();
 /// println!("Webcache location: {:?}", webcache_location);
 /// ```
 pub fn get_webcache_location() -> PathBuf {
@@ -183,9 +318,9 @@ pub fn get_webcache_location() -> PathBuf {
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let xdg_config_locations = config_locations::get_xdg_config_locations();
+/// let xdg_config_locations = locations::get_xdg_config_locations();
 /// println!("XDG config locations: {:?}", xdg_config_locations);
 /// ```
 pub fn get_xdg_config_locations() -> Vec<PathBuf> {
@@ -196,14 +331,69 @@ pub fn get_xdg_config_locations() -> Vec<PathBuf> {
         .collect()
 }
 
+/// Returns the files matching `pattern`, e.g. `mycroft.*.conf`, relative to
+/// the XDG config save path, sorted. Complements the fixed locations of
+/// [`get_xdg_config_locations`] for deployments that drop several config
+/// files into one directory. An invalid pattern is logged and matches nothing.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::locations;
+///
+/// for path in locations::glob_config_locations("mycroft.*.conf") {
+///     println!("Extra config: {:?}", path);
+/// }
+/// ```
+pub fn glob_config_locations(pattern: &str) -> Vec<PathBuf> {
+    let base = get_xdg_config_save_path(None);
+    let full = format!(
+        "{}/{}",
+        glob::Pattern::escape(&base.to_string_lossy()),
+        pattern
+    );
+    let paths = match glob::glob(&full) {
+        Ok(paths) => paths,
+        Err(e) => {
+            warn(&format!("Invalid config glob {:?}: {}", pattern, e));
+            return Vec::new();
+        }
+    };
+    let mut found: Vec<PathBuf> = paths.flatten().filter(|p| p.is_file()).collect();
+    found.sort();
+    found
+}
+
+/// Returns the usual fallbacks for storing the user config when its home is
+/// read-only, for [`LocalConfBuilder::store_fallbacks`]: `mycroft.conf` in the
+/// XDG config home, then the XDG state home, then the temp dir.
+///
+/// [`LocalConfBuilder::store_fallbacks`]: crate::config::LocalConfBuilder::store_fallbacks
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::locations;
+///
+/// let fallbacks = locations::store_fallback_locations();
+/// assert_eq!(fallbacks.len(), 3);
+/// ```
+pub fn store_fallback_locations() -> Vec<PathBuf> {
+    vec![
+        get_xdg_config_save_path(None).join("mycroft.conf"),
+        xdg::xdg_state_home().join("mycroft").join("mycroft.conf"),
+        env::temp_dir().join("mycroft").join("mycroft.conf"),
+    ]
+}
+
 /// Returns the path to the default config file.
 ///
 /// # Examples
 ///
 /// ```
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// let default_config = config_locations::find_default_config();
+/// let default_config = locations::find_default_config();
 /// println!("Default config path: {:?}", default_config);
 /// ```
 pub fn find_default_config() -> PathBuf {
@@ -216,14 +406,214 @@ lazy_static::lazy_static! {
         .unwrap_or_else(|_| String::from("/usr/share/mycroft/mycroft.conf")));
     pub static ref SYSTEM_CONFIG: PathBuf = PathBuf::from(env::var("MYCROFT_SYSTEM_CONFIG")
         .unwrap_or_else(|_| String::from("/etc/mycroft/mycroft.conf")));
-    pub static ref OLD_USER_CONFIG: PathBuf = Path::new(&env::var("HOME").unwrap_or_else(|_| String::from("/")))
-        .join(".mycroft/mycroft.conf");
-    pub static ref USER_CONFIG: PathBuf = get_xdg_config_save_path(None).join("mycroft.conf");
     pub static ref REMOTE_CONFIG: &'static str = "mycroft.ai";
     pub static ref WEB_CONFIG_CACHE: PathBuf = PathBuf::from(env::var("MYCROFT_WEB_CACHE")
         .unwrap_or_else(|_| get_webcache_location().to_string_lossy().into_owned()));
 }
 
+/// The legacy `~/.mycroft/mycroft.conf`.
+pub static OLD_USER_CONFIG: EnvPath = EnvPath::new(old_user_config);
+/// The user's `mycroft.conf` under the XDG config home.
+pub static USER_CONFIG: EnvPath = EnvPath::new(user_config);
+
+// Helper function to resolve `OLD_USER_CONFIG`
+fn old_user_config() -> PathBuf {
+    xdg::home_dir().join(".mycroft/mycroft.conf")
+}
+
+// Helper function to resolve `USER_CONFIG`
+fn user_config() -> PathBuf {
+    get_xdg_config_save_path(None).join("mycroft.conf")
+}
+
+/// A path derived from `HOME` and the XDG environment variables, resolved on
+/// first use and again by [`xdg::refresh_env_cache`]. Derefs to the
+/// [`PathBuf`] it currently holds.
+///
+/// Each distinct path a refresh moves to is kept for the rest of the process,
+/// so references handed out earlier stay valid.
+pub struct EnvPath {
+    resolve: fn() -> PathBuf,
+    current: RwLock<Option<&'static PathBuf>>,
+}
+
+impl EnvPath {
+    const fn new(resolve: fn() -> PathBuf) -> Self {
+        Self {
+            resolve,
+            current: RwLock::new(None),
+        }
+    }
+
+    // Helper function to resolve the path again, keeping the current one when
+    // it didn't move
+    fn refresh(&self) -> &'static PathBuf {
+        let path = xdg::without_override(self.resolve);
+        let mut current = self.current.write();
+        match *current {
+            Some(existing) if *existing == path => existing,
+            _ => current.insert(Box::leak(Box::new(path))),
+        }
+    }
+}
+
+impl std::ops::Deref for EnvPath {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        if let Some(path) = *self.current.read() {
+            return path;
+        }
+        self.refresh()
+    }
+}
+
+impl std::fmt::Debug for EnvPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// Helper function for `xdg::refresh_env_cache`
+pub(crate) fn refresh_env_paths() {
+    OLD_USER_CONFIG.refresh();
+    USER_CONFIG.refresh();
+}
+
+/// A config file or folder that any user may modify, found by [`audit_permissions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionIssue {
+    pub path: PathBuf,
+    /// The permission bits, e.g. `0o666`.
+    pub mode: u32,
+    pub is_dir: bool,
+}
+
+impl std::fmt::Display for PermissionIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.is_dir { "directory" } else { "file" };
+        write!(
+            f,
+            "{:?} is a world-writable {} (mode {:o})",
+            self.path, kind, self.mode
+        )
+    }
+}
+
+/// Reports existing config files, and the folders holding them, that are
+/// world-writable and so let any local user change the assistant's config.
+/// Folders with the sticky bit set (like `/tmp`) are not reported since other
+/// users can't replace files in them. This only diagnoses; nothing is changed.
+/// Always empty on non-Unix platforms.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::locations;
+///
+/// for issue in locations::audit_permissions() {
+///     println!("{}", issue);
+/// }
+/// ```
+pub fn audit_permissions() -> Vec<PermissionIssue> {
+    let mut paths = get_config_locations();
+    paths.extend(get_xdg_config_locations());
+    paths.push(DISTRIBUTION_CONFIG.to_path_buf());
+    paths.push(SYSTEM_CONFIG.to_path_buf());
+    let mut issues: Vec<PermissionIssue> = Vec::new();
+    for path in paths.iter().filter(|p| p.is_file()) {
+        let candidates = [Some(path.as_path()), path.parent()];
+        for candidate in candidates.into_iter().flatten() {
+            if issues.iter().any(|i| i.path == candidate) {
+                continue;
+            }
+            if let Some(issue) = world_writable(candidate) {
+                issues.push(issue);
+            }
+        }
+    }
+    issues
+}
+
+/// Helper function to check a single path for world-writable permissions.
+#[cfg(unix)]
+fn world_writable(path: &Path) -> Option<PermissionIssue> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    let mode = metadata.permissions().mode() & 0o7777;
+    let sticky_dir = metadata.is_dir() && mode & 0o1000 != 0;
+    (mode & 0o002 != 0 && !sticky_dir).then(|| PermissionIssue {
+        path: path.to_path_buf(),
+        mode,
+        is_dir: metadata.is_dir(),
+    })
+}
+
+#[cfg(not(unix))]
+fn world_writable(_path: &Path) -> Option<PermissionIssue> {
+    None
+}
+
+/// Bytes used by the files under each XDG save path, see [`dir_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DirUsage {
+    pub config: u64,
+    pub data: u64,
+    pub cache: u64,
+}
+
+impl DirUsage {
+    pub fn total(&self) -> u64 {
+        self.config + self.data + self.cache
+    }
+}
+
+/// Sums the sizes of the files under the XDG config, data and cache save
+/// paths, e.g. to report how much storage the assistant uses on a small
+/// device. Missing folders count as zero and unreadable subfolders are skipped
+/// with a warning. Symlinks are not followed.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::locations;
+///
+/// let usage = locations::dir_usage();
+/// println!("config {} data {} cache {}", usage.config, usage.data, usage.cache);
+/// ```
+pub fn dir_usage() -> DirUsage {
+    DirUsage {
+        config: dir_size(&get_xdg_config_save_path(None)),
+        data: dir_size(&get_xdg_data_save_path(None)),
+        cache: dir_size(&get_xdg_cache_save_path(None)),
+    }
+}
+
+/// Helper function to total the file sizes below `path`.
+fn dir_size(path: &Path) -> u64 {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return 0,
+        Err(e) => {
+            warn(&format!("Skipping {:?} in disk usage: {}", path, e));
+            return 0;
+        }
+    };
+    let mut total = 0;
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            total += dir_size(&entry.path());
+        } else if metadata.is_file() {
+            total += metadata.len();
+        }
+    }
+    total
+}
+
 /// Ensures that the directory for the specified path exists.
 ///
 /// # Arguments
@@ -234,12 +624,254 @@ lazy_static::lazy_static! {
 ///
 /// ```
 /// use std::path::PathBuf;
-/// use mycroft_config::config_locations;
+/// use ovos_config::locations;
 ///
-/// config_locations::ensure_folder_exists(&PathBuf::from("/tmp/mycroft/test.conf"));
+/// locations::ensure_folder_exists(&PathBuf::from("/tmp/mycroft/test.conf"));
 /// ```
 pub fn ensure_folder_exists(path: &Path) {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_locations_use_programdata_then_appdata() {
+        let _env = crate::testing::env_lock();
+        let saved: Vec<_> = ["ProgramData", "APPDATA"]
+            .iter()
+            .map(|var| (var, env::var_os(var)))
+            .collect();
+        env::set_var("ProgramData", r"C:\ProgramData");
+        env::set_var("APPDATA", r"C:\Users\ovos\AppData\Roaming");
+        let both = windows_config_locations();
+        let listed = get_config_locations();
+        env::remove_var("APPDATA");
+        let machine_only = windows_config_locations();
+        for (var, value) in saved {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+
+        let machine = PathBuf::from(r"C:\ProgramData\OVOS\mycroft.conf");
+        let user = PathBuf::from(r"C:\Users\ovos\AppData\Roaming\OVOS\mycroft.conf");
+        assert_eq!(both, [machine.clone(), user.clone()]);
+        assert!(listed.ends_with(&[machine.clone(), user]));
+        assert_eq!(machine_only, [machine]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn audit_permissions_flags_world_writable_config() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::testing::{isolated, user_config, write_file};
+
+        isolated(|root| {
+            let path = user_config(root);
+            write_file(&path, "{}");
+            let set_mode = |path: &Path, mode| {
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap()
+            };
+            set_mode(&path, 0o644);
+            set_mode(path.parent().unwrap(), 0o755);
+            assert!(audit_permissions()
+                .iter()
+                .all(|i| !i.path.starts_with(root)));
+
+            set_mode(&path, 0o666);
+            set_mode(path.parent().unwrap(), 0o1777);
+            let issues: Vec<_> = audit_permissions()
+                .into_iter()
+                .filter(|i| i.path.starts_with(root))
+                .collect();
+            assert_eq!(
+                issues,
+                [PermissionIssue {
+                    path: path.clone(),
+                    mode: 0o666,
+                    is_dir: false,
+                }]
+            );
+
+            set_mode(path.parent().unwrap(), 0o777);
+            let dir_issue = audit_permissions()
+                .into_iter()
+                .find(|i| i.path == path.parent().unwrap())
+                .unwrap();
+            assert!(dir_issue.is_dir);
+            assert_eq!(dir_issue.mode, 0o777);
+        });
+    }
+
+    #[test]
+    fn dir_usage_totals_known_file_sizes() {
+        use crate::testing::{isolated, write_file};
+
+        isolated(|root| {
+            let sized = |path: &str, len: usize| write_file(&root.join(path), &"x".repeat(len));
+            sized("config/mycroft/mycroft.conf", 100);
+            sized("config/mycroft/skills/weather/settings.json", 50);
+            sized("config/other-app/settings.json", 7);
+            sized("data/mycroft/models/model.bin", 1000);
+            sized("data/mycroft/intent_cache/a.intent", 24);
+            std::fs::create_dir_all(root.join("cache/mycroft/empty")).unwrap();
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(
+                root.join("data/mycroft/models/model.bin"),
+                root.join("cache/mycroft/model.bin"),
+            )
+            .unwrap();
+
+            assert_eq!(
+                dir_usage(),
+                DirUsage {
+                    config: 150,
+                    data: 1024,
+                    cache: 0,
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn store_fallback_locations_follow_xdg_config_then_state_then_temp() {
+        crate::testing::isolated(|root| {
+            assert_eq!(
+                store_fallback_locations(),
+                [
+                    root.join("config/mycroft/mycroft.conf"),
+                    root.join("state/mycroft/mycroft.conf"),
+                    env::temp_dir().join("mycroft/mycroft.conf"),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn config_save_path_sub_joins_and_sanitizes_segments() {
+        crate::testing::isolated(|root| {
+            let base = root.join("config");
+            assert_eq!(
+                get_xdg_config_save_path_sub(&["mycroft", "skills", "my.skill"]),
+                base.join("mycroft/skills/my.skill")
+            );
+            assert_eq!(get_xdg_config_save_path_sub(&[]), base);
+            for (segments, expected) in [
+                (&["mycroft", "..", "skills"][..], "mycroft/skills"),
+                (&["mycroft", ".", "skills"], "mycroft/skills"),
+                (&["mycroft", "", "skills"], "mycroft/skills"),
+                (&["..", "..", "etc"], "etc"),
+                (&["mycroft", "/abs"], "mycroft/_abs"),
+                (&["mycroft", "a\\b"], "mycroft/a_b"),
+                (&["mycroft", "../../etc/passwd"], "mycroft/.._.._etc_passwd"),
+            ] {
+                assert_eq!(
+                    get_xdg_config_save_path_sub(segments),
+                    base.join(expected),
+                    "{:?}",
+                    segments
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn skill_data_and_cache_paths_stay_under_their_homes() {
+        use crate::xdg::{with_xdg_dirs, XdgDirs};
+
+        crate::testing::isolated(|root| {
+            let skill = "skill-weather.openvoiceos";
+            assert_eq!(
+                skill_data_path(skill),
+                root.join("data/mycroft/skills/skill-weather.openvoiceos")
+            );
+            assert_eq!(
+                skill_cache_path(skill),
+                root.join("cache/mycroft/skills/skill-weather.openvoiceos")
+            );
+
+            assert_eq!(
+                skill_data_path("../../../etc"),
+                root.join("data/mycroft/skills/.._.._.._etc")
+            );
+            assert_eq!(skill_cache_path(".."), root.join("cache/mycroft/skills"));
+
+            let dirs = XdgDirs {
+                data_home: Some(root.join("srv/data")),
+                cache_home: Some(root.join("srv/cache")),
+                ..Default::default()
+            };
+            let (data, cache) =
+                with_xdg_dirs(dirs, || (skill_data_path(skill), skill_cache_path(skill)));
+            assert_eq!(
+                data,
+                root.join("srv/data/mycroft/skills/skill-weather.openvoiceos")
+            );
+            assert_eq!(
+                cache,
+                root.join("srv/cache/mycroft/skills/skill-weather.openvoiceos")
+            );
+        });
+    }
+
+    #[test]
+    fn existing_skill_settings_lists_only_skills_with_settings() {
+        use crate::testing::{isolated, write_file};
+
+        isolated(|root| {
+            assert!(existing_skill_settings().is_empty());
+
+            let skills = root.join("config/mycroft/skills");
+            write_file(&skills.join("skill-b/settings.json"), "{}");
+            write_file(&skills.join("skill-a/settings.json"), "{}");
+            std::fs::create_dir_all(skills.join("skill-c")).unwrap();
+            write_file(&skills.join("skill-d/settings.yaml"), "");
+            std::fs::create_dir_all(skills.join("skill-e/settings.json")).unwrap();
+            write_file(&skills.join("settings.json"), "{}");
+
+            assert_eq!(
+                existing_skill_settings(),
+                [
+                    ("skill-a".to_string(), skills.join("skill-a/settings.json")),
+                    ("skill-b".to_string(), skills.join("skill-b/settings.json")),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn glob_config_locations_matches_files_in_config_save_path() {
+        use crate::testing::{isolated, write_file};
+
+        isolated(|root| {
+            let mycroft = root.join("config/mycroft");
+            for name in [
+                "mycroft.b.conf",
+                "mycroft.a.conf",
+                "mycroft.conf",
+                "other.a.conf",
+            ] {
+                write_file(&mycroft.join(name), "{}");
+            }
+            std::fs::create_dir_all(mycroft.join("mycroft.dir.conf")).unwrap();
+            write_file(&root.join("config/mycroft.c.conf"), "{}");
+
+            assert_eq!(
+                glob_config_locations("mycroft.*.conf"),
+                [
+                    mycroft.join("mycroft.a.conf"),
+                    mycroft.join("mycroft.b.conf")
+                ]
+            );
+            assert!(glob_config_locations("*.yaml").is_empty());
+            assert!(glob_config_locations("mycroft.[.conf").is_empty());
+        });
+    }
+}
@@ -0,0 +1,79 @@
+//! Helpers shared by the unit tests.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::xdg::{self, XdgDirs};
+
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Serializes tests that read or change the process environment, such as
+/// `OVOS_PROFILE` or `OVOS_CONFIG__*` overrides.
+pub(crate) fn env_lock() -> MutexGuard<'static, ()> {
+    ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `f` with every XDG path of this thread inside a fresh temp dir, whose
+/// root is passed to `f`. Holds [`env_lock`] throughout, so the process
+/// environment can be changed safely inside `f`.
+pub(crate) fn isolated<R>(f: impl FnOnce(&Path) -> R) -> R {
+    let _env = env_lock();
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let dirs = XdgDirs {
+        cache_home: Some(root.join("cache")),
+        config_dirs: Some(vec![root.join("etc/xdg")]),
+        config_home: Some(root.join("config")),
+        data_dirs: Some(vec![root.join("share")]),
+        data_home: Some(root.join("data")),
+        home_fallback: Some(root.join("home")),
+        runtime_dir: Some(root.join("run")),
+        state_home: Some(root.join("state")),
+    };
+    xdg::with_xdg_dirs(dirs, || f(root))
+}
+
+/// Writes `contents` to `path`, creating its parent directories.
+pub(crate) fn write_file(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+/// Returns the user config path inside an [`isolated`] root.
+pub(crate) fn user_config(root: &Path) -> PathBuf {
+    root.join("config/mycroft/mycroft.conf")
+}
+
+/// Runs `f` with `HOME` unset, so [`xdg::home_dir`] falls back to the
+/// [`isolated`] root's `home`. Call it inside [`isolated`].
+pub(crate) fn without_home<R>(f: impl FnOnce() -> R) -> R {
+    let home = std::env::var_os("HOME");
+    std::env::remove_var("HOME");
+    let result = f();
+    if let Some(home) = home {
+        std::env::set_var("HOME", home);
+    }
+    result
+}
+
+thread_local! {
+    static DENIED: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with reads of `path` on this thread failing with
+/// `PermissionDenied`, which a file mode can't cause when tests run as root.
+pub(crate) fn deny_reads<R>(path: &Path, f: impl FnOnce() -> R) -> R {
+    DENIED.with(|d| d.borrow_mut().push(path.to_path_buf()));
+    let result = f();
+    DENIED.with(|d| d.borrow_mut().pop());
+    result
+}
+
+/// Fails if [`deny_reads`] is denying reads of `path`.
+pub(crate) fn check_read(path: &Path) -> std::io::Result<()> {
+    if DENIED.with(|d| d.borrow().iter().any(|p| p == path)) {
+        return Err(std::io::ErrorKind::PermissionDenied.into());
+    }
+    Ok(())
+}
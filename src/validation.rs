@@ -0,0 +1,369 @@
+//! Programmatic validation rules for constraints that are awkward to express
+//! in a schema, such as "`listener.sample_rate` must be 8000, 16000 or 48000".
+//!
+//! # Examples
+//!
+//! ```
+//! use ovos_config::config::ConfigDict;
+//! use ovos_config::validation::{one_of, required, ValidationRule};
+//! use serde_json::json;
+//!
+//! let rules: Vec<Box<dyn ValidationRule>> = vec![
+//!     required("lang"),
+//!     one_of("listener.sample_rate", [8000, 16000, 48000]),
+//! ];
+//!
+//! let config: ConfigDict = serde_json::from_value(json!({"listener": {"sample_rate": 22050}})).unwrap();
+//! let failures: Vec<String> = rules
+//!     .iter()
+//!     .filter_map(|rule| rule.check(&config))
+//!     .map(|e| e.path)
+//!     .collect();
+//! assert_eq!(failures, ["lang", "listener.sample_rate"]);
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::config::{get_path, parse_duration, parse_size_bytes, ConfigDict};
+
+/// A failed validation rule, naming the offending dotted path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A single check run against a merged configuration.
+pub trait ValidationRule {
+    /// Returns `None` when the config satisfies the rule.
+    fn check(&self, config: &ConfigDict) -> Option<ValidationError>;
+}
+
+impl<F> ValidationRule for F
+where
+    F: Fn(&ConfigDict) -> Option<ValidationError>,
+{
+    fn check(&self, config: &ConfigDict) -> Option<ValidationError> {
+        self(config)
+    }
+}
+
+/// Requires the value at `path`, when present, to be one of `values`.
+pub fn one_of<V: Into<Value>>(
+    path: &str,
+    values: impl IntoIterator<Item = V>,
+) -> Box<dyn ValidationRule> {
+    let path = path.to_string();
+    let values: Vec<Value> = values.into_iter().map(Into::into).collect();
+    Box::new(move |config: &ConfigDict| {
+        let value = get_path(config, &path)?;
+        if values.contains(value) {
+            None
+        } else {
+            let allowed: Vec<String> = values.iter().map(Value::to_string).collect();
+            Some(ValidationError {
+                path: path.clone(),
+                message: format!("{} is not one of {}", value, allowed.join(", ")),
+            })
+        }
+    })
+}
+
+/// Requires a value to be present at `path`.
+pub fn required(path: &str) -> Box<dyn ValidationRule> {
+    let path = path.to_string();
+    Box::new(move |config: &ConfigDict| match get_path(config, &path) {
+        Some(_) => None,
+        None => Some(ValidationError {
+            path: path.clone(),
+            message: "required value is missing".to_string(),
+        }),
+    })
+}
+
+/// Unit a string value must parse as, for [`unit`] and [`units`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// A number of seconds, or a string such as `"30s"`, `"500ms"` or `"2h"`
+    Duration,
+    /// A byte count, or a string such as `"16MB"` or `"16MiB"`
+    Size,
+}
+
+impl Unit {
+    fn accepts(self, value: &Value) -> bool {
+        match (self, value) {
+            (Unit::Duration, Value::Number(n)) => n.as_f64().is_some_and(|n| n >= 0.0),
+            (Unit::Duration, Value::String(s)) => parse_duration(s).is_some(),
+            (Unit::Size, Value::Number(n)) => n.is_u64(),
+            (Unit::Size, Value::String(s)) => parse_size_bytes(s).is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Duration => write!(f, "duration"),
+            Unit::Size => write!(f, "size"),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "duration" => Ok(Unit::Duration),
+            "size" => Ok(Unit::Size),
+            other => Err(format!("unknown unit {:?}", other)),
+        }
+    }
+}
+
+/// Requires the value at `path`, when present, to parse as `unit`.
+pub fn unit(path: &str, unit: Unit) -> Box<dyn ValidationRule> {
+    let path = path.to_string();
+    Box::new(move |config: &ConfigDict| {
+        let value = get_path(config, &path)?;
+        if unit.accepts(value) {
+            None
+        } else {
+            Some(ValidationError {
+                path: path.clone(),
+                message: format!("{} is not a valid {}", value, unit),
+            })
+        }
+    })
+}
+
+/// Builds [`unit`] rules from a companion schema that mirrors the config
+/// tree with unit names at the leaves.
+///
+/// Fails on the first leaf that isn't a known unit name.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::config::ConfigDict;
+/// use ovos_config::validation::units;
+/// use serde_json::json;
+///
+/// let rules = units(&json!({"listener": {"timeout": "duration"}})).unwrap();
+///
+/// let config: ConfigDict = serde_json::from_value(json!({"listener": {"timeout": "30s"}})).unwrap();
+/// assert!(rules[0].check(&config).is_none());
+///
+/// let config: ConfigDict = serde_json::from_value(json!({"listener": {"timeout": "soon"}})).unwrap();
+/// let error = rules[0].check(&config).unwrap();
+/// assert_eq!(error.path, "listener.timeout");
+/// assert_eq!(error.message, "\"soon\" is not a valid duration");
+/// ```
+pub fn units(schema: &Value) -> Result<Vec<Box<dyn ValidationRule>>, ValidationError> {
+    let mut rules = Vec::new();
+    collect_units("", schema, &mut rules)?;
+    Ok(rules)
+}
+
+// Helper function to gather a rule for every unit name below `path`
+fn collect_units(
+    path: &str,
+    schema: &Value,
+    rules: &mut Vec<Box<dyn ValidationRule>>,
+) -> Result<(), ValidationError> {
+    match schema {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                collect_units(&child_path, child, rules)?;
+            }
+            Ok(())
+        }
+        Value::String(name) => {
+            let parsed = name.parse().map_err(|message| ValidationError {
+                path: path.to_string(),
+                message,
+            })?;
+            rules.push(unit(path, parsed));
+            Ok(())
+        }
+        other => Err(ValidationError {
+            path: path.to_string(),
+            message: format!("expected a unit name, found {}", other),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Helper function to build a config from a JSON object
+    fn config(value: Value) -> ConfigDict {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn one_of_passes_for_allowed_value() {
+        let rule = one_of("listener.sample_rate", [8000, 16000, 48000]);
+        assert_eq!(
+            rule.check(&config(json!({"listener": {"sample_rate": 16000}}))),
+            None
+        );
+    }
+
+    #[test]
+    fn one_of_fails_for_other_value() {
+        let rule = one_of("listener.sample_rate", [8000, 16000, 48000]);
+        assert_eq!(
+            rule.check(&config(json!({"listener": {"sample_rate": 22050}}))),
+            Some(ValidationError {
+                path: "listener.sample_rate".to_string(),
+                message: "22050 is not one of 8000, 16000, 48000".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn one_of_ignores_missing_value() {
+        let rule = one_of("listener.sample_rate", [16000]);
+        assert_eq!(rule.check(&config(json!({}))), None);
+    }
+
+    #[test]
+    fn required_reports_missing_value() {
+        let rule = required("lang");
+        assert_eq!(rule.check(&config(json!({"lang": "en-us"}))), None);
+        assert_eq!(
+            rule.check(&config(json!({}))),
+            Some(ValidationError {
+                path: "lang".to_string(),
+                message: "required value is missing".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn unit_size_accepts_byte_counts_and_size_strings() {
+        let rule = unit("cache.max_size", Unit::Size);
+        for ok in [json!(1024), json!("16MB"), json!("16 MiB"), json!("512")] {
+            assert_eq!(
+                rule.check(&config(json!({"cache": {"max_size": ok}}))),
+                None
+            );
+        }
+        for bad in [
+            json!(-1),
+            json!(1.5),
+            json!("lots"),
+            json!("16XB"),
+            json!(true),
+        ] {
+            assert_eq!(
+                rule.check(&config(json!({"cache": {"max_size": bad.clone()}}))),
+                Some(ValidationError {
+                    path: "cache.max_size".to_string(),
+                    message: format!("{} is not a valid size", bad),
+                })
+            );
+        }
+        assert_eq!(rule.check(&config(json!({}))), None);
+    }
+
+    #[test]
+    fn unit_duration_rejects_negative_and_non_numeric_values() {
+        let rule = unit("timeout", Unit::Duration);
+        for ok in [json!(0), json!(2.5), json!("30s"), json!("500 ms")] {
+            assert_eq!(rule.check(&config(json!({"timeout": ok}))), None);
+        }
+        for bad in [
+            json!(-1),
+            json!(-0.5),
+            json!("-5s"),
+            json!("soon"),
+            json!(null),
+            json!([30]),
+        ] {
+            assert_eq!(
+                rule.check(&config(json!({"timeout": bad.clone()}))),
+                Some(ValidationError {
+                    path: "timeout".to_string(),
+                    message: format!("{} is not a valid duration", bad),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn units_builds_a_rule_per_leaf() {
+        let rules = units(&json!({
+            "listener": {"timeout": "duration"},
+            "cache": {"max_size": "size"}
+        }))
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        let errors: Vec<_> = rules
+            .iter()
+            .filter_map(|rule| {
+                rule.check(&config(json!({
+                    "listener": {"timeout": "soon"},
+                    "cache": {"max_size": "16MB"}
+                })))
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [ValidationError {
+                path: "listener.timeout".to_string(),
+                message: "\"soon\" is not a valid duration".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn units_rejects_unknown_unit_names() {
+        assert_eq!(
+            units(&json!({"listener": {"timeout": "fortnight"}})).err(),
+            Some(ValidationError {
+                path: "listener.timeout".to_string(),
+                message: "unknown unit \"fortnight\"".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn units_rejects_non_string_leaves() {
+        assert_eq!(
+            units(&json!({"listener": {"timeout": 30}})).err(),
+            Some(ValidationError {
+                path: "listener.timeout".to_string(),
+                message: "expected a unit name, found 30".to_string(),
+            })
+        );
+        assert_eq!(
+            units(&json!(["duration"])).err(),
+            Some(ValidationError {
+                path: String::new(),
+                message: "expected a unit name, found [\"duration\"]".to_string(),
+            })
+        );
+    }
+}
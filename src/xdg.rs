@@ -5,10 +5,19 @@
 //!
 //! It includes functions to get the XDG cache, config, and data directories,
 //! as well as the runtime directory.
+//!
+//! Paths are read from the environment on every call. The few paths
+//! `locations` keeps in statics are re-resolved by [`refresh_env_cache`].
+//!
+//! With the `xdg-override` feature, and in this crate's own unit tests,
+//! [`with_xdg_dirs`] substitutes paths for the current thread only, so tests
+//! can use isolated layouts without touching the process environment.
 
 use std::env;
 use std::path::{Path, PathBuf};
 
+use crate::log::warn;
+
 /// Returns the path to the XDG cache home directory.
 ///
 /// This function follows the XDG Base Directory Specification. It returns a `PathBuf` containing:
@@ -24,6 +33,10 @@ use std::path::{Path, PathBuf};
 /// println!("XDG cache home: {:?}", cache_home);
 /// ```
 pub fn xdg_cache_home() -> PathBuf {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.cache_home.clone()) {
+        return p;
+    }
     path_from_env("XDG_CACHE_HOME", || home_dir().join(".cache"))
 }
 
@@ -46,6 +59,10 @@ pub fn xdg_cache_home() -> PathBuf {
 /// }
 /// ```
 pub fn xdg_config_dirs() -> Vec<PathBuf> {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.config_dirs.clone()) {
+        return p;
+    }
     paths_from_env("XDG_CONFIG_DIRS", || vec![PathBuf::from("/etc/xdg")])
 }
 
@@ -64,6 +81,10 @@ pub fn xdg_config_dirs() -> Vec<PathBuf> {
 /// println!("XDG config home: {:?}", config_home);
 /// ```
 pub fn xdg_config_home() -> PathBuf {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.config_home.clone()) {
+        return p;
+    }
     path_from_env("XDG_CONFIG_HOME", || home_dir().join(".config"))
 }
 
@@ -86,6 +107,10 @@ pub fn xdg_config_home() -> PathBuf {
 /// }
 /// ```
 pub fn xdg_data_dirs() -> Vec<PathBuf> {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.data_dirs.clone()) {
+        return p;
+    }
     paths_from_env("XDG_DATA_DIRS", || {
         vec![
             PathBuf::from("/usr/local/share"),
@@ -109,6 +134,10 @@ pub fn xdg_data_dirs() -> Vec<PathBuf> {
 /// println!("XDG data home: {:?}", data_home);
 /// ```
 pub fn xdg_data_home() -> PathBuf {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.data_home.clone()) {
+        return p;
+    }
     path_from_env("XDG_DATA_HOME", || home_dir().join(".local").join("share"))
 }
 
@@ -130,6 +159,10 @@ pub fn xdg_data_home() -> PathBuf {
 /// }
 /// ```
 pub fn xdg_runtime_dir() -> Option<PathBuf> {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.runtime_dir.clone()) {
+        return Some(p);
+    }
     env::var_os("XDG_RUNTIME_DIR")
         .and_then(|os_str| os_str.into_string().ok())
         .and_then(|s| {
@@ -156,9 +189,120 @@ pub fn xdg_runtime_dir() -> Option<PathBuf> {
 /// println!("XDG state home: {:?}", state_home);
 /// ```
 pub fn xdg_state_home() -> PathBuf {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.state_home.clone()) {
+        return p;
+    }
     path_from_env("XDG_STATE_HOME", || home_dir().join(".local").join("state"))
 }
 
+/// Re-resolves the paths in [`crate::locations`] that are derived from the
+/// XDG environment variables and `HOME` but kept in statics, such as
+/// [`crate::locations::USER_CONFIG`].
+///
+/// The functions in this module read the environment on every call, so this
+/// is only needed when a process changes the variables mid-run, e.g. a
+/// session manager moving `XDG_CONFIG_HOME`.
+/// [`crate::config::Configuration::reload`] calls it before re-resolving its
+/// layer paths. Thread-local overrides from `with_xdg_dirs` are ignored, so
+/// they never leak into the process-wide paths.
+///
+/// # Examples
+///
+/// ```
+/// use ovos_config::xdg;
+///
+/// xdg::refresh_env_cache();
+/// println!("XDG config home: {:?}", xdg::xdg_config_home());
+/// ```
+pub fn refresh_env_cache() {
+    crate::locations::refresh_env_paths();
+}
+
+/// XDG base paths that replace the environment for the current thread while
+/// [`with_xdg_dirs`] runs. Fields left `None` still come from the environment.
+#[cfg(any(test, feature = "xdg-override"))]
+#[derive(Clone, Debug, Default)]
+pub struct XdgDirs {
+    pub cache_home: Option<PathBuf>,
+    pub config_dirs: Option<Vec<PathBuf>>,
+    pub config_home: Option<PathBuf>,
+    pub data_dirs: Option<Vec<PathBuf>>,
+    pub data_home: Option<PathBuf>,
+    /// Used by [`home_dir`] instead of the passwd entry and `/` when `HOME`
+    /// is unset or empty.
+    pub home_fallback: Option<PathBuf>,
+    pub runtime_dir: Option<PathBuf>,
+    pub state_home: Option<PathBuf>,
+}
+
+#[cfg(any(test, feature = "xdg-override"))]
+thread_local! {
+    static OVERRIDE: std::cell::RefCell<Option<XdgDirs>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Runs `f` with `dirs` overriding the XDG paths on this thread, restoring the
+/// previous override afterwards, even if `f` panics. Other threads are not
+/// affected.
+///
+/// Paths kept in statics such as `locations::USER_CONFIG` are resolved from
+/// the environment alone and don't see the override.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "xdg-override")]
+/// # {
+/// use std::path::PathBuf;
+/// use ovos_config::xdg::{self, XdgDirs};
+///
+/// let dirs = XdgDirs {
+///     config_home: Some(PathBuf::from("/tmp/test-config")),
+///     ..Default::default()
+/// };
+/// let config_home = xdg::with_xdg_dirs(dirs, xdg::xdg_config_home);
+/// assert_eq!(config_home, PathBuf::from("/tmp/test-config"));
+/// # }
+/// ```
+#[cfg(any(test, feature = "xdg-override"))]
+pub fn with_xdg_dirs<R>(dirs: XdgDirs, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<XdgDirs>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            OVERRIDE.with(|o| *o.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(OVERRIDE.with(|o| o.borrow_mut().replace(dirs)));
+    f()
+}
+
+/// Helper function to run `f` with the current thread's override suspended, so
+/// paths resolved for the whole process come from the environment alone.
+pub(crate) fn without_override<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(dirs) = OVERRIDE.with(|o| o.borrow_mut().take()) {
+        struct Restore(Option<XdgDirs>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                OVERRIDE.with(|o| *o.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(Some(dirs));
+        return f();
+    }
+    f()
+}
+
+/// Helper function to read one field of the current thread's override.
+#[cfg(any(test, feature = "xdg-override"))]
+fn overridden<T>(field: impl FnOnce(&XdgDirs) -> Option<T>) -> Option<T> {
+    OVERRIDE.with(|o| o.borrow().as_ref().and_then(field))
+}
+
 /// Helper function to get a path from an environment variable or use a default.
 fn path_from_env<F>(var: &str, default: F) -> PathBuf
 where
@@ -166,8 +310,7 @@ where
 {
     env::var_os(var)
         .and_then(|os_str| os_str.into_string().ok())
-        .filter(|s| !s.is_empty() && Path::new(s).is_absolute())
-        .map(PathBuf::from)
+        .and_then(|s| env_path(&s))
         .unwrap_or_else(default)
 }
 
@@ -178,20 +321,341 @@ where
 {
     env::var_os(var)
         .and_then(|os_str| os_str.into_string().ok())
-        .map(|s| {
-            s.split(':')
-                .filter(|path| !path.is_empty() && Path::new(path).is_absolute())
-                .map(PathBuf::from)
-                .collect()
-        })
+        .map(|s| s.split(':').filter_map(env_path).collect())
         .filter(|paths: &Vec<PathBuf>| !paths.is_empty())
         .unwrap_or_else(default)
 }
 
-/// Helper function to get the user's home directory.
-fn home_dir() -> PathBuf {
+/// Helper function to turn one env path entry into a path.
+///
+/// A leading `~` is expanded to the home directory, since shells don't expand
+/// it inside `VAR=~/dir` in every context, and trailing slashes are dropped.
+/// The spec's absolute-path requirement applies after expansion.
+fn env_path(s: &str) -> Option<PathBuf> {
+    let expanded = match s.strip_prefix('~') {
+        Some("") => home_dir(),
+        Some(rest) if rest.starts_with('/') => home_dir().join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(s),
+    };
+    let trimmed = expanded.to_str()?.trim_end_matches('/');
+    let path = if trimmed.is_empty() && expanded.has_root() {
+        PathBuf::from("/")
+    } else {
+        PathBuf::from(trimmed)
+    };
+    Some(path).filter(|p| p.is_absolute())
+}
+
+/// Returns the user's home directory, which every default XDG path and `~`
+/// expansion is built from.
+///
+/// Uses `HOME` when set and non-empty, otherwise the current user's passwd
+/// entry on Unix, and only then falls back to `/`. With the `xdg-override`
+/// feature, [`XdgDirs::home_fallback`] replaces both fallbacks so tests get
+/// the same answer on every machine.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "xdg-override")]
+/// # {
+/// use std::env;
+/// use std::path::PathBuf;
+/// use ovos_config::xdg::{self, XdgDirs};
+///
+/// let dirs = XdgDirs {
+///     home_fallback: Some(PathBuf::from("/tmp/fallback-home")),
+///     ..Default::default()
+/// };
+/// xdg::with_xdg_dirs(dirs, || {
+///     env::set_var("HOME", "/tmp/home");
+///     assert_eq!(xdg::home_dir(), PathBuf::from("/tmp/home"));
+///     env::set_var("HOME", "");
+///     assert_eq!(xdg::home_dir(), PathBuf::from("/tmp/fallback-home"));
+///     env::remove_var("HOME");
+///     assert_eq!(xdg::home_dir(), PathBuf::from("/tmp/fallback-home"));
+/// });
+/// # }
+/// ```
+pub fn home_dir() -> PathBuf {
     env::var_os("HOME")
-        .and_then(|h| if h.is_empty() { None } else { Some(h) })
+        .filter(|h| !h.is_empty())
         .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("/"))
+        .or_else(home_fallback)
+        .unwrap_or_else(|| {
+            warn("HOME is not set and no passwd entry was found, falling back to /");
+            PathBuf::from("/")
+        })
+}
+
+/// Helper function to find the home directory when `HOME` doesn't say.
+fn home_fallback() -> Option<PathBuf> {
+    #[cfg(any(test, feature = "xdg-override"))]
+    if let Some(p) = overridden(|d| d.home_fallback.clone()) {
+        return Some(p);
+    }
+    passwd_home_dir()
+}
+
+/// Helper function to look up the current user's home directory with `getpwuid_r`.
+#[cfg(unix)]
+fn passwd_home_dir() -> Option<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let rc = unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 || result.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes()))).filter(|d| !d.as_os_str().is_empty())
+}
+
+#[cfg(not(unix))]
+fn passwd_home_dir() -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[cfg(unix)]
+    #[test]
+    fn passwd_home_dir_matches_passwd_entry() {
+        let uid = unsafe { libc::getuid() }.to_string();
+        let passwd = std::fs::read_to_string("/etc/passwd").unwrap();
+        let expected = passwd
+            .lines()
+            .map(|line| line.split(':').collect::<Vec<_>>())
+            .find(|fields| fields.len() > 5 && fields[2] == uid)
+            .map(|fields| PathBuf::from(fields[5]));
+        assert_eq!(passwd_home_dir(), expected);
+    }
+
+    #[test]
+    fn home_fallback_prefers_override_then_passwd() {
+        assert_eq!(home_fallback(), passwd_home_dir());
+        let dirs = XdgDirs {
+            home_fallback: Some(PathBuf::from("/srv/ovos")),
+            ..Default::default()
+        };
+        assert_eq!(
+            with_xdg_dirs(dirs, home_fallback),
+            Some(PathBuf::from("/srv/ovos"))
+        );
+    }
+
+    #[test]
+    fn refresh_env_cache_picks_up_changed_env() {
+        use crate::locations::USER_CONFIG;
+
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let previous = env::var_os("XDG_CONFIG_HOME");
+
+        env::set_var("XDG_CONFIG_HOME", dir.path().join("before"));
+        refresh_env_cache();
+        assert_eq!(xdg_config_home(), dir.path().join("before"));
+        assert!(USER_CONFIG.starts_with(dir.path().join("before")));
+
+        env::set_var("XDG_CONFIG_HOME", dir.path().join("after"));
+        assert_eq!(xdg_config_home(), dir.path().join("after"));
+        assert!(USER_CONFIG.starts_with(dir.path().join("before")));
+        refresh_env_cache();
+        assert!(USER_CONFIG.starts_with(dir.path().join("after")));
+
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_HOME", value),
+            None => env::remove_var("XDG_CONFIG_HOME"),
+        }
+        refresh_env_cache();
+    }
+
+    #[test]
+    fn refresh_env_cache_ignores_thread_overrides() {
+        use crate::locations::USER_CONFIG;
+
+        let _env = crate::testing::env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let previous = env::var_os("XDG_CONFIG_HOME");
+        env::set_var("XDG_CONFIG_HOME", dir.path().join("env"));
+
+        let dirs = XdgDirs {
+            config_home: Some(dir.path().join("override")),
+            ..Default::default()
+        };
+        let inside = with_xdg_dirs(dirs, || {
+            refresh_env_cache();
+            (xdg_config_home(), USER_CONFIG.to_path_buf())
+        });
+        assert_eq!(inside.0, dir.path().join("override"));
+        assert!(inside.1.starts_with(dir.path().join("env")));
+        assert!(USER_CONFIG.starts_with(dir.path().join("env")));
+
+        match previous {
+            Some(value) => env::set_var("XDG_CONFIG_HOME", value),
+            None => env::remove_var("XDG_CONFIG_HOME"),
+        }
+        refresh_env_cache();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env_paths_expand_tilde_and_drop_trailing_slashes() {
+        let _env = crate::testing::env_lock();
+        let previous = env::var_os("HOME");
+        env::set_var("HOME", "/home/ovos");
+        env::set_var("OVOS_TEST_XDG_DIRS", "~/a:/etc/xdg/:relative:~other/b");
+
+        let paths = [
+            env_path("~/config"),
+            env_path("~"),
+            env_path("~/"),
+            env_path("/tmp/config//"),
+            env_path("/"),
+            env_path("config/"),
+            env_path("~ovos/config"),
+        ];
+        let dirs = paths_from_env("OVOS_TEST_XDG_DIRS", Vec::new);
+
+        match previous {
+            Some(home) => env::set_var("HOME", home),
+            None => env::remove_var("HOME"),
+        }
+        env::remove_var("OVOS_TEST_XDG_DIRS");
+
+        assert_eq!(
+            paths,
+            [
+                Some(PathBuf::from("/home/ovos/config")),
+                Some(PathBuf::from("/home/ovos")),
+                Some(PathBuf::from("/home/ovos")),
+                Some(PathBuf::from("/tmp/config")),
+                Some(PathBuf::from("/")),
+                None,
+                None,
+            ]
+        );
+        assert_eq!(
+            dirs,
+            [PathBuf::from("/home/ovos/a"), PathBuf::from("/etc/xdg")]
+        );
+    }
+
+    #[test]
+    fn overrides_are_isolated_per_thread() {
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let threads: Vec<_> = ["/srv/one", "/srv/two"]
+            .into_iter()
+            .map(|root| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let root = PathBuf::from(root);
+                    let dirs = XdgDirs {
+                        cache_home: Some(root.join("cache")),
+                        config_dirs: Some(vec![root.join("etc/xdg")]),
+                        config_home: Some(root.join("config")),
+                        data_dirs: Some(vec![root.join("share")]),
+                        data_home: Some(root.join("data")),
+                        runtime_dir: Some(root.join("run")),
+                        state_home: Some(root.join("state")),
+                        ..Default::default()
+                    };
+                    with_xdg_dirs(dirs, || {
+                        // Both overrides are live at once past this point
+                        barrier.wait();
+                        assert_eq!(xdg_cache_home(), root.join("cache"));
+                        assert_eq!(xdg_config_dirs(), [root.join("etc/xdg")]);
+                        assert_eq!(xdg_config_home(), root.join("config"));
+                        assert_eq!(xdg_data_dirs(), [root.join("share")]);
+                        assert_eq!(xdg_data_home(), root.join("data"));
+                        assert_eq!(xdg_runtime_dir(), Some(root.join("run")));
+                        assert_eq!(xdg_state_home(), root.join("state"));
+                        barrier.wait();
+                    });
+                    assert_ne!(xdg_config_home(), root.join("config"));
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn override_is_restored_after_a_panic() {
+        let _env = crate::testing::env_lock();
+        let before = xdg_config_home();
+        let dirs = XdgDirs {
+            config_home: Some(PathBuf::from("/srv/panicking")),
+            ..Default::default()
+        };
+        let result = std::panic::catch_unwind(|| {
+            with_xdg_dirs(dirs, || {
+                assert_eq!(xdg_config_home(), PathBuf::from("/srv/panicking"));
+                panic!("test panic");
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(xdg_config_home(), before);
+    }
+
+    #[test]
+    fn home_dir_distinguishes_set_empty_and_unset_home() {
+        let _env = crate::testing::env_lock();
+        let previous_home = env::var_os("HOME");
+        let previous_config = env::var_os("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_HOME");
+        let dirs = XdgDirs {
+            home_fallback: Some(PathBuf::from("/srv/fallback")),
+            ..Default::default()
+        };
+
+        let resolve = |home: Option<&str>| {
+            match home {
+                Some(home) => env::set_var("HOME", home),
+                None => env::remove_var("HOME"),
+            }
+            with_xdg_dirs(dirs.clone(), || {
+                (home_dir(), xdg_config_home(), env_path("~/skills"))
+            })
+        };
+        let fallback = (
+            PathBuf::from("/srv/fallback"),
+            PathBuf::from("/srv/fallback/.config"),
+            Some(PathBuf::from("/srv/fallback/skills")),
+        );
+        assert_eq!(
+            resolve(Some("/home/ovos")),
+            (
+                PathBuf::from("/home/ovos"),
+                PathBuf::from("/home/ovos/.config"),
+                Some(PathBuf::from("/home/ovos/skills")),
+            )
+        );
+        assert_eq!(resolve(Some("")), fallback);
+        assert_eq!(resolve(None), fallback);
+
+        match previous_home {
+            Some(value) => env::set_var("HOME", value),
+            None => env::remove_var("HOME"),
+        }
+        if let Some(value) = previous_config {
+            env::set_var("XDG_CONFIG_HOME", value);
+        }
+        refresh_env_cache();
+    }
 }